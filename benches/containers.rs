@@ -0,0 +1,230 @@
+// Benchmarks comparing TList with the std containers it might replace.
+//
+// Every workload is run against TList, Vec, VecDeque and LinkedList through the
+// small List trait below, at sizes from 1e2 up to 1e7 elements. BTreeMap is
+// included as a reference for what a balanced tree costs, but since it is keyed
+// rather than indexed it runs the nearest keyed equivalent of each workload:
+// random keys stand in for random indexes.
+//
+// The full range takes a long time to run. Set TLIST_BENCH_MAX_LEN to cap the
+// largest size, e.g. `TLIST_BENCH_MAX_LEN=100000 cargo bench`.
+
+extern crate criterion;
+extern crate rand;
+extern crate tlist;
+
+use std::collections::{BTreeMap, LinkedList, VecDeque};
+use std::env;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::{Rng, SeedableRng, XorShiftRng};
+use tlist::TList;
+
+const SIZES: [usize; 6] = [100, 1_000, 10_000, 100_000, 1_000_000, 10_000_000];
+const SEED: [u32; 4] = [0x193a_6754, 0xa8a7_d469, 0x9783_0e05, 0x113b_a7bb];
+const MIXED_EDITS: usize = 100;
+
+trait List {
+    const NAME: &'static str;
+
+    fn from_slice(data: &[u64]) -> Self;
+    fn push(&mut self, elem: u64);
+    fn insert(&mut self, index: usize, elem: u64);
+    fn remove(&mut self, index: usize) -> Option<u64>;
+    fn get(&self, index: usize) -> Option<&u64>;
+    fn get_mut(&mut self, index: usize) -> Option<&mut u64>;
+    fn sum(&self) -> u64;
+    fn len(&self) -> usize;
+}
+
+impl List for TList<u64> {
+    const NAME: &'static str = "TList";
+
+    fn from_slice(data: &[u64]) -> Self { TList::<u64>::from_data(data) }
+    fn push(&mut self, elem: u64) { TList::push(self, elem) }
+    fn insert(&mut self, index: usize, elem: u64) { TList::insert(self, elem, index) }
+    fn remove(&mut self, index: usize) -> Option<u64> { TList::remove(self, index) }
+    fn get(&self, index: usize) -> Option<&u64> { TList::get(self, index) }
+    fn get_mut(&mut self, index: usize) -> Option<&mut u64> { TList::get_mut(self, index) }
+    fn sum(&self) -> u64 { self.iter().sum() }
+    fn len(&self) -> usize { TList::len(self) }
+}
+
+impl List for Vec<u64> {
+    const NAME: &'static str = "Vec";
+
+    fn from_slice(data: &[u64]) -> Self { data.to_vec() }
+    fn push(&mut self, elem: u64) { Vec::push(self, elem) }
+    fn insert(&mut self, index: usize, elem: u64) { Vec::insert(self, index, elem) }
+    fn remove(&mut self, index: usize) -> Option<u64> { Some(Vec::remove(self, index)) }
+    fn get(&self, index: usize) -> Option<&u64> { <[u64]>::get(self, index) }
+    fn get_mut(&mut self, index: usize) -> Option<&mut u64> { <[u64]>::get_mut(self, index) }
+    fn sum(&self) -> u64 { self.iter().sum() }
+    fn len(&self) -> usize { Vec::len(self) }
+}
+
+impl List for VecDeque<u64> {
+    const NAME: &'static str = "VecDeque";
+
+    fn from_slice(data: &[u64]) -> Self { data.iter().cloned().collect() }
+    fn push(&mut self, elem: u64) { self.push_back(elem) }
+    fn insert(&mut self, index: usize, elem: u64) { VecDeque::insert(self, index, elem) }
+    fn remove(&mut self, index: usize) -> Option<u64> { VecDeque::remove(self, index) }
+    fn get(&self, index: usize) -> Option<&u64> { VecDeque::get(self, index) }
+    fn get_mut(&mut self, index: usize) -> Option<&mut u64> { VecDeque::get_mut(self, index) }
+    fn sum(&self) -> u64 { self.iter().sum() }
+    fn len(&self) -> usize { VecDeque::len(self) }
+}
+
+// LinkedList has no positional insert or remove, so they are built from
+// split_off() and append(), which is how they'd be done by hand anyway.
+impl List for LinkedList<u64> {
+    const NAME: &'static str = "LinkedList";
+
+    fn from_slice(data: &[u64]) -> Self { data.iter().cloned().collect() }
+    fn push(&mut self, elem: u64) { self.push_back(elem) }
+
+    fn insert(&mut self, index: usize, elem: u64) {
+        let mut tail = self.split_off(index);
+        self.push_back(elem);
+        self.append(&mut tail);
+    }
+
+    fn remove(&mut self, index: usize) -> Option<u64> {
+        let mut tail = self.split_off(index);
+        let removed = tail.pop_front();
+        self.append(&mut tail);
+        removed
+    }
+
+    fn get(&self, index: usize) -> Option<&u64> { self.iter().nth(index) }
+    fn get_mut(&mut self, index: usize) -> Option<&mut u64> { self.iter_mut().nth(index) }
+    fn sum(&self) -> u64 { self.iter().sum() }
+    fn len(&self) -> usize { LinkedList::len(self) }
+}
+
+fn sizes() -> Vec<usize> {
+    let max_len = env::var("TLIST_BENCH_MAX_LEN")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(usize::MAX);
+    SIZES.iter().cloned().filter(|&n| n <= max_len).collect()
+}
+
+fn rng() -> XorShiftRng {
+    XorShiftRng::from_seed(SEED)
+}
+
+fn data(len: usize) -> Vec<u64> {
+    let mut rng = rng();
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+fn bench_list<L: List>(c: &mut Criterion) {
+    for len in sizes() {
+        let data = data(len);
+        let mut list = L::from_slice(&data);
+        let mut rng = rng();
+        let mut group = c.benchmark_group(L::NAME);
+        // Large inputs make every sample slow, so fewer are taken.
+        group.sample_size(10);
+        group.throughput(Throughput::Elements(len as u64));
+
+        group.bench_with_input(BenchmarkId::new("from_data", len), &data, |b, data| {
+            b.iter(|| L::from_slice(black_box(data)))
+        });
+        group.bench_with_input(BenchmarkId::new("push", len), &data, |b, data| {
+            b.iter(|| {
+                let mut l = L::from_slice(&[]);
+                for &x in data {
+                    l.push(x);
+                }
+                l
+            })
+        });
+
+        group.bench_function(BenchmarkId::new("iterate", len), |b| {
+            b.iter(|| black_box(list.sum()))
+        });
+        group.throughput(Throughput::Elements(1));
+
+        // Inserts and removes are paired so the list stays the same size, which
+        // avoids rebuilding it for each sample.
+        group.bench_function(BenchmarkId::new("random_insert_remove", len), |b| {
+            b.iter(|| {
+                let loc = rng.gen_range(0, len);
+                list.insert(loc, black_box(7));
+                let loc = rng.gen_range(0, len + 1);
+                black_box(list.remove(loc))
+            })
+        });
+
+        group.bench_function(BenchmarkId::new("random_get", len), |b| {
+            b.iter(|| black_box(list.get(rng.gen_range(0, len))))
+        });
+
+        // A batch of edits like those produced by applying a diff: mostly
+        // in-place replacements with some inserted and removed lines.
+        group.bench_function(BenchmarkId::new("mixed_edits", len), |b| {
+            b.iter(|| {
+                for _ in 0..MIXED_EDITS {
+                    // Structural edits alternate around the original length so the
+                    // list doesn't drift in size over many iterations.
+                    if rng.gen_weighted_bool(2) {
+                        if list.len() <= len {
+                            list.insert(rng.gen_range(0, list.len() + 1), 7);
+                        } else {
+                            black_box(list.remove(rng.gen_range(0, list.len())));
+                        }
+                    } else if let Some(v) = list.get_mut(rng.gen_range(0, list.len())) {
+                        *v += 1;
+                    }
+                }
+            })
+        });
+
+        group.finish();
+    }
+}
+
+fn bench_btree_map(c: &mut Criterion) {
+    for len in sizes() {
+        let data = data(len);
+        let mut map: BTreeMap<u64, u64> = data.iter().map(|&k| (k, k)).collect();
+        let mut rng = rng();
+        let mut group = c.benchmark_group("BTreeMap");
+        group.sample_size(10);
+        group.throughput(Throughput::Elements(len as u64));
+
+        group.bench_with_input(BenchmarkId::new("from_data", len), &data, |b, data| {
+            b.iter(|| black_box(data).iter().map(|&k| (k, k)).collect::<BTreeMap<u64, u64>>())
+        });
+
+        group.bench_function(BenchmarkId::new("iterate", len), |b| {
+            b.iter(|| black_box(map.values().sum::<u64>()))
+        });
+        group.throughput(Throughput::Elements(1));
+
+        group.bench_function(BenchmarkId::new("random_insert_remove", len), |b| {
+            b.iter(|| {
+                let key = rng.gen();
+                map.insert(key, key);
+                black_box(map.remove(&key))
+            })
+        });
+
+        group.bench_function(BenchmarkId::new("random_get", len), |b| {
+            b.iter(|| black_box(map.get(&data[rng.gen_range(0, len)])))
+        });
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches,
+                 bench_list::<TList<u64>>,
+                 bench_list::<Vec<u64>>,
+                 bench_list::<VecDeque<u64>>,
+                 bench_list::<LinkedList<u64>>,
+                 bench_btree_map);
+criterion_main!(benches);
@@ -0,0 +1,13 @@
+#!/bin/sh
+# Checks that tlist builds and works without std: the library itself in each
+# configuration, then a no_std program exercising its API, run on the host.
+set -ex
+
+cd "$(dirname "$0")/.."
+cargo build --no-default-features
+cargo build --no-default-features --features u32-index
+cargo clippy --no-default-features -- -D warnings
+
+cd ci/no_std
+cargo run
+cargo run --features u32-index
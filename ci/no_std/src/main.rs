@@ -0,0 +1,81 @@
+// A no_std program which exercises the public API of tlist built without its std
+// feature. Since neither this crate nor tlist links std, any use of std creeping into
+// tlist fails the build with a missing crate or a duplicate panic handler.
+//
+// It runs on Linux by linking only libc, which provides the entry point and the
+// allocator, and exits with a non-zero status if any check fails.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::alloc::{GlobalAlloc, Layout};
+use core::panic::PanicInfo;
+
+use tlist::{ChunkedTList, TList, TListError};
+
+#[link(name = "c")]
+extern "C" {
+    fn malloc(size: usize) -> *mut u8;
+    fn free(ptr: *mut u8);
+    fn abort() -> !;
+}
+
+struct LibcAlloc;
+
+// malloc's results are aligned for any fundamental type, which covers every
+// allocation made here.
+const MALLOC_ALIGN: usize = 16;
+
+unsafe impl GlobalAlloc for LibcAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if layout.align() > MALLOC_ALIGN {
+            return core::ptr::null_mut();
+        }
+        malloc(layout.size())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        free(ptr)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: LibcAlloc = LibcAlloc;
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    unsafe { abort() }
+}
+
+// The prebuilt alloc library refers to the unwinding personality routine even though
+// this program aborts on panic, so it is never called.
+#[no_mangle]
+pub extern "C" fn rust_eh_personality() {}
+
+#[no_mangle]
+pub extern "C" fn main(_argc: isize, _argv: *const *const u8) -> isize {
+    let mut list = TList::<u32>::from_data(&[1, 2, 3]);
+    list.insert(0, 0);
+    list.push(4);
+    assert_eq!(Some(&2), list.get(2));
+    assert_eq!(Some(4), list.pop());
+    assert_eq!(Ok(()), list.try_insert(9, 4));
+    assert!(matches!(list.try_remove(9), Err(TListError::IndexOutOfBounds { .. })));
+    list.traverse(&|v| v * 10);
+
+    let handle = list.handle(1).unwrap();
+    list.remove(0);
+    let remap = list.compact();
+    assert_eq!(Some(0), remap.get(handle).and_then(|h| list.index_of(h)));
+    assert_eq!(list.iter().cloned().collect::<Vec<_>>(), [10, 20, 30, 90]);
+
+    let mut chunked = ChunkedTList::<u8, 4>::from_data(b"hello world");
+    chunked.insert(b'!', 11);
+    assert_eq!(Some(b' '), chunked.remove(5));
+    assert_eq!(chunked.into_iter().collect::<Vec<_>>(), b"helloworld!");
+
+    0
+}
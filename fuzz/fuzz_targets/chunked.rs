@@ -0,0 +1,49 @@
+#![no_main]
+
+// Applies a sequence of list operations decoded from the fuzzer input to both a
+// ChunkedTList<u8, 4> and a Vec<u8> oracle, checking that they agree after every
+// step. The small chunk size makes splits and merges frequent.
+
+use libfuzzer_sys::fuzz_target;
+use tlist::ChunkedTList;
+
+fuzz_target!(|data: &[u8]| {
+    let mut ops = data.iter().cloned();
+    let mut list = ChunkedTList::<u8, 4>::new();
+    let mut oracle = Vec::<u8>::new();
+
+    while let (Some(op), Some(arg)) = (ops.next(), ops.next()) {
+        // Indexes are drawn from 0..=len + 1 to exercise out of bounds accesses too
+        let index = arg as usize % (oracle.len() + 2);
+        match op % 5 {
+            0 => {
+                list.insert(op, index);
+                oracle.insert(index.min(oracle.len()), op);
+            },
+            1 => {
+                let expected = if index < oracle.len() { Some(oracle.remove(index)) } else { None };
+                assert_eq!(expected, list.remove(index));
+            },
+            2 => {
+                assert_eq!(oracle.pop(), list.pop());
+            },
+            3 => {
+                if let Some(v) = list.get_mut(index) {
+                    *v = op;
+                }
+                if let Some(v) = oracle.get_mut(index) {
+                    *v = op;
+                }
+            },
+            _ => {
+                assert_eq!(oracle.get(index), list.get(index));
+            },
+        }
+
+        assert_eq!(oracle.len(), list.len());
+        list.assert_invariants();
+    }
+
+    assert!(oracle.iter().eq(list.iter()));
+    assert_eq!(oracle, list.into_iter().collect::<Vec<u8>>());
+});
@@ -0,0 +1,33 @@
+#![no_main]
+
+// Builds a TList<u8> directly from the fuzzer input with from_data(), then
+// drains it from the front and back alternately, comparing against a Vec<u8>
+// oracle and checking the tree invariants along the way.
+
+use libfuzzer_sys::fuzz_target;
+use tlist::TList;
+
+fuzz_target!(|data: &[u8]| {
+    let mut list = TList::<u8>::from_data(data);
+    let mut oracle = data.to_vec();
+
+    list.assert_invariants();
+    assert_eq!(oracle.len(), list.len());
+    assert!(oracle.iter().eq(list.iter()));
+
+    let mut from_front = true;
+    while !oracle.is_empty() {
+        if from_front {
+            assert_eq!(Some(oracle.remove(0)), list.remove(0));
+        } else {
+            assert_eq!(oracle.pop(), list.pop());
+        }
+        from_front = !from_front;
+
+        if oracle.len() % 16 == 0 {
+            list.assert_invariants();
+        }
+    }
+
+    assert!(list.is_empty());
+});
@@ -0,0 +1,144 @@
+#![no_main]
+
+// Decodes the fuzzer input into a sequence of list operations and applies
+// each one to both a TList<u8> and a Vec<u8> oracle, checking that they agree
+// after every step and that the tree invariants hold at the end.
+
+use libfuzzer_sys::fuzz_target;
+use tlist::TList;
+
+struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn next_u8(&mut self) -> Option<u8> {
+        let (first, rest) = self.data.split_first()?;
+        self.data = rest;
+        Some(*first)
+    }
+
+    fn next_u16(&mut self) -> Option<u16> {
+        let hi = self.next_u8()?;
+        let lo = self.next_u8()?;
+        Some(u16::from_be_bytes([hi, lo]))
+    }
+
+    // Indexes are drawn from 0..=len + 1 so that out of bounds accesses,
+    // including the one-past-the-end insertion, are exercised too.
+    fn next_index(&mut self, len: usize) -> Option<usize> {
+        self.next_u16().map(|i| i as usize % (len + 2))
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut ops = Decoder { data };
+    let capacity = ops.next_u8().unwrap_or(0) as usize;
+    let mut list = TList::<u8>::with_capacity(capacity);
+    let mut oracle = Vec::<u8>::new();
+
+    while let Some(op) = ops.next_u8() {
+        match op % 13 {
+            0 => {
+                let value = match ops.next_u8() { Some(v) => v, None => break };
+                list.push(value);
+                oracle.push(value);
+            },
+            1 => {
+                let (index, value) = match (ops.next_index(oracle.len()), ops.next_u8()) {
+                    (Some(i), Some(v)) => (i, v),
+                    _ => break,
+                };
+                list.insert(value, index);
+                oracle.insert(index.min(oracle.len()), value);
+            },
+            2 => {
+                let (index, value) = match (ops.next_index(oracle.len()), ops.next_u8()) {
+                    (Some(i), Some(v)) => (i, v),
+                    _ => break,
+                };
+                list.insert_or_push(value, index);
+                oracle.insert(index.min(oracle.len()), value);
+            },
+            3 => {
+                let index = match ops.next_index(oracle.len()) { Some(i) => i, None => break };
+                let expected = if index < oracle.len() { Some(oracle.remove(index)) } else { None };
+                assert_eq!(expected, list.remove(index));
+            },
+            4 => {
+                assert_eq!(oracle.pop(), list.pop());
+            },
+            5 => {
+                let index = match ops.next_index(oracle.len()) { Some(i) => i, None => break };
+                assert_eq!(oracle.get(index), list.get(index));
+            },
+            6 => {
+                let (index, value) = match (ops.next_index(oracle.len()), ops.next_u8()) {
+                    (Some(i), Some(v)) => (i, v),
+                    _ => break,
+                };
+                if let Some(v) = list.get_mut(index) {
+                    *v = value;
+                }
+                if let Some(v) = oracle.get_mut(index) {
+                    *v = value;
+                }
+            },
+            7 => {
+                let (index, value) = match (ops.next_index(oracle.len()), ops.next_u8()) {
+                    (Some(i), Some(v)) => (i, v),
+                    _ => break,
+                };
+                if index <= oracle.len() {
+                    assert_eq!(Ok(()), list.try_insert(value, index));
+                    oracle.insert(index, value);
+                } else {
+                    assert!(list.try_insert(value, index).is_err());
+                }
+            },
+            8 => {
+                let index = match ops.next_index(oracle.len()) { Some(i) => i, None => break };
+                if index < oracle.len() {
+                    assert_eq!(Ok(oracle.remove(index)), list.try_remove(index));
+                } else {
+                    assert!(list.try_remove(index).is_err());
+                }
+            },
+            9 => {
+                let additional = match ops.next_u8() { Some(v) => v as usize, None => break };
+                list.reserve(additional);
+                assert!(list.capacity() >= list.len() + additional);
+                list.shrink_to_fit();
+                assert!(list.capacity() >= list.len());
+            },
+            10 => {
+                list.clear();
+                oracle.clear();
+            },
+            11 => {
+                let index = match ops.next_index(oracle.len()) { Some(i) => i, None => break };
+                let handle = list.handle(index);
+                let remap = list.compact();
+                list.assert_invariants();
+                if let Some(h) = handle {
+                    let new_handle = remap.get(h);
+                    assert_eq!(Some(index), new_handle.and_then(|h| list.index_of(h)));
+                    assert_eq!(oracle.get(index), new_handle.and_then(|h| list.get_by_handle(h)));
+                }
+            },
+            _ => {
+                let delta = match ops.next_u8() { Some(v) => v, None => break };
+                list.traverse(&|v: u8| v.wrapping_add(delta));
+                for v in oracle.iter_mut() {
+                    *v = v.wrapping_add(delta);
+                }
+            },
+        }
+
+        assert_eq!(oracle.len(), list.len());
+    }
+
+    list.assert_invariants();
+    assert!(oracle.iter().eq(list.iter()));
+    assert_eq!(oracle, list.into_iter().collect::<Vec<u8>>());
+});
@@ -0,0 +1,570 @@
+// (c) 2017 James Crooks
+//
+// A TList variant which stores a small inline array of elements in each tree node.
+
+//! ChunkedTList, a TList which stores up to B elements in each node of the tree.
+
+use core::fmt;
+use core::mem::{ManuallyDrop, MaybeUninit};
+use core::ptr;
+use core::slice;
+
+use {corrupted, Dir, Handle, TList, TListError};
+
+/// An indexable list with the same O(log N) index-based API as TList, but which stores up to
+/// B elements in a small inline array in each tree node rather than one, with each node's
+/// size counting elements. For small element types such as bytes, chars or tokens this cuts
+/// the per-element memory overhead by a factor of up to B, needs far fewer rotations, and
+/// keeps neighboring elements next to each other in memory.
+///
+/// A chunk which an insertion would overfill is split in half, and a chunk which removals
+/// leave less than half full is merged with a neighbor if their elements fit in one chunk.
+///
+/// B must be at least 2.
+#[derive(Debug, Clone)]
+pub struct ChunkedTList<T, const B: usize> {
+    tree: TList<Chunk<T, B>>,
+}
+
+impl<T, const B: usize> ChunkedTList<T, B> {
+    /// Generate a new, empty ChunkedTList.
+    pub fn new() -> ChunkedTList<T, B> {
+        Self::with_capacity(0)
+    }
+
+    /// Initialize a new ChunkedTList with room for at least capacity elements in full chunks.
+    pub fn with_capacity(capacity: usize) -> ChunkedTList<T, B> {
+        assert!(B >= 2, "ChunkedTList needs room for at least 2 elements per chunk");
+        ChunkedTList {
+            tree: TList::with_capacity(capacity.div_ceil(B)),
+        }
+    }
+
+    /// Build a new ChunkedTList from a slice of data elements, such that the index order of
+    /// the list matches the slice. Every chunk but the last is filled.
+    pub fn from_data(data: &[T]) -> ChunkedTList<T, B> where T: Clone {
+        let mut list = Self::with_capacity(data.len());
+        let mut prev = None;
+        for elems in data.chunks(B) {
+            let mut chunk = Chunk::new();
+            for elem in elems {
+                chunk.push(elem.clone());
+            }
+            let slot = list.tree.insert_after(prev, chunk, elems.len());
+            prev = Some(slot.expect("Memory allocation failure."));
+        }
+        list
+    }
+
+    /// Gets the number of elements in the ChunkedTList.
+    pub fn len(&self) -> usize {
+        self.tree.total_weight()
+    }
+
+    /// Returns true if the ChunkedTList contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Insert a new value into the ChunkedTList at the given index in O(log N + B) time. As
+    /// with TList, insertion at len() is equivalent to push(), and an index past len() appends.
+    pub fn insert(&mut self, elem: T, index: usize) {
+        let index = index.min(self.len());
+        self.try_insert(elem, index).expect("Memory allocation failure.");
+    }
+
+    /// Inserts a new value at the given index, which may be at most len(), as insert(). An
+    /// index past the end, a failure to allocate a new chunk, or a detected inconsistency in
+    /// the tree are reported as an error, and the value is dropped.
+    pub fn try_insert(&mut self, elem: T, index: usize) -> Result<(), TListError> {
+        let len = self.len();
+        if index > len {
+            return Err(TListError::IndexOutOfBounds { index, len });
+        }
+
+        // Reserving up front means a split never has to be undone
+        self.tree.try_reserve(1)?;
+
+        if len == 0 {
+            let mut chunk = Chunk::new();
+            chunk.push(elem);
+            self.tree.insert_after(None, chunk, 1)?;
+            return Ok(());
+        }
+
+        // Appends go at the end of the last chunk rather than the start of a missing one
+        let (slot, offset) = if index == len {
+            let (slot, offset) = self.tree.locate(len - 1)?;
+            (slot, offset + 1)
+        } else {
+            self.tree.locate(index)?
+        };
+
+        let chunk = self.chunk_mut(slot)?;
+        if chunk.len() < B {
+            chunk.insert(offset, elem);
+            return self.tree.adjust_weight(slot, 0, 1);
+        }
+
+        let half = B / 2;
+        let mut tail = chunk.split_off(half);
+        if offset <= half {
+            chunk.insert(offset, elem);
+        } else {
+            tail.insert(offset - half, elem);
+        }
+
+        let (head_len, tail_len) = (chunk.len(), tail.len());
+        self.tree.adjust_weight(slot, B, head_len)?;
+        self.tree.insert_after(Some(slot), tail, tail_len)?;
+        Ok(())
+    }
+
+    /// Appends an element to the end of the list in O(log N) time.
+    pub fn push(&mut self, elem: T) {
+        let loc = self.len();
+        self.insert(elem, loc);
+    }
+
+    /// Returns a reference to the value at index if it exists. Runs in O(log N) time.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        let (slot, offset) = self.tree.locate(index).ok()?;
+        self.tree.get_by_handle(Handle(slot)).and_then(|c| c.as_slice().get(offset))
+    }
+
+    /// Returns a mutable reference to the value at index if it exists. Runs in O(log N) time.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        let (slot, offset) = self.tree.locate(index).ok()?;
+        self.tree.get_mut_by_handle(Handle(slot)).and_then(|c| c.as_mut_slice().get_mut(offset))
+    }
+
+    /// Deletes the value at the specified index in O(log N + B) time, returning an owned value
+    /// if it exists. All higher indexes are shifted down, as with TList.
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        self.try_remove(index).ok()
+    }
+
+    /// Deletes the value at the specified index as remove(), reporting an index past the end
+    /// or a detected inconsistency in the tree as an error.
+    pub fn try_remove(&mut self, index: usize) -> Result<T, TListError> {
+        let (slot, offset) = self.tree.locate(index)?;
+        let chunk = self.chunk_mut(slot)?;
+        let elem = chunk.remove(offset);
+        let remaining = chunk.len();
+
+        // Node sizes can't be zero, so an emptied chunk is unlinked at its old weight
+        if remaining == 0 {
+            self.tree.remove_slot(slot)?;
+        } else {
+            self.tree.adjust_weight(slot, 1, 0)?;
+            if remaining < B / 2 {
+                self.merge(slot)?;
+            }
+        }
+
+        Ok(elem)
+    }
+
+    /// Equivalent to remove() on the last element of the list.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            None
+        } else {
+            let loc = self.len() - 1;
+            self.remove(loc)
+        }
+    }
+
+    /// Removes all elements from the list, keeping the allocated capacity.
+    pub fn clear(&mut self) {
+        self.tree.clear();
+    }
+
+    /// Returns an iterator over the values in index order, yielding immutable borrows. Runs in
+    /// O(N) time over the whole list.
+    pub fn iter(&self) -> Iter<'_, T, B> {
+        let first = self.tree.locate(0).ok().map(|(slot, _)| slot);
+        Iter {
+            tree: &self.tree,
+            next_slot: first,
+            elems: [].iter(),
+            remaining: self.len(),
+        }
+    }
+
+    /// Checks the Red-Black invariants of the underlying tree, that every node's size counts
+    /// the elements of its sub-tree, and that no chunk is empty, panicking with a description
+    /// of the first violation found. Only compiled for tests and fuzzing builds.
+    #[cfg(any(test, fuzzing))]
+    pub fn assert_invariants(&self) {
+        let total = self.tree.assert_weighted_invariants(&|chunk| {
+            assert!(chunk.len() > 0, "empty chunk");
+            chunk.len()
+        });
+        assert_eq!(self.len(), total);
+    }
+
+    fn merge(&mut self, slot: usize) -> Result<(), TListError> {
+        // Merges the under-filled chunk at slot with the following chunk if they fit in one,
+        // or else with the preceding chunk.
+        let len = self.chunk_mut(slot)?.len();
+
+        if let Some(next) = self.tree.neighbor(slot, Dir::Right) {
+            let next_len = self.chunk_mut(next)?.len();
+            if len + next_len <= B {
+                let mut next_chunk = self.tree.remove_slot(next)?;
+                self.chunk_mut(slot)?.append(&mut next_chunk);
+                return self.tree.adjust_weight(slot, len, len + next_len);
+            }
+        }
+
+        if let Some(prev) = self.tree.neighbor(slot, Dir::Left) {
+            let prev_len = self.chunk_mut(prev)?.len();
+            if prev_len + len <= B {
+                let mut chunk = self.tree.remove_slot(slot)?;
+                self.chunk_mut(prev)?.append(&mut chunk);
+                return self.tree.adjust_weight(prev, prev_len, prev_len + len);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn chunk_mut(&mut self, slot: usize) -> Result<&mut Chunk<T, B>, TListError> {
+        match self.tree.get_mut_by_handle(Handle(slot)) {
+            Some(chunk) => Ok(chunk),
+            None => Err(corrupted(slot, "No chunk at slot!")),
+        }
+    }
+}
+
+impl<T, const B: usize> Default for ChunkedTList<T, B> {
+    fn default() -> ChunkedTList<T, B> {
+        Self::new()
+    }
+}
+
+/// An iterator over a ChunkedTList that yields borrowed values in index order.
+pub struct Iter<'a, T: 'a, const B: usize> {
+    tree: &'a TList<Chunk<T, B>>,
+    next_slot: Option<usize>,
+    elems: slice::Iter<'a, T>,
+    remaining: usize,
+}
+
+impl<'a, T, const B: usize> Iterator for Iter<'a, T, B> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(elem) = self.elems.next() {
+                self.remaining -= 1;
+                return Some(elem);
+            }
+
+            let slot = self.next_slot?;
+            self.elems = self.tree.get_by_handle(Handle(slot))?.as_slice().iter();
+            self.next_slot = self.tree.neighbor(slot, Dir::Right);
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T, const B: usize> ExactSizeIterator for Iter<'a, T, B> {}
+
+impl<'a, T, const B: usize> IntoIterator for &'a ChunkedTList<T, B> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, B>;
+
+    fn into_iter(self) -> Iter<'a, T, B> {
+        self.iter()
+    }
+}
+
+/// An iterator over a ChunkedTList that consumes the list and yields owned values in index
+/// order.
+pub struct IntoIter<T, const B: usize> {
+    chunks: ::IntoIter<Chunk<T, B>>,
+    current: Option<ChunkIntoIter<T, B>>,
+    remaining: usize,
+}
+
+impl<T, const B: usize> IntoIterator for ChunkedTList<T, B> {
+    type Item = T;
+    type IntoIter = IntoIter<T, B>;
+
+    fn into_iter(self) -> IntoIter<T, B> {
+        let remaining = self.len();
+        IntoIter {
+            chunks: self.tree.into_iter(),
+            current: None,
+            remaining,
+        }
+    }
+}
+
+impl<T, const B: usize> Iterator for IntoIter<T, B> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(elem) = self.current.as_mut().and_then(|c| c.next()) {
+                self.remaining -= 1;
+                return Some(elem);
+            }
+
+            self.current = Some(self.chunks.next()?.into_iter());
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T, const B: usize> ExactSizeIterator for IntoIter<T, B> {}
+
+// A fixed capacity array of up to B elements, stored inline. The first len slots of items
+// are initialized and the rest are not.
+struct Chunk<T, const B: usize> {
+    len: usize,
+    items: [MaybeUninit<T>; B],
+}
+
+impl<T, const B: usize> Chunk<T, B> {
+    #[inline]
+    fn new() -> Chunk<T, B> {
+        Chunk {
+            len: 0,
+            items: [const { MaybeUninit::uninit() }; B],
+        }
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    fn as_slice(&self) -> &[T] {
+        // Safety: the first len items are initialized
+        unsafe { &*(&self.items[..self.len] as *const [MaybeUninit<T>] as *const [T]) }
+    }
+
+    #[inline]
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        // Safety: the first len items are initialized
+        unsafe { &mut *(&mut self.items[..self.len] as *mut [MaybeUninit<T>] as *mut [T]) }
+    }
+
+    #[inline]
+    fn push(&mut self, elem: T) {
+        let len = self.len;
+        self.insert(len, elem);
+    }
+
+    fn insert(&mut self, offset: usize, elem: T) {
+        assert!(self.len < B && offset <= self.len, "chunk insert out of range");
+        // Safety: shifts the initialized items offset..len up by one, which stays in bounds
+        // since len < B, then fills the gap.
+        unsafe {
+            let p = self.items.as_mut_ptr().add(offset);
+            ptr::copy(p, p.add(1), self.len - offset);
+        }
+        self.items[offset] = MaybeUninit::new(elem);
+        self.len += 1;
+    }
+
+    fn remove(&mut self, offset: usize) -> T {
+        assert!(offset < self.len, "chunk remove out of range");
+        // Safety: reads out the initialized item at offset, then shifts the items after it
+        // down over the gap, leaving the last slot logically uninitialized.
+        unsafe {
+            let p = self.items.as_mut_ptr().add(offset);
+            let elem = p.read().assume_init();
+            ptr::copy(p.add(1), p, self.len - offset - 1);
+            self.len -= 1;
+            elem
+        }
+    }
+
+    fn split_off(&mut self, at: usize) -> Chunk<T, B> {
+        // Moves the items from at onward into a new chunk
+        assert!(at <= self.len, "chunk split out of range");
+        let mut tail = Chunk::new();
+        let count = self.len - at;
+        // Safety: moves count initialized items, which then only belong to tail
+        unsafe {
+            ptr::copy_nonoverlapping(self.items.as_ptr().add(at), tail.items.as_mut_ptr(), count);
+        }
+        self.len = at;
+        tail.len = count;
+        tail
+    }
+
+    fn append(&mut self, other: &mut Chunk<T, B>) {
+        // Moves every item of other onto the end of this chunk
+        assert!(self.len + other.len <= B, "chunk append overflow");
+        // Safety: moves other's initialized items into the uninitialized end of this chunk,
+        // which then owns them
+        unsafe {
+            ptr::copy_nonoverlapping(other.items.as_ptr(), self.items.as_mut_ptr().add(self.len), other.len);
+        }
+        self.len += other.len;
+        other.len = 0;
+    }
+
+    fn into_iter(self) -> ChunkIntoIter<T, B> {
+        ChunkIntoIter {
+            chunk: ManuallyDrop::new(self),
+            front: 0,
+        }
+    }
+}
+
+impl<T, const B: usize> Drop for Chunk<T, B> {
+    fn drop(&mut self) {
+        // Safety: drops exactly the initialized items
+        unsafe { ptr::drop_in_place(self.as_mut_slice()) }
+    }
+}
+
+impl<T: Clone, const B: usize> Clone for Chunk<T, B> {
+    fn clone(&self) -> Chunk<T, B> {
+        let mut chunk = Chunk::new();
+        for elem in self.as_slice() {
+            chunk.push(elem.clone());
+        }
+        chunk
+    }
+}
+
+impl<T: fmt::Debug, const B: usize> fmt::Debug for Chunk<T, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.as_slice()).finish()
+    }
+}
+
+// Yields the items of a chunk by value. Items before front have been moved out, and the
+// rest are dropped along with the iterator.
+struct ChunkIntoIter<T, const B: usize> {
+    chunk: ManuallyDrop<Chunk<T, B>>,
+    front: usize,
+}
+
+impl<T, const B: usize> Iterator for ChunkIntoIter<T, B> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.front == self.chunk.len {
+            return None;
+        }
+        // Safety: items front..len are initialized and not yet moved out
+        let elem = unsafe { self.chunk.items[self.front].assume_init_read() };
+        self.front += 1;
+        Some(elem)
+    }
+}
+
+impl<T, const B: usize> Drop for ChunkIntoIter<T, B> {
+    fn drop(&mut self) {
+        // Safety: drops the items which haven't been moved out. The chunk itself is never
+        // dropped, so they aren't dropped again.
+        unsafe { ptr::drop_in_place(&mut self.chunk.as_mut_slice()[self.front..]) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use std::rc::Rc;
+
+    use super::ChunkedTList;
+
+    use self::rand::Rng;
+
+    #[test]
+    fn test_chunked_random_ops() {
+        let mut rng = rand::thread_rng();
+        let mut list = ChunkedTList::<u32, 4>::new();
+        let mut oracle = Vec::new();
+
+        for i in 0..4000 {
+            if oracle.is_empty() || rng.gen_weighted_bool(2) {
+                let loc = rng.gen_range(0, oracle.len() + 1);
+                list.insert(i, loc);
+                oracle.insert(loc, i);
+            } else {
+                let loc = rng.gen_range(0, oracle.len());
+                assert_eq!(Some(oracle.remove(loc)), list.remove(loc));
+            }
+
+            if i % 100 == 0 {
+                list.assert_invariants();
+                assert!(oracle.iter().eq(list.iter()));
+            }
+        }
+
+        list.assert_invariants();
+        assert_eq!(oracle.len(), list.len());
+        for (i, v) in oracle.iter().enumerate() {
+            assert_eq!(Some(v), list.get(i));
+        }
+        assert_eq!(None, list.get(oracle.len()));
+        assert_eq!(oracle, list.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_chunked_from_data_and_drain() {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut list = ChunkedTList::<u8, 16>::from_data(&data);
+        list.assert_invariants();
+        assert_eq!(256, list.len());
+        assert_eq!(16, list.tree.len());
+        assert!(data.iter().eq(list.iter()));
+
+        if let Some(v) = list.get_mut(100) {
+            *v = 0;
+        }
+        assert_eq!(Some(&0), list.get(100));
+
+        let mut oracle = data.clone();
+        oracle[100] = 0;
+        while !oracle.is_empty() {
+            assert_eq!(Some(oracle.remove(0)), list.remove(0));
+            assert_eq!(oracle.pop(), list.pop());
+            list.assert_invariants();
+        }
+        assert!(list.is_empty());
+        assert!(list.tree.is_empty());
+        assert!(list.try_remove(0).is_err());
+        assert!(list.try_insert(1, 1).is_err());
+    }
+
+    #[test]
+    fn test_chunked_drops() {
+        let counter = Rc::new(());
+        let mut list = ChunkedTList::<Rc<()>, 3>::new();
+        for i in 0..20 {
+            list.insert(counter.clone(), i / 2);
+        }
+        assert_eq!(21, Rc::strong_count(&counter));
+
+        drop(list.remove(5));
+        let copy = list.clone();
+        assert_eq!(39, Rc::strong_count(&counter));
+
+        let mut iter = copy.into_iter();
+        drop(iter.next());
+        drop(iter);
+        assert_eq!(20, Rc::strong_count(&counter));
+
+        list.clear();
+        assert_eq!(1, Rc::strong_count(&counter));
+    }
+}
@@ -0,0 +1,381 @@
+// (c) 2017 James Crooks
+//
+// A replicated list which converges under concurrent edits, built on a TList.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+
+use visible::VisibleTList;
+use {Change, Handle};
+
+/// The unique id of an element of a CrdtList: a Lamport timestamp from the replica which
+/// inserted it. Ids are ordered by counter, then replica.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CrdtId {
+    /// Greater than the counter of every element its replica had seen when inserting it.
+    pub counter: u64,
+    /// The replica which inserted the element.
+    pub replica: u64,
+}
+
+/// An edit made to a CrdtList, to be sent to the other replicas and applied there with
+/// apply_remote().
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CrdtOp<T> {
+    /// Inserts value with the given id directly after the element with id after, or at the
+    /// start if after is None.
+    Insert {
+        /// The id of the new element.
+        id: CrdtId,
+        /// The id of the element it was inserted after.
+        after: Option<CrdtId>,
+        /// The value of the new element.
+        value: T,
+    },
+    /// Deletes the element with the given id.
+    Delete {
+        /// The id of the deleted element.
+        id: CrdtId,
+    },
+}
+
+/// A list replicated between several replicas, each of which edits its own copy and sends
+/// the resulting CrdtOps to the others, in the style of an RGA. Replicas which have applied
+/// the same ops hold the same list, in whatever order the ops arrived.
+///
+/// Every element has a unique CrdtId, and deleted elements stay in the underlying TList as
+/// hidden tombstones so that later ops can still refer to them. Each node counts the
+/// visible elements of its sub-tree alongside its size, so elements are addressed by their
+/// index among the visible ones in O(log N) time for N elements including tombstones.
+///
+/// Ops may arrive more than once and in any order: duplicates are ignored, and an op which
+/// refers to an element not yet seen is held back until that element arrives, then applied
+/// in O(log N) time along with the others waiting for it.
+///
+/// Tombstones are never collected: a replica can't tell when every other one has seen a
+/// delete, and until then ops may still refer to the deleted element. Memory therefore
+/// grows with every element ever inserted, not only the visible ones.
+#[derive(Debug, Clone)]
+pub struct CrdtList<T> {
+    replica: u64,
+    clock: u64,
+    list: VisibleTList<Item<T>>,
+    ids: BTreeMap<CrdtId, Handle>,
+    // Held-back ops, by the id of the element each is waiting for, in the order they arrived
+    pending: BTreeMap<CrdtId, Vec<CrdtOp<T>>>,
+    pending_len: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Item<T> {
+    id: CrdtId,
+    // None once the element is deleted
+    value: Option<T>,
+}
+
+impl<T> CrdtList<T> {
+    /// Generate a new, empty CrdtList for the given replica, which must be unique among the
+    /// replicas sharing the list.
+    pub fn new(replica: u64) -> CrdtList<T> {
+        CrdtList {
+            replica,
+            clock: 0,
+            list: VisibleTList::new(),
+            ids: BTreeMap::new(),
+            pending: BTreeMap::new(),
+            pending_len: 0,
+        }
+    }
+
+    /// Returns the replica this copy of the list belongs to.
+    pub fn replica(&self) -> u64 {
+        self.replica
+    }
+
+    /// Gets the number of visible elements.
+    pub fn len(&self) -> usize {
+        self.list.visible_len()
+    }
+
+    /// Returns true if the list has no visible elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of received ops waiting for an element they refer to.
+    pub fn pending_len(&self) -> usize {
+        self.pending_len
+    }
+
+    /// Returns a reference to the visible element at index if it exists. Runs in O(log N)
+    /// time.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        let raw = self.list.raw_index(index)?;
+        self.list.get(raw).and_then(|item| item.value.as_ref())
+    }
+
+    /// Returns an iterator over the visible elements in index order.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.list.iter_visible().filter_map(|item| item.value.as_ref())
+    }
+
+    /// Inserts value at the given visible index, or appends it if the index is past the end,
+    /// and returns the op to send to the other replicas. Runs in O(log N) time.
+    pub fn local_insert(&mut self, index: usize, value: T) -> CrdtOp<T> where T: Clone {
+        let after = match index.min(self.len()) {
+            0 => None,
+            index => self.list.raw_index(index - 1).and_then(|raw| self.list.get(raw)).map(|item| item.id),
+        };
+        self.clock += 1;
+        let id = CrdtId { counter: self.clock, replica: self.replica };
+
+        let op = CrdtOp::Insert { id, after, value };
+        let _ = self.integrate(op.clone());
+        op
+    }
+
+    /// Deletes the visible element at index, if it exists, and returns the op to send to the
+    /// other replicas. Runs in O(log N) time.
+    pub fn local_delete(&mut self, index: usize) -> Option<CrdtOp<T>> {
+        let raw = self.list.raw_index(index)?;
+        let id = self.list.get(raw)?.id;
+        let _ = self.integrate(CrdtOp::Delete { id });
+        Some(CrdtOp::Delete { id })
+    }
+
+    /// Applies an op from another replica, along with any held-back ops it unblocks, and
+    /// returns the resulting Changes to the visible indexes in the order they were made.
+    pub fn apply_remote(&mut self, op: CrdtOp<T>) -> Vec<Change> {
+        let mut changes = Vec::new();
+        let mut ready = VecDeque::new();
+        ready.push_back(op);
+        while let Some(op) = ready.pop_front() {
+            let inserted = match op {
+                CrdtOp::Insert { id, .. } => Some(id),
+                CrdtOp::Delete { .. } => None,
+            };
+            match self.integrate(op) {
+                Ok(change) => changes.extend(change),
+                Err((missing, op)) => {
+                    self.pending.entry(missing).or_default().push(op);
+                    self.pending_len += 1;
+                    continue;
+                },
+            }
+
+            // The new element unblocks the ops waiting for it, which may unblock more
+            if let Some(waiting) = inserted.and_then(|id| self.pending.remove(&id)) {
+                self.pending_len -= waiting.len();
+                ready.extend(waiting);
+            }
+        }
+        changes
+    }
+
+    fn integrate(&mut self, op: CrdtOp<T>) -> Result<Option<Change>, (CrdtId, CrdtOp<T>)> {
+        // Applies an op, returning the Change it made to the visible indexes, or hands it
+        // back with the id of the element it refers to if that hasn't been seen yet
+        match op {
+            CrdtOp::Insert { id, after, value } => {
+                if self.ids.contains_key(&id) {
+                    return Ok(None);
+                }
+                let mut index = match after {
+                    None => 0,
+                    Some(after_id) => match self.raw_index_of(after_id) {
+                        Some(raw) => raw + 1,
+                        None => return Err((after_id, CrdtOp::Insert { id, after, value })),
+                    },
+                };
+
+                // Elements inserted concurrently after the same one are ordered by
+                // descending id, skipping past any with a later id and their successors
+                while self.list.get(index).is_some_and(|item| item.id > id) {
+                    index += 1;
+                }
+
+                self.clock = self.clock.max(id.counter);
+                self.list.insert(Item { id, value: Some(value) }, index, true);
+                if let Some(handle) = self.list.handle(index) {
+                    self.ids.insert(id, handle);
+                }
+                Ok(Some(Change::Inserted { index: self.list.visible_before(index), count: 1 }))
+            },
+            CrdtOp::Delete { id } => {
+                let Some(raw) = self.raw_index_of(id) else {
+                    return Err((id, CrdtOp::Delete { id }));
+                };
+                if self.list.set_visible(raw, false) != Some(true) {
+                    return Ok(None);
+                }
+                if let Some(item) = self.list.get_mut(raw) {
+                    item.value = None;
+                }
+                Ok(Some(Change::Removed { index: self.list.visible_before(raw), count: 1 }))
+            },
+        }
+    }
+
+    fn raw_index_of(&self, id: CrdtId) -> Option<usize> {
+        self.ids.get(&id).and_then(|&handle| self.list.index_of(handle))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::Rng;
+    use super::{CrdtList, CrdtOp};
+    use Change;
+
+    fn contents(list: &CrdtList<u32>) -> Vec<u32> {
+        list.iter().cloned().collect()
+    }
+
+    struct Replica {
+        list: CrdtList<u32>,
+        mirror: Vec<u32>,
+        inbox: Vec<CrdtOp<u32>>,
+    }
+
+    impl Replica {
+        fn deliver(&mut self, count: usize) {
+            for op in self.inbox.drain(..count) {
+                let changes = self.list.apply_remote(op);
+                apply_changes(&self.list, &mut self.mirror, changes);
+            }
+        }
+    }
+
+    #[test]
+    fn test_crdt_concurrent_inserts() {
+        let mut a = CrdtList::new(1);
+        let mut b = CrdtList::new(2);
+        let ops = [a.local_insert(0, 1), a.local_insert(1, 2)];
+        for op in ops.iter().cloned() {
+            b.apply_remote(op);
+        }
+
+        // Both insert between 1 and 2, and one deletes 2, at the same time
+        let from_a = [a.local_insert(1, 10), a.local_insert(2, 11)];
+        let from_b = [b.local_insert(1, 20), b.local_delete(2).unwrap()];
+        for op in from_a.iter().cloned() {
+            b.apply_remote(op);
+        }
+        assert_eq!(vec![1, 20, 10, 11], contents(&b));
+        for op in from_b.iter().rev().cloned() {
+            a.apply_remote(op);
+        }
+        assert_eq!(contents(&a), contents(&b));
+        assert_eq!(0, a.pending_len());
+        assert_eq!(Some(&20), a.get(1));
+        assert_eq!(None, a.get(4));
+
+        // Ops already applied are ignored
+        assert!(a.apply_remote(from_a[0].clone()).is_empty());
+        assert_eq!(4, a.len());
+    }
+
+    #[test]
+    fn test_crdt_out_of_order() {
+        let mut a = CrdtList::new(1);
+        let mut b = CrdtList::new(2);
+        let ops = [a.local_insert(0, 1), a.local_insert(1, 2), a.local_delete(0).unwrap()];
+
+        assert!(b.apply_remote(ops[2].clone()).is_empty());
+        assert!(b.apply_remote(ops[1].clone()).is_empty());
+        assert_eq!(2, b.pending_len());
+        let changes = b.apply_remote(ops[0].clone());
+        // Held-back ops are retried in the order they arrived
+        assert_eq!(vec![Change::Inserted { index: 0, count: 1 }, Change::Removed { index: 0, count: 1 },
+                        Change::Inserted { index: 0, count: 1 }], changes);
+        assert_eq!(vec![2], contents(&b));
+        assert_eq!(0, b.pending_len());
+    }
+
+    #[test]
+    fn test_crdt_reversed_chain() {
+        // Each insert waits on the one before it, so nothing applies until the first arrives
+        let mut a = CrdtList::new(1);
+        let mut b = CrdtList::new(2);
+        let ops: Vec<_> = (0..200).map(|i| a.local_insert(i as usize, i)).collect();
+        for op in ops[1..].iter().rev() {
+            assert!(b.apply_remote(op.clone()).is_empty());
+        }
+        assert_eq!(199, b.pending_len());
+        assert_eq!(200, b.apply_remote(ops[0].clone()).len());
+        assert_eq!(0, b.pending_len());
+        assert_eq!(contents(&a), contents(&b));
+    }
+
+    #[test]
+    fn test_crdt_replicas_converge() {
+        // Replicas edit at random and exchange ops in random order, checking that the
+        // reported Changes keep a plain Vec in step with each replica
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            let mut replicas: Vec<Replica> = (0..3)
+                .map(|r| Replica { list: CrdtList::new(r), mirror: Vec::new(), inbox: Vec::new() })
+                .collect();
+            let mut value = 0;
+
+            for _ in 0..300 {
+                let r = rng.gen_range(0, replicas.len());
+                let replica = &mut replicas[r];
+                if rng.gen_weighted_bool(3) {
+                    // Deliver some of the ops waiting for replica r
+                    rng.shuffle(&mut replica.inbox);
+                    let count = rng.gen_range(0, replica.inbox.len() + 1);
+                    replica.deliver(count);
+                    continue;
+                }
+
+                let index = rng.gen_range(0, replica.list.len() + 1);
+                let op = if index < replica.list.len() && rng.gen_weighted_bool(3) {
+                    replica.mirror.remove(index);
+                    replica.list.local_delete(index).unwrap()
+                } else {
+                    value += 1;
+                    replica.mirror.insert(index, value);
+                    replica.list.local_insert(index, value)
+                };
+                for (other, replica) in replicas.iter_mut().enumerate() {
+                    if other != r {
+                        replica.inbox.push(op.clone());
+                    }
+                }
+            }
+
+            for replica in &mut replicas {
+                rng.shuffle(&mut replica.inbox);
+                let count = replica.inbox.len();
+                replica.deliver(count);
+                assert_eq!(0, replica.list.pending_len());
+                assert_eq!(replica.mirror, contents(&replica.list));
+            }
+            assert_eq!(contents(&replicas[0].list), contents(&replicas[1].list));
+            assert_eq!(contents(&replicas[0].list), contents(&replicas[2].list));
+        }
+    }
+
+    fn apply_changes(list: &CrdtList<u32>, mirror: &mut Vec<u32>, changes: Vec<Change>) {
+        // Inserted values aren't part of a Change, so they go in as 0s, filled in from the
+        // list once all the Changes are applied, while the values already there must match
+        for change in changes {
+            match change {
+                Change::Inserted { index, .. } => mirror.insert(index, 0),
+                Change::Removed { index, .. } => { mirror.remove(index); },
+                Change::Replaced { .. } => {},
+            }
+        }
+        assert_eq!(list.len(), mirror.len());
+        for (index, elem) in mirror.iter_mut().enumerate() {
+            let value = *list.get(index).unwrap();
+            if *elem == 0 {
+                *elem = value;
+            }
+            assert_eq!(value, *elem);
+        }
+    }
+}
@@ -0,0 +1,366 @@
+// (c) 2017 James Crooks
+//
+// Indexed edits, and applying sequences of them to a TList.
+
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+use {Allocator, TList, TListError, Transaction, Transform};
+
+/// An edit to a list at a given index, as applied by TList::apply().
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Delta<T> {
+    /// Inserts value at index, before the element which was there.
+    Insert {
+        /// The index to insert at, which may be the length of the list to append.
+        index: usize,
+        /// The value to insert.
+        value: T,
+    },
+    /// Deletes the element at index.
+    Delete {
+        /// The index of the element to delete.
+        index: usize,
+    },
+    /// Replaces the element at index with value.
+    Replace {
+        /// The index of the element to replace.
+        index: usize,
+        /// The value to replace it with.
+        value: T,
+    },
+    /// Inserts values at index, in order, before the element which was there.
+    InsertRange {
+        /// The index to insert at, which may be the length of the list to append.
+        index: usize,
+        /// The values to insert.
+        values: Vec<T>,
+    },
+    /// Deletes count elements starting at index.
+    DeleteRange {
+        /// The index of the first element to delete.
+        index: usize,
+        /// The number of elements to delete.
+        count: usize,
+    },
+    /// Replaces the elements starting at index with values, one for one.
+    ReplaceRange {
+        /// The index of the first element to replace.
+        index: usize,
+        /// The values to replace them with.
+        values: Vec<T>,
+    },
+}
+
+/// How TList::apply() interprets the indexes of a sequence of Deltas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeltaMode {
+    /// Each Delta's index is relative to the list as left by the Deltas before it.
+    Sequential,
+    /// Every Delta's index is relative to the list before any of them are applied. Inserts
+    /// at the same index are applied in the order given. No two Deltas may delete or
+    /// replace the same element, and none may insert strictly inside a range another deletes
+    /// or replaces.
+    Simultaneous,
+}
+
+impl<T> Delta<T> {
+    /// Returns the index the Delta applies at.
+    pub fn index(&self) -> usize {
+        match *self {
+            Delta::Insert { index, .. } | Delta::Delete { index } |
+            Delta::Replace { index, .. } | Delta::InsertRange { index, .. } |
+            Delta::DeleteRange { index, .. } | Delta::ReplaceRange { index, .. } => index,
+        }
+    }
+
+    fn is_insert(&self) -> bool {
+        matches!(*self, Delta::Insert { .. } | Delta::InsertRange { .. })
+    }
+
+    fn span(&self) -> (usize, usize) {
+        // The range of existing elements the Delta deletes or replaces, which is empty at
+        // the insertion point for inserts. Saturates rather than overflowing, since the end
+        // of a range past MAX_LEN is out of bounds either way.
+        let index = self.index();
+        let count = match *self {
+            Delta::Insert { .. } | Delta::InsertRange { .. } => 0,
+            Delta::Delete { .. } | Delta::Replace { .. } => 1,
+            Delta::DeleteRange { count, .. } => count,
+            Delta::ReplaceRange { ref values, .. } => values.len(),
+        };
+        (index, index.saturating_add(count))
+    }
+}
+
+impl Transform {
+    /// Returns the Transform made by applying deltas per mode, as TList::apply() would. A
+    /// replaced element keeps its index.
+    pub fn from_deltas<T>(deltas: &[Delta<T>], mode: DeltaMode) -> Transform {
+        // Simultaneous Deltas are recorded from the highest index down, in the same order
+        // apply() makes them, so that each index stays valid
+        let mut order: Vec<(usize, &Delta<T>)> = deltas.iter().enumerate().collect();
+        if mode == DeltaMode::Simultaneous {
+            order.sort_by_key(|&(seq, delta)| (Reverse(delta.index()), delta.is_insert(), Reverse(seq)));
+        }
+
+        let mut transform = Transform::new();
+        for (_, delta) in order {
+            match *delta {
+                Delta::Insert { index, .. } => transform.insert(index, 1),
+                Delta::InsertRange { index, ref values } => transform.insert(index, values.len()),
+                Delta::Delete { index } => transform.remove(index, 1),
+                Delta::DeleteRange { index, count } => transform.remove(index, count),
+                Delta::Replace { .. } | Delta::ReplaceRange { .. } => {},
+            }
+        }
+        transform
+    }
+}
+
+impl<T, A> TList<T, A> where T: Clone, A: Allocator + Clone {
+    /// Applies a sequence of Deltas to the TList, with their indexes interpreted per mode.
+    /// Either every Delta is applied or, if one is out of bounds, Simultaneous Deltas
+    /// conflict or allocating a node fails, none are and the error is returned.
+    ///
+    /// Runs in O(K log N) time for K inserted, deleted or replaced elements, plus
+    /// O(D log D) to order D Simultaneous Deltas.
+    pub fn apply<I>(&mut self, deltas: I, mode: DeltaMode) -> Result<(), TListError>
+        where I: IntoIterator<Item = Delta<T>> {
+        match mode {
+            DeltaMode::Sequential => self.transaction(|tx| {
+                for delta in deltas {
+                    apply_delta(tx, delta)?;
+                }
+                Ok(())
+            }),
+            DeltaMode::Simultaneous => self.apply_simultaneous(deltas.into_iter().collect()),
+        }
+    }
+
+    fn apply_simultaneous(&mut self, deltas: Vec<Delta<T>>) -> Result<(), TListError> {
+        let len = self.len();
+        for delta in &deltas {
+            let (start, end) = delta.span();
+            if start > len || end > len {
+                let index = if delta.is_insert() || start == end { start } else { end - 1 };
+                return Err(TListError::IndexOutOfBounds { index, len });
+            }
+        }
+
+        // Working from the highest index down, each Delta only moves elements which have
+        // already been dealt with, so every index stays valid without rebasing. At the same
+        // index, the element there is deleted or replaced before inserting in front of it,
+        // and inserts go in last first so they end up in the order given.
+        let mut order: Vec<(usize, Delta<T>)> = deltas.into_iter()
+            .enumerate()
+            .filter(|(_, delta)| delta.is_insert() || delta.span().0 < delta.span().1)
+            .collect();
+        order.sort_by_key(|&(seq, ref delta)| (Reverse(delta.index()), delta.is_insert(), Reverse(seq)));
+
+        let mut covered_end = 0;
+        for (_, delta) in order.iter().rev() {
+            let (start, end) = delta.span();
+            if start < covered_end {
+                return Err(TListError::ConflictingDeltas { index: start });
+            }
+            covered_end = covered_end.max(end);
+        }
+
+        // The checks above rule out bad indexes, but allocating a node can still fail
+        // partway, so the edits are made in a transaction which undoes them if it does
+        self.transaction(|tx| {
+            for (_, delta) in order {
+                apply_delta(tx, delta)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+fn apply_delta<T, A>(tx: &mut Transaction<'_, T, A>, delta: Delta<T>) -> Result<(), TListError>
+    where T: Clone, A: Allocator + Clone {
+    let (start, end) = delta.span();
+    if !delta.is_insert() && end > tx.len() {
+        return Err(TListError::IndexOutOfBounds { index: start.max(tx.len()), len: tx.len() });
+    }
+
+    match delta {
+        Delta::Insert { index, value } => tx.insert(value, index),
+        Delta::InsertRange { index, values } => {
+            for (offset, value) in values.into_iter().enumerate() {
+                tx.insert(value, index + offset)?;
+            }
+            Ok(())
+        },
+        Delta::Delete { index } => tx.remove(index).map(|_| ()),
+        Delta::DeleteRange { index, count } => {
+            for _ in 0..count {
+                tx.remove(index)?;
+            }
+            Ok(())
+        },
+        Delta::Replace { index, value } => {
+            *tx.get_mut(index)? = value;
+            Ok(())
+        },
+        Delta::ReplaceRange { index, values } => {
+            for (offset, value) in values.into_iter().enumerate() {
+                *tx.get_mut(index + offset)? = value;
+            }
+            Ok(())
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::Rng;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::{Delta, DeltaMode};
+    use tests::CountingAlloc;
+    use {TList, TListError};
+
+    #[test]
+    fn test_apply_sequential() {
+        let mut test_list = TList::<u32>::from_data(&[0, 1, 2, 3, 4, 5]);
+        test_list.apply(vec![
+            Delta::Delete { index: 0 },
+            Delta::Insert { index: 0, value: 10 },
+            Delta::Replace { index: 5, value: 15 },
+            Delta::InsertRange { index: 6, values: vec![16, 17] },
+            Delta::DeleteRange { index: 1, count: 2 },
+            Delta::ReplaceRange { index: 0, values: vec![20, 21] },
+        ], DeltaMode::Sequential).unwrap();
+        test_list.assert_invariants();
+        assert!(test_list.iter().eq([20, 21, 4, 15, 16, 17].iter()));
+
+        // A failing Delta leaves the list as it was
+        for bad in [
+            Delta::Insert { index: 8, value: 0 },
+            Delta::Delete { index: 6 },
+            Delta::Replace { index: 6, value: 0 },
+            Delta::DeleteRange { index: 4, count: 3 },
+            Delta::ReplaceRange { index: 5, values: vec![0, 0] },
+        ] {
+            let result = test_list.apply(vec![
+                Delta::Delete { index: 0 },
+                Delta::Insert { index: 1, value: 99 },
+                bad,
+            ], DeltaMode::Sequential);
+            assert!(matches!(result, Err(TListError::IndexOutOfBounds { .. })), "{:?}", result);
+            test_list.assert_invariants();
+            assert!(test_list.iter().eq([20, 21, 4, 15, 16, 17].iter()));
+        }
+    }
+
+    #[test]
+    fn test_apply_simultaneous() {
+        let mut test_list = TList::<u32>::from_data(&[0, 1, 2, 3, 4, 5]);
+        test_list.apply(vec![
+            Delta::Insert { index: 6, value: 16 },
+            Delta::Delete { index: 0 },
+            Delta::Insert { index: 2, value: 12 },
+            Delta::Replace { index: 2, value: 22 },
+            Delta::InsertRange { index: 2, values: vec![13, 14] },
+            Delta::DeleteRange { index: 3, count: 2 },
+            Delta::Insert { index: 3, value: 33 },
+            Delta::Insert { index: 5, value: 35 },
+            Delta::DeleteRange { index: 5, count: 0 },
+        ], DeltaMode::Simultaneous).unwrap();
+        test_list.assert_invariants();
+        assert!(test_list.iter().eq([1, 12, 13, 14, 22, 33, 35, 5, 16].iter()));
+
+        for (bad, err) in [
+            (Delta::Insert { index: 10, value: 0 }, TListError::IndexOutOfBounds { index: 10, len: 9 }),
+            (Delta::DeleteRange { index: 8, count: 2 }, TListError::IndexOutOfBounds { index: 9, len: 9 }),
+            (Delta::Replace { index: 1, value: 0 }, TListError::ConflictingDeltas { index: 1 }),
+            (Delta::Insert { index: 2, value: 0 }, TListError::ConflictingDeltas { index: 2 }),
+            (Delta::DeleteRange { index: 2, count: 2 }, TListError::ConflictingDeltas { index: 2 }),
+        ] {
+            let result = test_list.apply(vec![
+                Delta::Insert { index: 0, value: 99 },
+                Delta::DeleteRange { index: 1, count: 2 },
+                bad,
+            ], DeltaMode::Simultaneous);
+            assert_eq!(Err(err), result);
+            assert_eq!(9, test_list.len());
+        }
+    }
+
+    #[test]
+    fn test_apply_simultaneous_allocation_failure() {
+        // The node arena is full, so the second insert can't get a node once the first has
+        // taken the one the delete freed, and the edits made by then are rolled back
+        let live = Rc::new(Cell::new(0));
+        let unlimited = CountingAlloc { live: live.clone(), limit: usize::MAX };
+        let arena_size = TList::<u32, _>::with_capacity_in(8, unlimited).allocator().live.get();
+        let limited = CountingAlloc { live, limit: arena_size + 64 };
+        let mut test_list = TList::<u32, _>::with_capacity_in(8, limited);
+        for i in 0..8 {
+            test_list.push(i);
+        }
+
+        let result = test_list.apply(vec![
+            Delta::Insert { index: 0, value: 10 },
+            Delta::Insert { index: 0, value: 11 },
+            Delta::Replace { index: 5, value: 15 },
+            Delta::Delete { index: 7 },
+        ], DeltaMode::Simultaneous);
+        assert!(matches!(result, Err(TListError::AllocationFailure(_))), "{:?}", result);
+        test_list.assert_invariants();
+        assert!(test_list.iter().cloned().eq(0..8));
+    }
+
+    #[test]
+    fn test_apply_simultaneous_random() {
+        // Checks against building the result by walking the original indexes in order
+        let mut rng = rand::thread_rng();
+        for _ in 0..200 {
+            let len = rng.gen_range(0, 40);
+            let data: Vec<u32> = (0..len as u32).collect();
+            let mut deltas = Vec::new();
+            let mut expected = Vec::new();
+            let mut next = 1000;
+            let mut pos = 0;
+            while pos <= len {
+                next += 10;
+                if rng.gen_weighted_bool(3) {
+                    let values: Vec<u32> = (next..next + rng.gen_range(0, 3)).collect();
+                    expected.extend(values.iter().cloned());
+                    deltas.push(Delta::InsertRange { index: pos, values });
+                }
+                if pos == len {
+                    break;
+                }
+
+                match rng.gen_range(0, 3) {
+                    0 => {
+                        let count = rng.gen_range(1, 4).min(len - pos);
+                        deltas.push(Delta::DeleteRange { index: pos, count });
+                        pos += count;
+                    },
+                    1 => {
+                        expected.push(next + 5);
+                        deltas.push(Delta::Replace { index: pos, value: next + 5 });
+                        pos += 1;
+                    },
+                    _ => {
+                        expected.push(data[pos]);
+                        pos += 1;
+                    },
+                }
+            }
+
+            rng.shuffle(&mut deltas[..]);
+            let mut test_list = TList::<u32>::from_data(&data);
+            test_list.apply(deltas, DeltaMode::Simultaneous).unwrap();
+            test_list.assert_invariants();
+            assert!(expected.iter().eq(test_list.iter()));
+        }
+    }
+}
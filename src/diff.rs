@@ -0,0 +1,310 @@
+// (c) 2017 James Crooks
+//
+// Myers' diff between two TLists, producing the Deltas which turn one into the other.
+
+use alloc::vec::Vec;
+
+use {Allocator, Delta, TList};
+
+/// Returns the Deltas which turn a into b, as Inserts and Deletes of single elements, with
+/// indexes relative to a, to be applied with DeltaMode::Simultaneous. The script is as short
+/// as possible, found with Myers' O(ND) algorithm in linear space, where D is the number of
+/// Deltas.
+pub fn diff<T, A, B>(a: &TList<T, A>, b: &TList<T, B>) -> Vec<Delta<T>>
+    where T: Eq + Clone, A: Allocator + Clone, B: Allocator + Clone {
+    diff_by(a, b, |x, y| x == y)
+}
+
+/// Returns the Deltas which turn a into b as diff(), with elements compared by eq.
+pub fn diff_by<T, U, A, B, F>(a: &TList<T, A>, b: &TList<U, B>, eq: F) -> Vec<Delta<U>>
+    where U: Clone, A: Allocator + Clone, B: Allocator + Clone, F: Fn(&T, &U) -> bool {
+    let new = in_order_values(b);
+    let mut deltas = Vec::new();
+    for hunk in hunks(&in_order_values(a), &new, eq) {
+        deltas.extend((hunk.a_lo..hunk.a_hi).map(|index| Delta::Delete { index }));
+        deltas.extend(new[hunk.b_lo..hunk.b_hi].iter()
+            .map(|&value| Delta::Insert { index: hunk.a_hi, value: value.clone() }));
+    }
+    deltas
+}
+
+/// Returns the Deltas which turn a into b as diff(), but with each run of changed elements
+/// expressed as at most two range Deltas: a ReplaceRange for as many elements as are both
+/// deleted and inserted, then a DeleteRange or InsertRange for the rest.
+pub fn diff_ranges<T, A, B>(a: &TList<T, A>, b: &TList<T, B>) -> Vec<Delta<T>>
+    where T: Eq + Clone, A: Allocator + Clone, B: Allocator + Clone {
+    diff_ranges_by(a, b, |x, y| x == y)
+}
+
+/// Returns the range Deltas which turn a into b as diff_ranges(), with elements compared
+/// by eq.
+pub fn diff_ranges_by<T, U, A, B, F>(a: &TList<T, A>, b: &TList<U, B>, eq: F) -> Vec<Delta<U>>
+    where U: Clone, A: Allocator + Clone, B: Allocator + Clone, F: Fn(&T, &U) -> bool {
+    let new = in_order_values(b);
+    let mut deltas = Vec::new();
+    for hunk in hunks(&in_order_values(a), &new, eq) {
+        let values = |lo: usize, hi: usize| new[lo..hi].iter().map(|&v| v.clone()).collect();
+        let deleted = hunk.a_hi - hunk.a_lo;
+        let inserted = hunk.b_hi - hunk.b_lo;
+        let replaced = deleted.min(inserted);
+
+        if replaced > 0 {
+            let values = values(hunk.b_lo, hunk.b_lo + replaced);
+            deltas.push(Delta::ReplaceRange { index: hunk.a_lo, values });
+        }
+        if deleted > replaced {
+            deltas.push(Delta::DeleteRange { index: hunk.a_lo + replaced, count: deleted - replaced });
+        }
+        if inserted > replaced {
+            let values = values(hunk.b_lo + replaced, hunk.b_hi);
+            deltas.push(Delta::InsertRange { index: hunk.a_hi, values });
+        }
+    }
+    deltas
+}
+
+fn in_order_values<T, A>(list: &TList<T, A>) -> Vec<&T> where A: Allocator + Clone {
+    // Gathers the values in O(N), rather than the O(N log N) of searching for each index
+    list.in_order()
+        .into_iter()
+        .filter_map(|idx| list.node_list[idx].as_ref().map(|n| &n.data))
+        .collect()
+}
+
+// A run of changes, which replaces old[a_lo..a_hi] with new[b_lo..b_hi]
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Hunk {
+    a_lo: usize,
+    a_hi: usize,
+    b_lo: usize,
+    b_hi: usize,
+}
+
+fn hunks<T, U, F>(old: &[&T], new: &[&U], eq: F) -> Vec<Hunk> where F: Fn(&T, &U) -> bool {
+    let mut myers = Myers {
+        old,
+        new,
+        eq,
+        forward: Diagonals::new(old.len() + new.len()),
+        backward: Diagonals::new(old.len() + new.len()),
+        hunks: Vec::new(),
+    };
+    myers.conquer(0, old.len(), 0, new.len());
+    myers.hunks
+}
+
+// Furthest reaching x on each diagonal k = x - y, for k in -max..=max
+struct Diagonals {
+    v: Vec<usize>,
+    offset: isize,
+}
+
+impl Diagonals {
+    fn new(len: usize) -> Diagonals {
+        let max = len.div_ceil(2) + 1;
+        Diagonals { v: vec![0; 2 * max + 1], offset: max as isize }
+    }
+
+    #[inline]
+    fn get(&self, k: isize) -> usize {
+        self.v[(k + self.offset) as usize]
+    }
+
+    #[inline]
+    fn set(&mut self, k: isize, x: usize) {
+        self.v[(k + self.offset) as usize] = x;
+    }
+}
+
+struct Myers<'a, 'b, T: 'a, U: 'b, F> {
+    old: &'a [&'a T],
+    new: &'b [&'b U],
+    eq: F,
+    forward: Diagonals,
+    backward: Diagonals,
+    hunks: Vec<Hunk>,
+}
+
+impl<'a, 'b, T, U, F> Myers<'a, 'b, T, U, F> where F: Fn(&T, &U) -> bool {
+    fn conquer(&mut self, mut a_lo: usize, mut a_hi: usize, mut b_lo: usize, mut b_hi: usize) {
+        // Finds the changes turning old[a_lo..a_hi] into new[b_lo..b_hi] by splitting both at
+        // the middle snake of an optimal path, which takes O((N + M) D) time overall
+        while a_lo < a_hi && b_lo < b_hi && (self.eq)(self.old[a_lo], self.new[b_lo]) {
+            a_lo += 1;
+            b_lo += 1;
+        }
+        while a_lo < a_hi && b_lo < b_hi && (self.eq)(self.old[a_hi - 1], self.new[b_hi - 1]) {
+            a_hi -= 1;
+            b_hi -= 1;
+        }
+
+        if a_lo == a_hi || b_lo == b_hi {
+            self.push(Hunk { a_lo, a_hi, b_lo, b_hi });
+        } else {
+            let (x, y) = self.middle_snake(a_lo, a_hi, b_lo, b_hi);
+            self.conquer(a_lo, x, b_lo, y);
+            self.conquer(x, a_hi, y, b_hi);
+        }
+    }
+
+    fn push(&mut self, hunk: Hunk) {
+        // Joins hunk onto the previous one if nothing is left unchanged between them
+        if hunk.a_lo == hunk.a_hi && hunk.b_lo == hunk.b_hi {
+            return;
+        }
+        match self.hunks.last_mut() {
+            Some(last) if last.a_hi == hunk.a_lo && last.b_hi == hunk.b_lo => {
+                last.a_hi = hunk.a_hi;
+                last.b_hi = hunk.b_hi;
+            },
+            _ => self.hunks.push(hunk),
+        }
+    }
+
+    fn middle_snake(&mut self, a_lo: usize, a_hi: usize, b_lo: usize, b_hi: usize) -> (usize, usize) {
+        // Runs Myers' search from both corners at once until the paths overlap, returning the
+        // point where the overlapping snake starts. Both sequences are non-empty and differ
+        // at each end, so the point is strictly inside the rectangle.
+        let n = a_hi - a_lo;
+        let m = b_hi - b_lo;
+        let delta = n as isize - m as isize;
+        let odd = delta & 1 == 1;
+        self.forward.set(1, 0);
+        self.backward.set(1, 0);
+
+        let d_max = (n + m).div_ceil(2) + 1;
+        for d in 0..d_max as isize {
+            for k in (-d..=d).rev().step_by(2) {
+                let mut x = if k == -d || (k != d && self.forward.get(k - 1) < self.forward.get(k + 1)) {
+                    self.forward.get(k + 1)
+                } else {
+                    self.forward.get(k - 1) + 1
+                };
+                let mut y = (x as isize - k) as usize;
+                let (x0, y0) = (x, y);
+                while x < n && y < m && (self.eq)(self.old[a_lo + x], self.new[b_lo + y]) {
+                    x += 1;
+                    y += 1;
+                }
+                self.forward.set(k, x);
+
+                if odd && (k - delta).abs() < d && x + self.backward.get(delta - k) >= n {
+                    return (a_lo + x0, b_lo + y0);
+                }
+            }
+
+            for k in (-d..=d).rev().step_by(2) {
+                let mut x = if k == -d || (k != d && self.backward.get(k - 1) < self.backward.get(k + 1)) {
+                    self.backward.get(k + 1)
+                } else {
+                    self.backward.get(k - 1) + 1
+                };
+                let mut y = (x as isize - k) as usize;
+                while x < n && y < m && (self.eq)(self.old[a_hi - x - 1], self.new[b_hi - y - 1]) {
+                    x += 1;
+                    y += 1;
+                }
+                self.backward.set(k, x);
+
+                if !odd && (k - delta).abs() <= d && x + self.forward.get(delta - k) >= n {
+                    return (a_hi - x, b_hi - y);
+                }
+            }
+        }
+
+        unreachable!("the forward and backward searches always meet");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::Rng;
+    use super::{diff, diff_by, diff_ranges, diff_ranges_by};
+    use {Delta, DeltaMode, TList};
+
+    fn check_diff(a: &[u8], b: &[u8]) -> usize {
+        let (a_list, b_list) = (TList::<u8>::from_data(a), TList::<u8>::from_data(b));
+
+        let deltas = diff(&a_list, &b_list);
+        let mut patched = a_list.clone();
+        patched.apply(deltas.clone(), DeltaMode::Simultaneous).unwrap();
+        assert!(b.iter().eq(patched.iter()), "{:?} -> {:?} by {:?}", a, b, deltas);
+
+        let ranges = diff_ranges(&a_list, &b_list);
+        let mut patched = a_list.clone();
+        patched.apply(ranges, DeltaMode::Simultaneous).unwrap();
+        assert!(b.iter().eq(patched.iter()));
+
+        deltas.len()
+    }
+
+    fn lcs_len(a: &[u8], b: &[u8]) -> usize {
+        let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+        for i in 0..a.len() {
+            for j in 0..b.len() {
+                table[i + 1][j + 1] = if a[i] == b[j] {
+                    table[i][j] + 1
+                } else {
+                    table[i][j + 1].max(table[i + 1][j])
+                };
+            }
+        }
+        table[a.len()][b.len()]
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(0, check_diff(b"", b""));
+        assert_eq!(0, check_diff(b"abc", b"abc"));
+        assert_eq!(3, check_diff(b"", b"abc"));
+        assert_eq!(3, check_diff(b"abc", b""));
+        assert_eq!(5, check_diff(b"abcabba", b"cbabac"));
+
+        let a = TList::<u8>::from_data(b"the cat sat");
+        let b = TList::<u8>::from_data(b"the bat sat down");
+        let mut expected = vec![Delta::Delete { index: 4 }, Delta::Insert { index: 5, value: b'b' }];
+        expected.extend(b" down".iter().map(|&value| Delta::Insert { index: 11, value }));
+        assert_eq!(expected, diff(&a, &b));
+        assert_eq!(vec![
+            Delta::ReplaceRange { index: 4, values: b"b".to_vec() },
+            Delta::InsertRange { index: 11, values: b" down".to_vec() },
+        ], diff_ranges(&a, &b));
+    }
+
+    #[test]
+    fn test_diff_random() {
+        // The script is minimal when its length matches the longest common subsequence
+        let mut rng = rand::thread_rng();
+        for _ in 0..300 {
+            let a: Vec<u8> = (0..rng.gen_range(0, 60)).map(|_| rng.gen_range(0, 4)).collect();
+            let mut b = a.clone();
+            for _ in 0..rng.gen_range(0, 20) {
+                let len = b.len();
+                match rng.gen_range(0, 3) {
+                    0 => b.insert(rng.gen_range(0, len + 1), rng.gen_range(0, 6)),
+                    1 if len > 0 => { b.remove(rng.gen_range(0, len)); },
+                    _ if len > 0 => b[rng.gen_range(0, len)] = rng.gen_range(0, 6),
+                    _ => {},
+                }
+            }
+
+            let lcs = lcs_len(&a, &b);
+            assert_eq!(a.len() + b.len() - 2 * lcs, check_diff(&a, &b), "{:?} -> {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_diff_by() {
+        let a = TList::<&str>::from_data(&["One", "two", "THREE"]);
+        let b = TList::<String>::from_data(&["one".to_string(), "Two".to_string(), "four".to_string()]);
+        let deltas = diff_by(&a, &b, |x, y| x.eq_ignore_ascii_case(y));
+        assert_eq!(vec![
+            Delta::Delete { index: 2 },
+            Delta::Insert { index: 3, value: "four".to_string() },
+        ], deltas);
+        assert_eq!(vec![Delta::ReplaceRange { index: 2, values: vec!["four".to_string()] }],
+                   diff_ranges_by(&a, &b, |x, y| x.eq_ignore_ascii_case(y)));
+    }
+}
@@ -0,0 +1,520 @@
+// (c) 2017 James Crooks
+//
+// A text document held as a TList of its lines, enabled by the "std" feature.
+
+//! LineDocument, a text document addressed by line and column, stored as a TList<String> of
+//! its lines so that edits anywhere in a large file take O(log N) time in its line count.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+
+use patch::{HunkResult, Patch, DEFAULT_FUZZ};
+use {Change, TList};
+
+/// A line ending in a LineDocument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum LineEnding {
+    /// "\n", as on Unix.
+    #[default]
+    Lf,
+    /// "\r\n", as on Windows.
+    CrLf,
+}
+
+impl LineEnding {
+    /// Returns the characters of the line ending.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+/// The unit columns of a Position are counted in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ColumnUnit {
+    /// Bytes of UTF-8.
+    #[default]
+    Bytes,
+    /// Unicode scalar values, i.e. Rust chars.
+    Chars,
+    /// UTF-16 code units, as used by the Language Server Protocol and JavaScript.
+    Utf16,
+}
+
+/// A place in a LineDocument, between two characters or at either end of a line. Both the
+/// line and the column count from 0, with the column in the document's ColumnUnit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Position {
+    /// The line, counting from 0.
+    pub line: usize,
+    /// The column, counting from 0 in the document's ColumnUnit.
+    pub column: usize,
+}
+
+impl Position {
+    /// Returns the Position at the given line and column.
+    pub fn new(line: usize, column: usize) -> Position {
+        Position { line, column }
+    }
+}
+
+/// A Position which is past the end of its line or of the document, or which splits a
+/// character, or a Range of them which ends before it starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidPosition(pub Position);
+
+impl fmt::Display for InvalidPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid position at line {}, column {}", self.0.line, self.0.column)
+    }
+}
+
+impl Error for InvalidPosition {}
+
+/// A text document held as a TList of its lines, without their line endings.
+///
+/// A document always has at least one line: the text after its last line ending, which is
+/// empty if the text ends with a line ending. Every other line keeps the ending it was read
+/// or inserted with, so writing the document out reproduces the text it was read from byte
+/// for byte, even where LF and CRLF are mixed. A "\r" which isn't followed by "\n" is part
+/// of its line.
+///
+/// Inserting text splits the line it's inserted into at each line ending in the text, and
+/// deleting a range spanning several lines merges what's left of the first and last.
+#[derive(Debug, Clone)]
+pub struct LineDocument {
+    lines: TList<String>,
+    // The ending of each line, kept in step with lines, which is None for the last line only
+    endings: TList<Option<LineEnding>>,
+    // The ending given to lines added by a patch
+    ending: LineEnding,
+    unit: ColumnUnit,
+}
+
+impl LineDocument {
+    /// Generate a new document holding a single empty line, with LF as its line_ending() and
+    /// with columns counted in bytes.
+    pub fn new() -> LineDocument {
+        let mut lines = TList::new();
+        lines.push(String::new());
+        let mut endings = TList::new();
+        endings.push(None);
+        LineDocument { lines, endings, ending: LineEnding::Lf, unit: ColumnUnit::Bytes }
+    }
+
+    /// Reads a document from reader, which must hold UTF-8 text, keeping the ending of each
+    /// line. The first line's ending becomes the document's line_ending().
+    pub fn from_reader<R>(mut reader: R) -> io::Result<LineDocument> where R: BufRead {
+        let mut doc = LineDocument { lines: TList::new(), endings: TList::new(), ..LineDocument::new() };
+        let mut ending = None;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                break;
+            }
+            if !line.ends_with('\n') {
+                // The text after the last line ending
+                doc.lines.push(line.clone());
+                doc.endings.push(None);
+                return Ok(doc);
+            }
+
+            line.pop();
+            let line_ending = if line.ends_with('\r') {
+                line.pop();
+                LineEnding::CrLf
+            } else {
+                LineEnding::Lf
+            };
+            doc.ending = *ending.get_or_insert(line_ending);
+            doc.lines.push(line.clone());
+            doc.endings.push(Some(line_ending));
+        }
+
+        doc.lines.push(String::new());
+        doc.endings.push(None);
+        Ok(doc)
+    }
+
+    /// Reads a document from a string, as from_reader().
+    pub fn from_text(text: &str) -> LineDocument {
+        match LineDocument::from_reader(text.as_bytes()) {
+            Ok(doc) => doc,
+            Err(_) => unreachable!("a str is valid UTF-8 and reading it can't fail"),
+        }
+    }
+
+    /// Writes the document's lines to writer, each followed by its own line ending.
+    pub fn write_to<W>(&self, mut writer: W) -> io::Result<()> where W: Write {
+        for (line, ending) in self.lines.iter().zip(self.endings.iter()) {
+            writer.write_all(line.as_bytes())?;
+            if let Some(ending) = *ending {
+                writer.write_all(ending.as_str().as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the number of lines, which is at least one.
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Returns the text of a line, without its line ending, if it exists.
+    pub fn line(&self, line: usize) -> Option<&str> {
+        self.lines.get(line).map(String::as_str)
+    }
+
+    /// Returns the lines of the document, without their line endings.
+    pub fn lines(&self) -> &TList<String> {
+        &self.lines
+    }
+
+    /// Returns the ending of a line, or None for the last line, which has none, or a line
+    /// which doesn't exist.
+    pub fn ending_of(&self, line: usize) -> Option<LineEnding> {
+        self.endings.get(line).cloned().flatten()
+    }
+
+    /// Returns the document's line ending: the first line's when it was read, or the one last
+    /// set. Lines added by apply_patch() are given it.
+    pub fn line_ending(&self) -> LineEnding {
+        self.ending
+    }
+
+    /// Sets the document's line ending, converting every line to it.
+    pub fn set_line_ending(&mut self, ending: LineEnding) {
+        self.ending = ending;
+        self.endings.traverse(&|old: Option<LineEnding>| old.map(|_| ending));
+    }
+
+    /// Returns the unit the columns of Positions are counted in.
+    pub fn column_unit(&self) -> ColumnUnit {
+        self.unit
+    }
+
+    /// Sets the unit the columns of Positions are counted in.
+    pub fn set_column_unit(&mut self, unit: ColumnUnit) {
+        self.unit = unit;
+    }
+
+    /// Returns the Position at the end of the document.
+    pub fn end(&self) -> Position {
+        let line = self.lines.len() - 1;
+        Position::new(line, self.columns(self.line(line).unwrap_or("")))
+    }
+
+    /// Returns the text between two Positions, with each line's own ending.
+    pub fn text(&self, range: Range<Position>) -> Result<String, InvalidPosition> {
+        let (start, end) = self.byte_range(&range)?;
+        if range.start.line == range.end.line {
+            return Ok(self.line_text(range.start.line)[start..end].to_string());
+        }
+
+        let mut text = self.line_text(range.start.line)[start..].to_string();
+        for line in range.start.line + 1..=range.end.line {
+            text.push_str(self.ending_of(line - 1).unwrap_or_default().as_str());
+            if line < range.end.line {
+                text.push_str(self.line_text(line));
+            }
+        }
+        text.push_str(&self.line_text(range.end.line)[..end]);
+        Ok(text)
+    }
+
+    /// Inserts text at pos, splitting the line at each "\n" or "\r\n" in text, and returns the
+    /// Position at the end of the inserted text. Each new line break keeps the ending it has
+    /// in text.
+    pub fn insert_text(&mut self, pos: Position, text: &str) -> Result<Position, InvalidPosition> {
+        let offset = self.byte_offset(pos)?;
+        let mut pieces = text.split('\n');
+        let last = pieces.next_back().unwrap_or("");
+        // Each piece but the last was followed by "\n", so a "\r" ending it was part of a CRLF
+        let broken: Vec<(&str, LineEnding)> = pieces.map(|piece| match piece.strip_suffix('\r') {
+            Some(piece) => (piece, LineEnding::CrLf),
+            None => (piece, LineEnding::Lf),
+        }).collect();
+
+        let line = self.lines.get_mut(pos.line).ok_or(InvalidPosition(pos))?;
+        let Some((&(first, _), middle)) = broken.split_first() else {
+            line.insert_str(offset, last);
+            return Ok(Position::new(pos.line, pos.column + self.columns(last)));
+        };
+
+        // The line's own ending moves to the end of the last new line
+        let tail = line.split_off(offset);
+        line.push_str(first);
+        for (i, &(piece, _)) in middle.iter().enumerate() {
+            self.lines.insert(piece.to_string(), pos.line + 1 + i);
+        }
+        for (i, &(_, ending)) in broken.iter().enumerate() {
+            self.endings.insert(Some(ending), pos.line + i);
+        }
+        let last_line = pos.line + broken.len();
+        self.lines.insert(format!("{}{}", last, tail), last_line);
+        Ok(Position::new(last_line, self.columns(last)))
+    }
+
+    /// Deletes the text between two Positions, merging the start of the first line with the
+    /// end of the last, which keeps the last line's ending, and returns it as text() would.
+    pub fn delete(&mut self, range: Range<Position>) -> Result<String, InvalidPosition> {
+        let deleted = self.text(range.clone())?;
+        let (start, end) = self.byte_range(&range)?;
+
+        let tail = self.line_text(range.end.line)[end..].to_string();
+        for _ in range.start.line..range.end.line {
+            self.lines.remove(range.start.line + 1);
+            self.endings.remove(range.start.line);
+        }
+        if let Some(line) = self.lines.get_mut(range.start.line) {
+            line.truncate(start);
+            line.push_str(&tail);
+        }
+        Ok(deleted)
+    }
+
+    /// Applies the hunks of a unified diff to the document's lines, as Patch::apply(). Lines
+    /// the patch adds are given the document's line_ending(), and the others keep theirs.
+    pub fn apply_patch(&mut self, patch: &Patch) -> Vec<HunkResult> {
+        // Follows the line which was last, and so has no ending, as lines move around it
+        let (endings, ending) = (&mut self.endings, self.ending);
+        let mut was_last = Some(self.lines.len() - 1);
+        let results = patch.apply_observed(&mut self.lines, DEFAULT_FUZZ, &mut |change| match change {
+            Change::Inserted { index, .. } => {
+                endings.insert(Some(ending), index);
+                was_last = was_last.map(|last| if last >= index { last + 1 } else { last });
+            },
+            Change::Removed { index, .. } => {
+                endings.remove(index);
+                was_last = was_last.filter(|&last| last != index).map(|last| if last > index { last - 1 } else { last });
+            },
+            Change::Replaced { .. } => {},
+        });
+
+        // Lines added after the last one leave it needing an ending, and whichever line is
+        // now last has none
+        if let Some(line_ending) = was_last.and_then(|line| self.endings.get_mut(line)) {
+            line_ending.get_or_insert(ending);
+        }
+        if self.lines.is_empty() {
+            self.lines.push(String::new());
+            self.endings.push(None);
+        }
+        if let Some(line_ending) = self.endings.get_mut(self.lines.len() - 1) {
+            *line_ending = None;
+        }
+        results
+    }
+
+    fn line_text(&self, line: usize) -> &str {
+        self.line(line).unwrap_or("")
+    }
+
+    fn byte_range(&self, range: &Range<Position>) -> Result<(usize, usize), InvalidPosition> {
+        let start = self.byte_offset(range.start)?;
+        let end = self.byte_offset(range.end)?;
+        if range.end < range.start {
+            return Err(InvalidPosition(range.end));
+        }
+        Ok((start, end))
+    }
+
+    fn byte_offset(&self, pos: Position) -> Result<usize, InvalidPosition> {
+        // Converts pos's column to a byte offset into its line, checking that it falls
+        // between two characters
+        let line = self.line(pos.line).ok_or(InvalidPosition(pos))?;
+        let offset = match self.unit {
+            ColumnUnit::Bytes => Some(pos.column).filter(|&column| line.is_char_boundary(column)),
+            ColumnUnit::Chars => line.char_indices()
+                .map(|(offset, _)| offset)
+                .chain(Some(line.len()))
+                .nth(pos.column),
+            ColumnUnit::Utf16 => {
+                let mut units = 0;
+                let mut offset = None;
+                for (byte, c) in line.char_indices().chain(Some((line.len(), '\0'))) {
+                    if units >= pos.column {
+                        // Past the column only if it falls inside a surrogate pair
+                        offset = Some(byte).filter(|_| units == pos.column);
+                        break;
+                    }
+                    units += c.len_utf16();
+                }
+                offset
+            },
+        };
+        offset.ok_or(InvalidPosition(pos))
+    }
+
+    fn columns(&self, text: &str) -> usize {
+        match self.unit {
+            ColumnUnit::Bytes => text.len(),
+            ColumnUnit::Chars => text.chars().count(),
+            ColumnUnit::Utf16 => text.encode_utf16().count(),
+        }
+    }
+}
+
+impl Default for LineDocument {
+    fn default() -> LineDocument {
+        LineDocument::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::Rng;
+    use super::{ColumnUnit, InvalidPosition, LineDocument, LineEnding, Position};
+    use patch::{HunkResult, Patch};
+
+    fn written(doc: &LineDocument) -> String {
+        let mut out = Vec::new();
+        doc.write_to(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_document_round_trip() {
+        for text in ["", "\n", "one", "one\ntwo\n", "one\r\ntwo\r\n", "one\r\n\r\ntwo", "é\n😀",
+                     "one\ntwo\r\nthree", "\r", "one\r\r\n\rtwo\n"] {
+            let doc = LineDocument::from_text(text);
+            assert_eq!(text, written(&doc));
+            doc.lines().assert_invariants();
+        }
+
+        let doc = LineDocument::from_text("one\r\ntwo\nthree");
+        assert_eq!(LineEnding::CrLf, doc.line_ending());
+        assert_eq!(3, doc.line_count());
+        assert_eq!(Some("two"), doc.line(1));
+        assert_eq!("one\r\ntwo\nthree", written(&doc));
+
+        // Each line keeps its own ending, and a lone "\r" stays in its line
+        let mut doc = LineDocument::from_text("one\ntwo\r\nthree\r");
+        assert_eq!(LineEnding::Lf, doc.line_ending());
+        assert_eq!((Some("two"), Some(LineEnding::CrLf)), (doc.line(1), doc.ending_of(1)));
+        assert_eq!((Some("three\r"), None), (doc.line(2), doc.ending_of(2)));
+        assert_eq!(Ok("\ntwo\r\n".to_string()), doc.text(Position::new(0, 3)..Position::new(2, 0)));
+        doc.set_line_ending(LineEnding::CrLf);
+        assert_eq!("one\r\ntwo\r\nthree\r", written(&doc));
+
+        assert_eq!(2, LineDocument::from_text("one\n").line_count());
+        assert_eq!(1, LineDocument::new().line_count());
+        assert!(LineDocument::from_reader(&b"bad \xff utf-8"[..]).is_err());
+    }
+
+    #[test]
+    fn test_document_columns() {
+        // é is 2 bytes and 1 UTF-16 unit, 😀 is 4 bytes and 2 UTF-16 units
+        let mut doc = LineDocument::from_text("aé😀b");
+        for (unit, end, inside) in [(ColumnUnit::Bytes, 8, 2), (ColumnUnit::Chars, 4, 5), (ColumnUnit::Utf16, 5, 3)] {
+            doc.set_column_unit(unit);
+            assert_eq!(Position::new(0, end), doc.end());
+            assert_eq!(Ok("b".to_string()), doc.text(Position::new(0, end - 1)..Position::new(0, end)));
+            assert_eq!(Err(InvalidPosition(Position::new(0, inside))),
+                       doc.text(Position::new(0, 0)..Position::new(0, inside)));
+        }
+
+        doc.set_column_unit(ColumnUnit::Utf16);
+        assert_eq!(Ok(Position::new(0, 4)), doc.insert_text(Position::new(0, 2), "xy"));
+        assert_eq!("aéxy😀b", written(&doc));
+        assert_eq!(Ok("😀".to_string()), doc.delete(Position::new(0, 4)..Position::new(0, 6)));
+        assert_eq!("aéxyb", written(&doc));
+        assert_eq!(Err(InvalidPosition(Position::new(1, 0))), doc.insert_text(Position::new(1, 0), "x"));
+    }
+
+    #[test]
+    fn test_document_split_merge() {
+        let mut doc = LineDocument::from_text("one\ntwo\nthree\n");
+        assert_eq!(Ok(Position::new(3, 2)), doc.insert_text(Position::new(1, 1), "X\r\nY\nZZ"));
+        assert_eq!("one\ntX\r\nY\nZZwo\nthree\n", written(&doc));
+
+        let range = Position::new(0, 2)..Position::new(3, 3);
+        assert_eq!(Ok("e\ntX\r\nY\nZZw".to_string()), doc.text(range.clone()));
+        assert_eq!(Ok("e\ntX\r\nY\nZZw".to_string()), doc.delete(range));
+        assert_eq!("ono\nthree\n", written(&doc));
+
+        assert_eq!(Err(InvalidPosition(Position::new(0, 1))),
+                   doc.delete(Position::new(1, 0)..Position::new(0, 1)));
+        assert_eq!(Ok("\n".to_string()), doc.delete(Position::new(1, 5)..Position::new(2, 0)));
+        assert_eq!("ono\nthree", written(&doc));
+        doc.lines().assert_invariants();
+    }
+
+    #[test]
+    fn test_document_random() {
+        // Checks edits against the same edits on a String, in chars
+        let mut rng = rand::thread_rng();
+        let alphabet: Vec<char> = "ab é😀\r\n".chars().collect();
+        let mut model = String::from("hello\nwörld\n");
+        let mut doc = LineDocument::from_text(&model);
+        doc.set_column_unit(ColumnUnit::Chars);
+
+        // Maps a char offset into the model to a Position and a byte offset, going by the lines
+        // and line endings of the document, or to None if it falls inside a CRLF
+        let position = |doc: &LineDocument, model: &str, char_offset: usize| {
+            let byte = model.char_indices().map(|(byte, _)| byte).chain(Some(model.len())).nth(char_offset)?;
+            let mut start = 0;
+            for line in 0..doc.line_count() {
+                let text = doc.line(line).unwrap();
+                if byte <= start + text.len() {
+                    return Some((Position::new(line, text[..byte - start].chars().count()), byte));
+                }
+                start += text.len() + doc.ending_of(line).map_or(0, |ending| ending.as_str().len());
+                if byte < start {
+                    return None;
+                }
+            }
+            None
+        };
+
+        for _ in 0..500 {
+            let chars = model.chars().count();
+            if rng.gen_weighted_bool(2) {
+                let text: String = (0..rng.gen_range(0, 6)).map(|_| *rng.choose(&alphabet).unwrap()).collect();
+                let Some((pos, offset)) = position(&doc, &model, rng.gen_range(0, chars + 1)) else {
+                    continue;
+                };
+                let end = doc.insert_text(pos, &text).unwrap();
+                model.insert_str(offset, &text);
+                assert_eq!(model, written(&doc));
+                let end_offset = model[..offset].chars().count() + text.chars().count();
+                assert_eq!(Some(end), position(&doc, &model, end_offset).map(|(pos, _)| pos));
+            } else {
+                let a = rng.gen_range(0, chars + 1);
+                let b = rng.gen_range(a, chars + 1);
+                let (Some((start, start_byte)), Some((end, end_byte))) = (position(&doc, &model, a), position(&doc, &model, b)) else {
+                    continue;
+                };
+                assert_eq!(Ok(model[start_byte..end_byte].to_string()), doc.delete(start..end));
+                model.replace_range(start_byte..end_byte, "");
+            }
+            assert_eq!(model, written(&doc));
+        }
+        doc.lines().assert_invariants();
+    }
+
+    #[test]
+    fn test_document_patch() {
+        let mut doc = LineDocument::from_text("one\r\ntwo\r\nthree\r\n");
+        let patch = Patch::parse("@@ -1,3 +1,3 @@\n one\n-two\n+TWO\n three\n").unwrap();
+        assert_eq!(vec![HunkResult::Applied { offset: 0, fuzz: 0 }], doc.apply_patch(&patch));
+        assert_eq!("one\r\nTWO\r\nthree\r\n", written(&doc));
+
+        // Lines the patch adds take the document's line ending, and the rest keep their own
+        let mut doc = LineDocument::from_text("one\r\ntwo\nthree");
+        let patch = Patch::parse("@@ -2,2 +2,3 @@\n two\n-three\n+THREE\n+four\n").unwrap();
+        assert_eq!(vec![HunkResult::Applied { offset: 0, fuzz: 0 }], doc.apply_patch(&patch));
+        assert_eq!("one\r\ntwo\nTHREE\r\nfour", written(&doc));
+        let patch = Patch::parse("@@ -4 +4,2 @@\n four\n+five\n").unwrap();
+        assert_eq!(vec![HunkResult::Applied { offset: 0, fuzz: 0 }], doc.apply_patch(&patch));
+        assert_eq!("one\r\ntwo\nTHREE\r\nfour\r\nfive", written(&doc));
+        doc.lines().assert_invariants();
+    }
+}
@@ -0,0 +1,73 @@
+// (c) 2017 James Crooks
+//
+// Error type for the fallible TList API.
+
+use allocator_api2::collections::TryReserveError;
+use core::error::Error;
+use core::fmt;
+
+/// The ways a fallible TList operation such as try_insert() or try_reserve() can fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TListError {
+    /// The index passed was past the end of the list.
+    IndexOutOfBounds {
+        /// The index that was requested.
+        index: usize,
+        /// The length of the list at the time of the request.
+        len: usize,
+    },
+    /// Allocating space for more nodes failed, or the requested capacity overflowed.
+    AllocationFailure(TryReserveError),
+    /// The list would grow past MAX_LEN elements, the most its node links can address.
+    CapacityOverflow,
+    /// Deltas applied simultaneously touch the same element, or one inserts inside a range
+    /// another deletes or replaces.
+    ConflictingDeltas {
+        /// The index, relative to the original list, at which the deltas conflict.
+        index: usize,
+    },
+    /// The tree was found to be internally inconsistent, e.g. a missing child or an
+    /// empty slot where a node should be. This indicates a bug in TList, and the list
+    /// should not be relied upon after it is reported.
+    Corrupted {
+        /// The node slot at which the inconsistency was detected.
+        node: usize,
+    },
+}
+
+impl fmt::Display for TListError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TListError::IndexOutOfBounds { index, len } => {
+                write!(f, "index {} is out of bounds for a TList of length {}", index, len)
+            },
+            TListError::AllocationFailure(ref e) => write!(f, "TList allocation failed: {}", e),
+            TListError::CapacityOverflow => {
+                write!(f, "TList can't hold more than {} elements", ::MAX_LEN)
+            },
+            TListError::ConflictingDeltas { index } => {
+                write!(f, "simultaneous deltas conflict at index {}", index)
+            },
+            TListError::Corrupted { node } => {
+                write!(f, "TList is internally inconsistent at node slot {}", node)
+            },
+        }
+    }
+}
+
+impl Error for TListError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            // allocator-api2 only implements Error for its TryReserveError with std
+            #[cfg(feature = "std")]
+            TListError::AllocationFailure(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<TryReserveError> for TListError {
+    fn from(e: TryReserveError) -> TListError {
+        TListError::AllocationFailure(e)
+    }
+}
@@ -0,0 +1,413 @@
+// (c) 2017 James Crooks
+//
+// A TList supporting O(log N) updates to ranges of elements, pushed down to them lazily.
+
+//! LazyTList, a TList whose ranges of elements can be updated in O(log N) time.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use {Dir, Handle, TList};
+
+/// A summary of a run of elements, such as their sum or maximum, which LazyTList keeps for
+/// every sub-tree so that the summary of any range can be found in O(log N) time.
+pub trait Summary<T>: Clone {
+    /// The summary of a single element.
+    fn of(value: &T) -> Self;
+
+    /// The summary of a run of elements followed by the run right summarizes.
+    fn combine(&self, right: &Self) -> Self;
+}
+
+/// The summary of a LazyTList which keeps none.
+impl<T> Summary<T> for () {
+    fn of(_value: &T) {}
+
+    fn combine(&self, _right: &()) {}
+}
+
+/// An update applied to every element of a range of a LazyTList, such as adding to or
+/// assigning a number.
+pub trait Action<T, S: Summary<T> = ()>: Clone {
+    /// Applies the action to a single element.
+    fn apply(&self, value: &mut T);
+
+    /// Applies the action to the summary of a run of len elements, giving the summary of the
+    /// run after the action is applied to each of them.
+    fn apply_summary(&self, summary: &mut S, len: usize);
+
+    /// Returns the action which has the same effect as applying this one and then later.
+    fn then(&self, later: &Self) -> Self;
+}
+
+/// A TList whose ranges of elements can be updated all at once, as when shifting the indent
+/// level of a whole block of lines, in O(log N) time however long the range.
+///
+/// update_range() applies its Action straight away only to the O(log N) nodes at the top of
+/// the sub-trees making up the range, leaving it pending there for the rest. Pending actions
+/// are pushed down a level whenever the tree is searched, rotated or iterated below them,
+/// which is why the read methods take &mut self. Each node also keeps a Summary of its
+/// sub-tree, which summary() combines for any range in O(log N) time.
+#[derive(Debug, Clone)]
+pub struct LazyTList<T, Act, S = ()> {
+    tree: TList<Entry<T, Act, S>>,
+}
+
+#[derive(Debug, Clone)]
+struct Entry<T, Act, S> {
+    // The value and summary are always current, while tag is pending for the children
+    value: T,
+    summary: S,
+    tag: Option<Act>,
+}
+
+impl<T, Act: Action<T, S>, S: Summary<T>> Entry<T, Act, S> {
+    fn apply(&mut self, action: &Act, len: usize) {
+        // Applies action to this entry's sub-tree of len elements, pending for its children
+        action.apply(&mut self.value);
+        action.apply_summary(&mut self.summary, len);
+        self.tag = Some(match self.tag.take() {
+            Some(tag) => tag.then(action),
+            None => action.clone(),
+        });
+    }
+}
+
+impl<T, Act: Action<T, S>, S: Summary<T>> LazyTList<T, Act, S> {
+    /// Generate a new, empty LazyTList.
+    pub fn new() -> LazyTList<T, Act, S> {
+        let mut tree = TList::new();
+        tree.augment = Some(summarize::<T, Act, S>);
+        tree.push_down = Some(push_down::<T, Act, S>);
+        LazyTList { tree }
+    }
+
+    /// Gets the number of elements.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Returns true if the list contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Returns a reference to the element at index if it exists, pushing down any actions
+    /// pending above it. Runs in O(log N) time.
+    pub fn get(&mut self, index: usize) -> Option<&T> {
+        let slot = self.tree.search(index)?;
+        self.tree.push_path(slot);
+        self.entry(slot).map(|entry| &entry.value)
+    }
+
+    /// Replaces the element at index with value, returning the old one if it exists. Runs in
+    /// O(log N) time.
+    pub fn replace(&mut self, index: usize, value: T) -> Option<T> {
+        let slot = self.tree.search(index)?;
+        self.tree.push_path(slot);
+        let entry = self.tree.get_mut_by_handle(Handle(slot))?;
+        let old = core::mem::replace(&mut entry.value, value);
+        self.tree.augment_path(Some(slot));
+        Some(old)
+    }
+
+    /// Inserts value at index. As with TList, an index past the end appends.
+    pub fn insert(&mut self, value: T, index: usize) {
+        self.tree.insert(Entry { summary: S::of(&value), value, tag: None }, index);
+    }
+
+    /// Appends value to the end of the list.
+    pub fn push(&mut self, value: T) {
+        let index = self.len();
+        self.insert(value, index);
+    }
+
+    /// Deletes the element at index, returning it if it exists.
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        self.tree.remove(index).map(|entry| entry.value)
+    }
+
+    /// Applies action to every element in range, clamped to the list. Runs in O(log N) time,
+    /// however many elements the range holds.
+    pub fn update_range(&mut self, range: Range<usize>, action: Act) {
+        let end = range.end.min(self.len());
+        if range.start < end {
+            let root = self.tree.root_idx;
+            self.update(root, 0, range.start..end, &action);
+        }
+    }
+
+    /// Returns the summary of the elements in range, clamped to the list, or None if that
+    /// holds no elements. Runs in O(log N) time.
+    pub fn summary(&mut self, range: Range<usize>) -> Option<S> {
+        let end = range.end.min(self.len());
+        if range.start >= end {
+            return None;
+        }
+        let root = self.tree.root_idx;
+        self.query(root, 0, range.start..end)
+    }
+
+    /// Returns an iterator over the elements in index order, after pushing down every
+    /// pending action. Runs in O(N) time.
+    pub fn iter(&mut self) -> impl Iterator<Item = &T> {
+        if !self.is_empty() {
+            let mut stack = vec![self.tree.root_idx];
+            while let Some(slot) = stack.pop() {
+                push_down(&mut self.tree, slot);
+                stack.extend(self.tree.get_child_idx(slot, Dir::Left));
+                stack.extend(self.tree.get_child_idx(slot, Dir::Right));
+            }
+        }
+        // Walking the slots in order, rather than looking each index up, keeps this O(N)
+        let tree = &self.tree;
+        tree.in_order()
+            .into_iter()
+            .filter_map(move |slot| tree.get_by_handle(Handle(slot)))
+            .map(|entry| &entry.value)
+    }
+
+    /// Checks the Red-Black invariants of the underlying tree, and, once every pending action
+    /// is pushed down, that every node's summary combines its sub-tree's, panicking with a
+    /// description of the first violation found. Only compiled for tests and fuzzing builds.
+    #[cfg(any(test, fuzzing))]
+    pub fn assert_invariants(&mut self) where S: PartialEq + core::fmt::Debug {
+        self.tree.assert_invariants();
+        let _ = self.iter().count();
+        for slot in self.tree.in_order() {
+            let stored = self.entry(slot).map(|entry| entry.summary.clone());
+            summarize(&mut self.tree, slot);
+            assert_eq!(stored.as_ref(), self.entry(slot).map(|entry| &entry.summary), "stale summary");
+        }
+    }
+
+    fn update(&mut self, slot: usize, offset: usize, range: Range<usize>, action: &Act) {
+        // Applies action to the elements in range of the sub-tree at slot, whose first
+        // element is at index offset
+        let size = self.sub_size(slot);
+        if range.end <= offset || offset + size <= range.start {
+            return;
+        }
+        if range.start <= offset && offset + size <= range.end {
+            if let Some(entry) = self.tree.get_mut_by_handle(Handle(slot)) {
+                entry.apply(action, size);
+            }
+            return;
+        }
+
+        push_down(&mut self.tree, slot);
+        let own = offset + self.tree.get_child_size(slot, Dir::Left).unwrap_or(0);
+        if let Some(left) = self.tree.get_child_idx(slot, Dir::Left) {
+            self.update(left, offset, range.clone(), action);
+        }
+        if range.contains(&own) {
+            if let Some(entry) = self.tree.get_mut_by_handle(Handle(slot)) {
+                action.apply(&mut entry.value);
+            }
+        }
+        if let Some(right) = self.tree.get_child_idx(slot, Dir::Right) {
+            self.update(right, own + 1, range, action);
+        }
+        summarize(&mut self.tree, slot);
+    }
+
+    fn query(&mut self, slot: usize, offset: usize, range: Range<usize>) -> Option<S> {
+        // Summarizes the elements in range of the sub-tree at slot, whose first element is
+        // at index offset
+        let size = self.sub_size(slot);
+        if range.end <= offset || offset + size <= range.start {
+            return None;
+        }
+        if range.start <= offset && offset + size <= range.end {
+            return self.entry(slot).map(|entry| entry.summary.clone());
+        }
+
+        push_down(&mut self.tree, slot);
+        let own = offset + self.tree.get_child_size(slot, Dir::Left).unwrap_or(0);
+        let mut parts = Vec::with_capacity(3);
+        if let Some(left) = self.tree.get_child_idx(slot, Dir::Left) {
+            parts.extend(self.query(left, offset, range.clone()));
+        }
+        if range.contains(&own) {
+            parts.extend(self.entry(slot).map(|entry| S::of(&entry.value)));
+        }
+        if let Some(right) = self.tree.get_child_idx(slot, Dir::Right) {
+            parts.extend(self.query(right, own + 1, range));
+        }
+        parts.into_iter().reduce(|left, right| left.combine(&right))
+    }
+
+    fn entry(&self, slot: usize) -> Option<&Entry<T, Act, S>> {
+        self.tree.get_by_handle(Handle(slot))
+    }
+
+    fn sub_size(&self, slot: usize) -> usize {
+        self.tree.node_list[slot].as_ref().map_or(0, |node| node.size())
+    }
+}
+
+impl<T, Act: Action<T, S>, S: Summary<T>> Default for LazyTList<T, Act, S> {
+    fn default() -> LazyTList<T, Act, S> {
+        LazyTList::new()
+    }
+}
+
+fn summarize<T, Act, S: Summary<T>>(tree: &mut TList<Entry<T, Act, S>>, slot: usize) {
+    // The TList's augment hook, which recombines an entry's summary from its children's
+    let child_summary = |dir| {
+        tree.get_child_idx(slot, dir)
+            .and_then(|idx| tree.node_list[idx].as_ref())
+            .map(|child| child.data.summary.clone())
+    };
+    let (left, right) = (child_summary(Dir::Left), child_summary(Dir::Right));
+    if let Some(node) = tree.node_list[slot].as_mut() {
+        let mut summary = S::of(&node.data.value);
+        if let Some(left) = left {
+            summary = left.combine(&summary);
+        }
+        if let Some(right) = right {
+            summary = summary.combine(&right);
+        }
+        node.data.summary = summary;
+    }
+}
+
+fn push_down<T, Act: Action<T, S>, S: Summary<T>>(tree: &mut TList<Entry<T, Act, S>>, slot: usize) {
+    // The TList's push_down hook, which hands an entry's pending action on to its children
+    let Some(action) = tree.node_list[slot].as_mut().and_then(|node| node.data.tag.take()) else {
+        return;
+    };
+    for dir in [Dir::Left, Dir::Right] {
+        if let Some(child) = tree.get_child_idx(slot, dir).and_then(|idx| tree.node_list[idx].as_mut()) {
+            let len = child.size();
+            child.data.apply(&action, len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::Rng;
+    use super::{Action, LazyTList, Summary};
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Update {
+        Add(i64),
+        Assign(i64),
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Sum(i64);
+
+    impl Summary<i64> for Sum {
+        fn of(value: &i64) -> Sum {
+            Sum(*value)
+        }
+
+        fn combine(&self, right: &Sum) -> Sum {
+            Sum(self.0 + right.0)
+        }
+    }
+
+    impl Action<i64, Sum> for Update {
+        fn apply(&self, value: &mut i64) {
+            match *self {
+                Update::Add(delta) => *value += delta,
+                Update::Assign(new) => *value = new,
+            }
+        }
+
+        fn apply_summary(&self, summary: &mut Sum, len: usize) {
+            match *self {
+                Update::Add(delta) => summary.0 += delta * len as i64,
+                Update::Assign(new) => summary.0 = new * len as i64,
+            }
+        }
+
+        fn then(&self, later: &Update) -> Update {
+            match (*self, *later) {
+                (Update::Add(a), Update::Add(b)) => Update::Add(a + b),
+                (Update::Assign(a), Update::Add(b)) => Update::Assign(a + b),
+                (_, later) => later,
+            }
+        }
+    }
+
+    // The same updates, for a LazyTList which keeps no summary
+    impl Action<i64> for Update {
+        fn apply(&self, value: &mut i64) {
+            Action::<i64, Sum>::apply(self, value)
+        }
+
+        fn apply_summary(&self, _summary: &mut (), _len: usize) {}
+
+        fn then(&self, later: &Update) -> Update {
+            Action::<i64, Sum>::then(self, later)
+        }
+    }
+
+    #[test]
+    fn test_lazy_indent() {
+        // Indenting a block of lines, then flattening part of it
+        let mut indents: LazyTList<i64, Update, Sum> = LazyTList::new();
+        for indent in [0, 1, 1, 2, 1, 0].iter().cloned() {
+            indents.push(indent);
+        }
+        indents.update_range(1..5, Update::Add(4));
+        assert_eq!(Some(&6), indents.get(3));
+        assert_eq!(Some(Sum(21)), indents.summary(0..6));
+        indents.update_range(2..10, Update::Assign(3));
+        indents.insert(7, 3);
+        assert_eq!(Some(3), indents.remove(4));
+        assert_eq!(Some(3), indents.replace(2, 9));
+        assert_eq!(vec![0, 5, 9, 7, 3, 3], indents.iter().cloned().collect::<Vec<_>>());
+        assert_eq!(Some(Sum(21)), indents.summary(1..4));
+        assert_eq!(None, indents.summary(6..8));
+        indents.assert_invariants();
+
+        // Without a summary, only the values are updated
+        let mut plain: LazyTList<i64, Update> = LazyTList::new();
+        plain.push(1);
+        plain.update_range(0..1, Update::Add(1));
+        assert_eq!(Some(&2), plain.get(0));
+    }
+
+    #[test]
+    fn test_lazy_random() {
+        let mut rng = rand::thread_rng();
+        let mut list: LazyTList<i64, Update, Sum> = LazyTList::new();
+        let mut model: Vec<i64> = Vec::new();
+
+        for n in 0..2000 {
+            let index = rng.gen_range(0, model.len() + 1);
+            let end = rng.gen_range(index, model.len() + 2);
+            let value = rng.gen_range(-50, 50);
+            match rng.gen_range(0, 6) {
+                0 | 1 => {
+                    list.insert(value, index);
+                    model.insert(index, value);
+                },
+                2 if index < model.len() => assert_eq!(Some(model.remove(index)), list.remove(index)),
+                3 => {
+                    let update = if rng.gen_weighted_bool(2) { Update::Add(value) } else { Update::Assign(value) };
+                    list.update_range(index..end, update);
+                    for elem in model.iter_mut().take(end).skip(index) {
+                        Action::<i64, Sum>::apply(&update, elem);
+                    }
+                },
+                4 => {
+                    let expected = Some(Sum(model.iter().take(end).skip(index).sum())).filter(|_| index < end.min(model.len()));
+                    assert_eq!(expected, list.summary(index..end));
+                },
+                _ => assert_eq!(model.get(index), list.get(index)),
+            }
+            if n % 500 == 0 {
+                list.assert_invariants();
+            }
+        }
+        list.assert_invariants();
+        assert_eq!(model, list.iter().cloned().collect::<Vec<_>>());
+    }
+}
@@ -1,4 +1,5 @@
 #![deny(missing_docs)]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
 // (c) 2017 James Crooks
 //
@@ -24,12 +25,68 @@
 //! in situations where reads are primarily via iteration
 //! over the whole collection, or ranges, but mutation
 //! is primarily through insert/delete, e.g. delta-merge
-//! operations.
+//! operations, which TList::apply() performs for a sequence of Deltas.
 //! 
 //! Implemented internally using a Vec and indexes
 //! in an Arena-like fashion.
 
-use std::mem;
+#[macro_use]
+extern crate alloc;
+extern crate allocator_api2;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(any(feature = "std", test))]
+extern crate core;
+
+pub mod chunked;
+mod crdt;
+mod delta;
+mod diff;
+#[cfg(feature = "std")]
+pub mod document;
+mod error;
+pub mod lazy;
+mod node;
+mod observer;
+mod ot;
+mod transaction;
+mod transform;
+pub mod undo;
+pub mod visible;
+#[cfg(feature = "rayon")]
+pub mod par;
+pub mod patch;
+pub mod rope;
+#[cfg(feature = "std")]
+pub mod shared;
+
+pub use chunked::ChunkedTList;
+pub use crdt::{CrdtId, CrdtList, CrdtOp};
+pub use delta::{Delta, DeltaMode};
+pub use diff::{diff, diff_by, diff_ranges, diff_ranges_by};
+#[cfg(feature = "std")]
+pub use document::LineDocument;
+pub use allocator_api2::alloc::{Allocator, Global};
+pub use error::TListError;
+pub use lazy::{Action, LazyTList, Summary};
+pub use node::MAX_LEN;
+pub use observer::{Change, Observer};
+pub use ot::transform;
+pub use patch::{Hunk, HunkLine, HunkResult, ParseError, Patch};
+pub use rope::TRope;
+pub use transaction::Transaction;
+pub use transform::{Bias, Transform};
+pub use undo::UndoTList;
+pub use visible::VisibleTList;
+#[cfg(feature = "std")]
+pub use shared::{SharedTList, Snapshot};
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use allocator_api2::vec::Vec as ArenaVec;
+
+use node::{Color, Node};
+use observer::ObserverSlot;
 
 const INITIAL_SIZE: usize = 256; // Initial number of nodes allocated by default
 const DEFAULT_STACK_DEPTH: usize = 64;
@@ -43,42 +100,22 @@ struct NodeLoc {
      node_idx: usize,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum Color {
-    Red,
-    Black,
-}
-
-#[derive(Debug, Clone)]
-struct Node<T: Sized> {
-    data: T,
-    color: Color,
-    parent: Option<usize>,
-    left: Option<usize>,
-    right: Option<usize>,
-    size: usize,
-}
-
-impl<T> Node<T> where T: Sized {
-    #[inline]
-    fn new_leaf(elem: T, color: Color) -> Node<T> {
-        Node {
-            data: elem,
-            color: color,
-            parent: None,
-            left: None,
-            right: None,
-            size: 1,
-        }
-    }
-}
-
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum Dir {
     Left,
     Right
 }
 
+// Reports an internal inconsistency found at the given node slot. Tests and fuzzing
+// builds panic immediately so that the failure is caught where it is detected.
+#[inline]
+fn corrupted(node: usize, msg: &str) -> TListError {
+    if cfg!(any(test, fuzzing)) {
+        panic!("{} (node {})", msg, node);
+    }
+    TListError::Corrupted { node }
+}
+
 /// An indexable list structure based on a Red-Black tree that uses
 /// sub-tree counts as a 'key' to achieve O(lg N) insertion,
 /// deletion, and access. Originally designed for rapid
@@ -94,12 +131,55 @@ enum Dir {
 /// 
 /// Implemented internally using a Vec and indexes
 /// in an Arena-like fashion.
+///
+/// The nodes are allocated with A, the global allocator by default, so that a TList can be
+/// placed in an arena with new_in() or with_capacity_in(). Temporary buffers, e.g. those used
+/// by compact(), still come from the global allocator.
+///
+/// An Observer registered with set_observer() is told of every Change made to the indexes.
 
 #[derive(Debug, Clone)]
-pub struct TList<T: Sized> {
-    node_list: Vec<Option<Node<T>>>,
-    free_list: Vec<usize>,
+pub struct TList<T: Sized, A: Allocator + Clone = Global> {
+    // Slots at or above node_list.len() are never materialized: they are the Vec's
+    // spare capacity, and node_list.len() acts as a high-water mark. The free_list
+    // only tracks the empty slots below the mark, left behind by removals.
+    node_list: ArenaVec<Option<Node<T>>, A>,
+    free_list: ArenaVec<usize, A>,
     root_idx: usize,
+    compact_threshold: Option<f64>,
+    observer: ObserverSlot,
+    // Recomputes any per-node summary kept in a node's value from its own value and its
+    // children's, run wherever sub-tree sizes change. Set by the types built on TList which
+    // keep per-node summaries, such as TRope.
+    augment: Option<NodeHook<T, A>>,
+    // Pushes any update pending for a node's sub-tree down to its children, run on the nodes
+    // whose sub-trees are about to change shape, top down. Only LazyTList sets one.
+    push_down: Option<NodeHook<T, A>>,
+}
+
+type NodeHook<T, A> = fn(&mut TList<T, A>, usize);
+
+/// A stable reference to an element of a TList. Unlike an index, a Handle keeps referring to
+/// the same element as other elements are inserted and removed around it, and finding the
+/// element's current index from it takes O(log N).
+///
+/// A Handle is invalidated when its element is removed, since the slot may be reused by a
+/// later insertion, and by compact(), which returns a Remap to translate outstanding Handles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle(usize);
+
+/// Translates the Handles of a TList from before a call to compact() to after it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Remap {
+    slots: Vec<Option<usize>>,
+}
+
+impl Remap {
+    /// Returns the new Handle for an element given its Handle from before compaction,
+    /// or None if the old Handle didn't refer to an element.
+    pub fn get(&self, old: Handle) -> Option<Handle> {
+        self.slots.get(old.0).and_then(|slot| slot.map(Handle))
+    }
 }
 
 impl<T> TList<T> where T: Sized {
@@ -112,60 +192,46 @@ impl<T> TList<T> where T: Sized {
 
     /// Initialize a new TList<T> with a chosen capacity
     pub fn with_capacity(capacity: usize) -> TList<T> {
-        let mut node_list = Vec::with_capacity(capacity);
-        let mut free_list = Vec::with_capacity(capacity);
-        for i in 0..capacity {
-            node_list.push(None);
-            free_list.push(i);
-        }
-
-        TList {
-            node_list: node_list,
-            free_list: free_list,
-            root_idx: 0,
-        }
-    }
-
-    /// Gets the number of elements in the TList
-    pub fn len(&self) -> usize {
-        // Return the number of filled nodes
-        self.node_list.len() - self.free_list.len()
-    }
-
-    /// Gets the total possible number of elements the TList can store
-    /// without reallocating.
-    pub fn capacity(&self) -> usize {
-        // Capacity is the number of free slots
-        self.node_list.len()
+        Self::with_capacity_in(capacity, Global)
     }
 
     /// Build a new TList from a vector
     /// of data elements, such that the inorder traversal
     /// of the TList maintains the same ordering as
     /// the original vector.
+    ///
+    /// Panics if data holds more than MAX_LEN elements.
     pub fn from_data<U>(data: &[U]) -> TList<U> where U: Sized + Clone {
         // We allocate the node_list with capacity for data.len() items,
-        // and empty the free_list since we will return a tree with
-        // capacity exactly the same as the size of data, and all slots will
+        // and raise the high-water mark to the top since we will return a tree
+        // with capacity exactly the same as the size of data, and all slots will
         // be filled.
 
+        assert!(data.len() <= MAX_LEN, "TList capacity overflow");
         let mut index_tree = TList::with_capacity(data.len());
-        index_tree.free_list.clear();
+        index_tree.node_list.resize_with(data.len(), || None);
 
         // degenerate case of no data
-        if 0 == data.len() {
+        if data.is_empty() {
             return index_tree;
         }
         
         // otherwise we choose the central data value as our root node value
         let root_data_val_loc = data.len() >> 1;
 
+        // Splitting at the midpoint fills every level of the tree except the deepest, so
+        // colors alternate by depth, anchored such that the deepest level is Red. This keeps
+        // the black height equal along every path. The root is always painted Black when
+        // the node is built, even if its alternating color would be Red.
+        let max_depth = usize::BITS - 1 - data.len().leading_zeros();
+        let root_color = if max_depth & 1 == 0 { Color::Red } else { Color::Black };
+
         // allocate a stack for storing next node locations to insert data into
         // and initialize with the data to insert the root node.
         let mut build_stack = Vec::<NodeLoc>::with_capacity(DEFAULT_STACK_DEPTH);
         build_stack.push(
             NodeLoc {
-                color: Color::Black,
+                color: root_color,
                 parent: None,
                 left_edge: 0,
                 right_edge: data.len(),
@@ -177,22 +243,254 @@ impl<T> TList<T> where T: Sized {
             let mut node = Node::new_leaf(data[node_loc.node_idx].clone(), node_loc.color);
 
             if let Some((loc, count)) = Self::prepare_left_child(&mut build_stack, &node_loc) {
-                node.left = Some(loc);
-                node.size += count;
+                node.set_left(Some(loc));
+                node.set_size(node.size() + count).expect("TList capacity overflow");
             }
 
             if let Some((loc, count)) = Self::prepare_right_child(&mut build_stack, &node_loc) {
-                node.right = Some(loc);
-                node.size += count;
+                node.set_right(Some(loc));
+                node.set_size(node.size() + count).expect("TList capacity overflow");
+            }
+            node.set_parent(node_loc.parent);
+            if node.parent().is_none() {
+                node.set_color(Color::Black);
             }
-            node.parent = node_loc.parent;
 
             index_tree.node_list[node_loc.node_idx] = Some(node);
         }
 
         index_tree
     }
-   
+}
+
+impl<T, A> TList<T, A> where T: Sized, A: Allocator + Clone {
+    /// Generate a new TList<T, A> with the default capacity, allocating its nodes with alloc.
+    pub fn new_in(alloc: A) -> TList<T, A> {
+        Self::with_capacity_in(INITIAL_SIZE, alloc)
+    }
+
+    /// Initialize a new TList<T, A> with a chosen capacity, allocating its nodes with alloc.
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> TList<T, A> {
+        TList {
+            node_list: ArenaVec::with_capacity_in(capacity, alloc.clone()),
+            free_list: ArenaVec::new_in(alloc),
+            root_idx: 0,
+            compact_threshold: None,
+            observer: ObserverSlot::default(),
+            augment: None,
+            push_down: None,
+        }
+    }
+
+    /// Returns a reference to the allocator the TList's nodes are allocated with.
+    pub fn allocator(&self) -> &A {
+        self.node_list.allocator()
+    }
+
+    /// Gets the number of elements in the TList
+    pub fn len(&self) -> usize {
+        // Return the number of filled nodes
+        self.node_list.len() - self.free_list.len()
+    }
+
+    /// Returns true if the TList contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gets the total possible number of elements the TList can store
+    /// without reallocating.
+    pub fn capacity(&self) -> usize {
+        // Capacity is every slot below the high-water mark, filled or free,
+        // plus the untouched slots above it
+        self.node_list.capacity()
+    }
+
+    /// Reserves capacity for at least additional more elements to be inserted without
+    /// reallocating. May reserve more space to avoid frequent reallocations, as Vec does.
+    ///
+    /// Panics if the new capacity overflows usize.
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = additional.saturating_sub(self.free_list.len());
+        self.node_list.reserve(needed);
+    }
+
+    /// Reserves capacity for exactly additional more elements to be inserted without
+    /// reallocating, as Vec's reserve_exact().
+    ///
+    /// Panics if the new capacity overflows usize.
+    pub fn reserve_exact(&mut self, additional: usize) {
+        let needed = additional.saturating_sub(self.free_list.len());
+        self.node_list.reserve_exact(needed);
+    }
+
+    /// Tries to reserve capacity for at least additional more elements to be inserted without
+    /// reallocating. If the list would exceed MAX_LEN elements, the capacity overflows, or the
+    /// allocator reports a failure, an error is returned and the TList is left unchanged.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TListError> {
+        self.check_max_len(additional)?;
+        let needed = additional.saturating_sub(self.free_list.len());
+        self.node_list.try_reserve(needed)?;
+        Ok(())
+    }
+
+    /// Tries to reserve capacity for exactly additional more elements, as reserve_exact(),
+    /// reporting failure as try_reserve() does.
+    pub fn try_reserve_exact(&mut self, additional: usize) -> Result<(), TListError> {
+        self.check_max_len(additional)?;
+        let needed = additional.saturating_sub(self.free_list.len());
+        self.node_list.try_reserve_exact(needed)?;
+        Ok(())
+    }
+
+    /// Shrinks the capacity of the TList as much as possible. Empty slots left between
+    /// elements by removals can't be released without moving nodes, so only the free
+    /// slots past the last occupied one are given back.
+    pub fn shrink_to_fit(&mut self) {
+        while let Some(&None) = self.node_list.last() {
+            self.node_list.pop();
+        }
+
+        let high_water = self.node_list.len();
+        self.free_list.retain(|&idx| idx < high_water);
+        self.node_list.shrink_to_fit();
+        self.free_list.shrink_to_fit();
+    }
+
+    /// Rebuilds the node arena so that the nodes are stored densely, in index order, with no
+    /// empty slots between them. Over many random insertions and removals, neighboring
+    /// elements end up scattered across the arena, which this restores for cache-friendly
+    /// iteration. Runs in O(N) time and moves nodes in place, without a second arena.
+    ///
+    /// Every outstanding Handle is invalidated; the returned Remap translates them.
+    pub fn compact(&mut self) -> Remap {
+        let order = self.in_order();
+        let len = order.len();
+        let high_water = self.node_list.len();
+
+        // targets[old] is the slot each node moves to. Empty slots are sent to the end,
+        // which turns the move into a permutation of the whole node_list.
+        let mut slots = vec![None; high_water];
+        let mut targets = vec![0; high_water];
+        for (new_idx, &old_idx) in order.iter().enumerate() {
+            slots[old_idx] = Some(new_idx);
+            targets[old_idx] = new_idx;
+        }
+        for (hole, &old_idx) in self.free_list.iter().enumerate() {
+            targets[old_idx] = len + hole;
+        }
+
+        let relink = |link: Option<usize>| link.and_then(|idx| slots[idx]);
+        for n in self.node_list.iter_mut().flatten() {
+            n.set_parent(relink(n.parent()));
+            n.set_left(relink(n.left()));
+            n.set_right(relink(n.right()));
+        }
+
+        // Follow each cycle of the permutation, swapping nodes into place
+        for start in 0..high_water {
+            while targets[start] != start {
+                let target = targets[start];
+                self.node_list.swap(start, target);
+                targets.swap(start, target);
+            }
+        }
+
+        self.root_idx = slots.get(self.root_idx).and_then(|&slot| slot).unwrap_or(0);
+        self.node_list.truncate(len);
+        self.free_list.clear();
+        Remap { slots }
+    }
+
+    /// Sets the fraction of empty slots in the arena, between 0 and 1, above which a removal
+    /// automatically compacts the TList. None, the default, disables automatic compaction.
+    ///
+    /// Automatic compaction invalidates outstanding Handles without a Remap to translate
+    /// them, so it shouldn't be enabled while Handles are held.
+    pub fn set_compact_threshold(&mut self, threshold: Option<f64>) {
+        self.compact_threshold = threshold;
+    }
+
+    /// Registers observer to be told of every Change made to the TList from now on, replacing
+    /// any Observer registered before. A clone of the TList starts out without an Observer.
+    pub fn set_observer<O>(&mut self, observer: O) where O: Observer + 'static {
+        self.observer = ObserverSlot(Some(Box::new(observer)));
+    }
+
+    /// Unregisters the TList's Observer, returning it if there was one.
+    pub fn take_observer(&mut self) -> Option<Box<dyn Observer>> {
+        self.observer.0.take()
+    }
+
+    #[inline]
+    fn notify(&mut self, change: Change) {
+        if let Some(observer) = self.observer.0.as_mut() {
+            observer.on_change(change);
+        }
+    }
+
+    /// Returns a Handle to the element at index, if it exists. Runs in O(log N) time.
+    pub fn handle(&self, index: usize) -> Option<Handle> {
+        self.search(index).map(Handle)
+    }
+
+    /// Returns the current index of the element referred to by handle, if it still exists.
+    /// Runs in O(log N) time.
+    pub fn index_of(&self, handle: Handle) -> Option<usize> {
+        let mut idx = handle.0;
+        let mut index = match self.node_list.get(idx) {
+            Some(Some(_)) => self.get_child_size(idx, Dir::Left).ok()?,
+            _ => return None,
+        };
+
+        // Every ancestor we reach from its right child precedes us, along with its left sub-tree
+        while let Some(p_idx) = self.get_parent_idx(idx) {
+            if self.get_child_idx(p_idx, Dir::Right) == Some(idx) {
+                index += self.get_child_size(p_idx, Dir::Left).ok()? + 1;
+            }
+            idx = p_idx;
+        }
+
+        Some(index)
+    }
+
+    /// Returns a reference to the element referred to by handle, if it still exists. Runs in
+    /// O(1) time.
+    pub fn get_by_handle(&self, handle: Handle) -> Option<&T> {
+        match self.node_list.get(handle.0) {
+            Some(Some(node)) => Some(&node.data),
+            _ => None,
+        }
+    }
+
+    /// Returns a mutable reference to the element referred to by handle, if it still exists.
+    /// Runs in O(1) time, or O(log N) with an Observer, which is told the element was
+    /// Replaced as get_mut() describes.
+    pub fn get_mut_by_handle(&mut self, handle: Handle) -> Option<&mut T> {
+        // Finding the index to report takes O(log N), so it's skipped without an Observer
+        if self.observer.0.is_some() {
+            if let Some(index) = self.index_of(handle) {
+                self.notify(Change::Replaced { index });
+            }
+        }
+
+        match self.node_list.get_mut(handle.0) {
+            Some(Some(node)) => Some(&mut node.data),
+            _ => None,
+        }
+    }
+
+    /// Removes all elements from the TList, keeping the allocated capacity.
+    pub fn clear(&mut self) {
+        let count = self.len();
+        if count > 0 {
+            self.notify(Change::Removed { index: 0, count });
+        }
+        self.node_list.clear();
+        self.free_list.clear();
+        self.root_idx = 0;
+    }
+
     /// Insert a new value into the TList at the given index. Unlike a regular Vec,
     /// insertion at one past the final element (the TList.len() value) is valid and equivalent
     /// to push(). Insertion is guarenteed to operate in O(log N).
@@ -201,21 +499,69 @@ impl<T> TList<T> where T: Sized {
     /// behavior is not guarenteed by the contract of this function and may change. Use
     /// insert_or_push() which specifies that behavior.
     pub fn insert(&mut self, elem: T, index: usize) {
-
         // Insert element into the node_list and get it's index
-        let insert_idx = self.add_leaf(elem);
-        
+        let insert_idx = self.add_leaf(elem).expect("Memory allocation failure.");
+        let _ = self.link_leaf(insert_idx, index);
+    }
+
+    /// Inserts a new value into the TList at the given index, which may be at most len(). Unlike
+    /// insert(), this never panics: an index past the end, a failure to allocate space for the
+    /// new node, or a detected inconsistency in the tree are reported as an error. On error
+    /// the value is dropped.
+    pub fn try_insert(&mut self, elem: T, index: usize) -> Result<(), TListError> {
+        if index > self.len() {
+            return Err(TListError::IndexOutOfBounds { index, len: self.len() });
+        }
+
+        let insert_idx = self.add_leaf(elem)?;
+        self.link_leaf(insert_idx, index)
+    }
+
+    /// Appends an element to the end of the list, as push(), reporting allocation failure or
+    /// a detected inconsistency in the tree as an error instead of panicking.
+    pub fn try_push(&mut self, elem: T) -> Result<(), TListError> {
+        let loc = self.len();
+        self.try_insert(elem, loc)
+    }
+
+    #[inline]
+    fn link_leaf(&mut self, insert_idx: usize, index: usize) -> Result<(), TListError> {
+        // Links the newly added leaf at insert_idx into the tree so that it has the given
+        // index, or is appended if the index is past the end, then tells any observer once
+        // it's in place.
+        let index = index.min(self.len() - 1);
+        self.place_leaf(insert_idx, index)?;
+        self.notify(Change::Inserted { index, count: 1 });
+        Ok(())
+    }
+
+    #[inline]
+    fn place_leaf(&mut self, insert_idx: usize, index: usize) -> Result<(), TListError> {
+        // Links the leaf at insert_idx in at index, which is at most len() - 1, then repairs
+        // the Red-Black invariants.
+        // if the tree is empty, the new leaf becomes the root
+        if self.len() == 1 {
+            self.root_idx = insert_idx;
+            self.augment_path(Some(insert_idx));
+            return self.insert_fix_up(insert_idx);
+        }
+
         let mut target_rank = index;
         let mut search_idx = self.root_idx;
 
         loop {
-            self.node_list[search_idx]
-                .as_mut()
-                .map(|n| n.size += 1);
+            if let Some(push_down) = self.push_down {
+                push_down(self, search_idx);
+            }
+            match self.node_list[search_idx].as_mut() {
+                Some(n) => n.set_size(n.size() + 1)
+                    .map_err(|()| corrupted(search_idx, "Sub-tree size out of range!"))?,
+                None => return Err(corrupted(search_idx, "No node on insertion path!")),
+            }
 
-            let rank = self.get_child_size(search_idx, Dir::Left);
+            let rank = self.get_child_size(search_idx, Dir::Left)?;
             if target_rank > rank { //attempt to insert on the right
-                match self.node_list[search_idx].as_ref().and_then(|n| n.right) {
+                match self.node_list[search_idx].as_ref().and_then(|n| n.right()) {
                     Some(new_idx) => {
                         // Keep searching at the right sub-tree
                         target_rank -= rank + 1;
@@ -225,50 +571,42 @@ impl<T> TList<T> where T: Sized {
                     None => {
                         // If there's no right child, then we've reached the largest
                         // index value in the sub-tree, so we insert here.
-                        self.node_list[search_idx]
-                            .as_mut()
-                            .map(|n| n.right = Some(insert_idx));
-                        self.node_list[insert_idx]
-                            .as_mut()
-                            .map(|n| n.parent = Some(search_idx));
+                        if let Some(n) = self.node_list[search_idx].as_mut() {
+                            n.set_right(Some(insert_idx));
+                        }
+                        if let Some(n) = self.node_list[insert_idx].as_mut() {
+                            n.set_parent(Some(search_idx));
+                        }
                         break;
                     },
                 }
             } else { // attempt to insert on the left
-                match self.node_list[search_idx].as_ref().and_then(|n| n.left) {
+                match self.node_list[search_idx].as_ref().and_then(|n| n.left()) {
                     Some(new_idx) => {
                         search_idx = new_idx;
                         continue;
                     },
                     None => {
-                        self.node_list[search_idx]
-                            .as_mut()
-                            .map(|n| n.left = Some(insert_idx));
-                        self.node_list[insert_idx]
-                            .as_mut()
-                            .map(|n| n.parent = Some(search_idx));
+                        if let Some(n) = self.node_list[search_idx].as_mut() {
+                            n.set_left(Some(insert_idx));
+                        }
+                        if let Some(n) = self.node_list[insert_idx].as_mut() {
+                            n.set_parent(Some(search_idx));
+                        }
                         break;
                     }
                 }
             }
         }
 
-        self.insert_fix_up(insert_idx);
+        self.augment_path(Some(insert_idx));
+        self.insert_fix_up(insert_idx)
     }
 
     /// Inserts an element at the end of the list. Unlike Vec, this is an O(log N) operation.
     pub fn push(&mut self, elem: T) {
-        // if the root is empty, insert into root
-        if self.len() == 0 {
-            let insert_idx = self.add_leaf(elem);
-            self.node_list[insert_idx]
-                .as_mut()
-                .map(|n| n.color = Color::Black);
-            self.root_idx = insert_idx;
-        } else {
-            let loc = self.len();
-            self.insert(elem, loc);
-        }
+        let loc = self.len();
+        self.insert(elem, loc);
     }
 
     /// Inserts an element at index if the index is valid, and appends the element per push if the
@@ -283,10 +621,7 @@ impl<T> TList<T> where T: Sized {
 
     /// Returns a reference to the value at index if it exists. Runs in O(log N) time.
     pub fn get(&self, index: usize) -> Option<&T> {
-        let target_idx = match self.search(index) {
-            Some(idx) => idx,
-            None => return None,
-        };
+        let target_idx = self.search(index)?;
 
         let node = match self.node_list[target_idx] {
             Some(ref node) => node,
@@ -297,11 +632,13 @@ impl<T> TList<T> where T: Sized {
     }
 
     /// Returns a mutable reference to the value at index if it exists. Runs in O(log N) time.
+    ///
+    /// Any Observer is told of a Change::Replaced at index as the reference is handed out,
+    /// whether or not the caller goes on to write through it, since the TList can't tell.
+    /// Use get() to read without reporting a change.
     pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
-        let target_idx = match self.search(index) {
-            Some(idx) => idx,
-            None => return None,
-        };
+        let target_idx = self.search(index)?;
+        self.notify(Change::Replaced { index });
 
         let node = match self.node_list[target_idx] {
             Some(ref mut node) => node,
@@ -315,49 +652,157 @@ impl<T> TList<T> where T: Sized {
     /// indexes are shifted down "implicitly", yielding the same behavior as removing a value from
     /// a Vec but with O(log N) running time.
     pub fn remove(&mut self, index: usize) -> Option<T> {
-        if index >= self.len() {
-            return None;
+        self.try_remove(index).ok()
+    }
+
+    /// Deletes the value at the specified index as remove(), reporting an index past the end or
+    /// a detected inconsistency in the tree as an error.
+    pub fn try_remove(&mut self, index: usize) -> Result<T, TListError> {
+        let z_idx = self.try_search(index)?;
+        let removed = self.remove_slot(z_idx)?;
+        self.notify(Change::Removed { index, count: 1 });
+
+        if let Some(threshold) = self.compact_threshold {
+            if self.free_list.len() as f64 > threshold * self.node_list.len() as f64 {
+                self.compact();
+            }
         }
-    
 
-        None
+        Ok(removed)
     }
 
-    #[inline]
-    fn transplant(&mut self, u: usize, v: usize) {
-        let u_p = match self.node_list[u].as_ref() {
-            Some(node) => node.parent,
-            None => {
-                if cfg!(test) {
-                    panic!("Invalid transplant choice u at index {}", u);
+    fn remove_slot(&mut self, z_idx: usize) -> Result<T, TListError> {
+        // Unlinks the node at z_idx from the tree and frees its slot, returning its value.
+        let (z_left, z_right, z_parent, z_color, z_size) = match self.node_list[z_idx] {
+            Some(ref node) => (node.left(), node.right(), node.parent(), node.color(), node.size()),
+            None => return Err(corrupted(z_idx, "No node at removal index!")),
+        };
+
+        // Following CLRS, y is the node physically spliced out of the tree: z itself
+        // if z has at most one child, or otherwise the successor of z, which moves
+        // into z's position. x is the child that moves into y's old position, and
+        // since x may be nil, we track its parent separately.
+        let y_idx = match (z_left, z_right) {
+            (Some(_), Some(r_idx)) => self.minimum(r_idx),
+            _ => z_idx,
+        };
+        self.push_path(y_idx);
+        let z_weight = self.own_weight(z_idx)?;
+        let mut weight = self.own_weight(y_idx)?;
+
+        // Every ancestor of y's old position loses y from its sub-tree, up to z, where y
+        // takes over z's reduced size below. Everything above z loses z itself.
+        let mut walk_idx = self.get_parent_idx(y_idx);
+        while let Some(idx) = walk_idx {
+            if idx == z_idx {
+                weight = z_weight;
+            }
+            walk_idx = match self.node_list[idx].as_mut() {
+                Some(node) => {
+                    node.set_size(node.size() - weight)
+                        .map_err(|()| corrupted(idx, "Sub-tree size out of range!"))?;
+                    node.parent()
+                },
+                None => return Err(corrupted(idx, "No node on removal path!")),
+            };
+        }
+
+        let mut y_color = z_color;
+        let x_idx;
+        let x_parent;
+
+        if z_left.is_none() {
+            x_idx = z_right;
+            x_parent = z_parent;
+            self.transplant(z_idx, z_right)?;
+        } else if z_right.is_none() {
+            x_idx = z_left;
+            x_parent = z_parent;
+            self.transplant(z_idx, z_left)?;
+        } else {
+            let (y_right, y_parent) = match self.node_list[y_idx] {
+                Some(ref node) => {
+                    y_color = node.color();
+                    (node.right(), node.parent())
+                },
+                None => return Err(corrupted(y_idx, "No node at successor!")),
+            };
+
+            x_idx = y_right;
+            if y_parent == Some(z_idx) {
+                x_parent = Some(y_idx);
+            } else {
+                x_parent = y_parent;
+                self.transplant(y_idx, y_right)?;
+                if let Some(y_node) = self.node_list[y_idx].as_mut() {
+                    y_node.set_right(z_right);
+                }
+                if let Some(n) = z_right.and_then(|idx| self.node_list[idx].as_mut()) {
+                    n.set_parent(Some(y_idx));
                 }
-                return;
             }
+
+            self.transplant(z_idx, Some(y_idx))?;
+            if let Some(y_node) = self.node_list[y_idx].as_mut() {
+                y_node.set_left(z_left);
+                y_node.set_color(z_color);
+                y_node.set_size(z_size - z_weight)
+                    .map_err(|()| corrupted(y_idx, "Sub-tree size out of range!"))?;
+            }
+            if let Some(n) = z_left.and_then(|idx| self.node_list[idx].as_mut()) {
+                n.set_parent(Some(y_idx));
+            }
+        }
+
+        self.augment_path(x_parent);
+        if y_color == Color::Black {
+            self.remove_fix_up(x_idx, x_parent)?;
+        }
+
+        let removed = match self.node_list[z_idx].take() {
+            Some(removed) => removed,
+            None => return Err(corrupted(z_idx, "Removed node went missing!")),
+        };
+        self.free_list.push(z_idx);
+        Ok(removed.data)
+    }
+
+    #[inline]
+    fn transplant(&mut self, u: usize, v: Option<usize>) -> Result<(), TListError> {
+        // Replaces the sub-tree rooted at u with the sub-tree rooted at v, per CLRS.
+        // v may be nil, in which case only u's parent is updated.
+        let u_p = match self.node_list[u].as_ref() {
+            Some(node) => node.parent(),
+            None => return Err(corrupted(u, "Invalid transplant choice u")),
         };
 
         match u_p {
             Some(up_idx) => {
-                self.node_list[up_idx]
-                    .as_mut()
-                    .map(|up_node| {
-                        if up_node.left == Some(u) {
-                            up_node.left = Some(v);
-                        } else {
-                            up_node.right = Some(v);
-                        }
-                    });
+                if let Some(up_node) = self.node_list[up_idx].as_mut() {
+                    if up_node.left() == Some(u) {
+                        up_node.set_left(v);
+                    } else {
+                        up_node.set_right(v);
+                    }
+                }
+            },
+            None => {
+                if let Some(v_idx) = v {
+                    self.root_idx = v_idx;
+                }
             },
-            None => self.root_idx = v,
         };
 
-        self.node_list[v]
-            .as_mut()
-            .map(|v_node| v_node.parent = u_p);
+        if let Some(v_node) = v.and_then(|v_idx| self.node_list[v_idx].as_mut()) {
+            v_node.set_parent(u_p);
+        }
+
+        Ok(())
     }
 
     /// Equivalent to remove() on the last element of the TList.
     pub fn pop(&mut self) -> Option<T> {
-        if self.len() == 0 {
+        if self.is_empty() {
             None
         } else {
             let loc = self.len() - 1;
@@ -367,48 +812,112 @@ impl<T> TList<T> where T: Sized {
 
     /// Returns an iterator over the values in the TList in index order, yielding immutable
     /// borrows. Mirrors Vec's iter() and Iter<T>.
-    pub fn iter(&self) -> Iter<T> {
+    pub fn iter(&self) -> Iter<'_, T, A> {
         Iter {
             tree: self,
             loc: 0
         }
     }
 
-    fn traverse<F>(&mut self, f: &F) where F: Fn(T) -> T {
+    /// Replaces every value in the TList, in index order, with the result of calling f on it.
+    /// This stands in for an IterMut, allowing systematic mutation of the whole list.
+    pub fn traverse<F>(&mut self, f: &F) where F: Fn(T) -> T {
         let len = self.len();
 
         for i in 0..len {
             let search_idx = self.search(i);
-            let new_entry = self.search(i)
-                .and_then(|idx| mem::replace(&mut self.node_list[idx], None))
+            let new_entry = search_idx
+                .and_then(|idx| self.node_list[idx].take())
                 .map(|mut n| {
                     n.data = {
                         f(n.data)
                     };
                     n
                 });
-            search_idx.map(|idx| mem::replace(&mut self.node_list[idx], new_entry));
+            if let Some(idx) = search_idx {
+                self.node_list[idx] = new_entry;
+                self.notify(Change::Replaced { index: i });
+            }
         }
     }
 
-    /// Consumes self and returns an iterator over the values in the TList in index order, yielding
-    /// owned values. Mirrors Vec's into_iter() and IntoIter<T>.
-    pub fn into_iter(self) -> IntoIter<T> {
-        let iter = IntoIter {
-            tree: self,
-            traversal_list: Vec::new(),
-        };
-        unimplemented!()
+    /// Checks the parent links, sub-tree sizes and Red-Black coloring of the whole tree,
+    /// panicking with a description of the first violation found. Only compiled for tests
+    /// and fuzzing builds.
+    #[cfg(any(test, fuzzing))]
+    pub fn assert_invariants(&self) {
+        let size = self.assert_weighted_invariants(&|_| 1);
+        assert_eq!(self.len(), size, "root size doesn't match len()");
+    }
+
+    #[cfg(any(test, fuzzing))]
+    fn assert_weighted_invariants(&self, weight: &dyn Fn(&T) -> usize) -> usize {
+        // Checks the whole tree as assert_invariants(), with each node counting weight() of
+        // its value towards the sizes, and returns the total weight.
+        assert_eq!(self.node_list.len(), self.len() + self.free_list.len());
+        if self.is_empty() {
+            return 0;
+        }
+
+        assert_eq!(None, self.get_parent_idx(self.root_idx), "root has a parent");
+        assert_eq!(Color::Black, self.color_of(Some(self.root_idx)), "root is red");
+        self.check_sub_tree(self.root_idx, weight).0
+    }
+
+    #[cfg(any(test, fuzzing))]
+    fn check_sub_tree(&self, index: usize, weight: &dyn Fn(&T) -> usize) -> (usize, usize) {
+        // Returns the (size, black height) of the sub-tree rooted at index
+        let node = self.node_list[index].as_ref().expect("Link to an empty slot!");
+        let mut size = weight(&node.data);
+        let mut black_heights = [1, 1];
+
+        for (i, &child) in [node.left(), node.right()].iter().enumerate() {
+            if let Some(c_idx) = child {
+                assert_eq!(Some(index), self.get_parent_idx(c_idx), "bad parent link at {}", c_idx);
+                if node.color() == Color::Red {
+                    assert_eq!(Color::Black, self.color_of(child), "red node {} has a red child", index);
+                }
+                let (c_size, c_black) = self.check_sub_tree(c_idx, weight);
+                size += c_size;
+                black_heights[i] = c_black;
+            }
+        }
+
+        assert_eq!(node.size(), size, "wrong size at {}", index);
+        assert_eq!(black_heights[0], black_heights[1], "unequal black heights under {}", index);
+        let own_black = if node.color() == Color::Black { 1 } else { 0 };
+        (size, black_heights[0] + own_black)
     }
 
     // Private auxillary functions for implementing Red-Black semantics
 
+    fn in_order(&self) -> Vec<usize> {
+        // Returns the slots of every node, in index order
+        let mut order = Vec::with_capacity(self.len());
+        let mut stack = Vec::with_capacity(DEFAULT_STACK_DEPTH);
+        let mut current = if self.is_empty() { None } else { Some(self.root_idx) };
+
+        while current.is_some() || !stack.is_empty() {
+            while let Some(idx) = current {
+                stack.push(idx);
+                current = self.get_child_idx(idx, Dir::Left);
+            }
+
+            if let Some(idx) = stack.pop() {
+                order.push(idx);
+                current = self.get_child_idx(idx, Dir::Right);
+            }
+        }
+
+        order
+    }
+
     #[inline]
     fn get_child_idx(&self, index: usize, dir: Dir) -> Option<usize> {
         match self.node_list[index] {
             Some(ref node) => match dir {
-                Dir::Left => node.left,
-                Dir::Right => node.right,
+                Dir::Left => node.left(),
+                Dir::Right => node.right(),
             },
             None => None,
         }
@@ -417,40 +926,222 @@ impl<T> TList<T> where T: Sized {
     #[inline]
     fn get_grandparent_idx(&self, index: usize) -> Option<usize> {
         self.node_list[index].as_ref()
-            .and_then(|n| n.parent)
+            .and_then(|n| n.parent())
             .and_then(|pidx| self.node_list[pidx].as_ref())
-            .and_then(|n| n.parent)
+            .and_then(|n| n.parent())
     }
 
     #[inline]
     fn get_parent_idx(&self, index: usize) -> Option<usize> {
         self.node_list[index]
             .as_ref()
-            .and_then(|n| n.parent)
+            .and_then(|n| n.parent())
     }
 
     #[inline]
-    fn get_child_size(&self, index: usize, dir: Dir) -> usize {
+    fn get_child_size(&self, index: usize, dir: Dir) -> Result<usize, TListError> {
         let child_idx = self.get_child_idx(index, dir);
         
         match child_idx {
             Some(c_idx) => {
                 match self.node_list[c_idx] {
-                    Some(ref node) => node.size,
-                    None => {
-                        if cfg!(test) {
-                            panic!("No node at listed child!")
-                        }
-                        return 0;
-                    },
+                    Some(ref node) => Ok(node.size()),
+                    None => Err(corrupted(c_idx, "No node at listed child!")),
                 }
             },
-            None => 0
+            None => Ok(0)
+        }
+    }
+
+    #[inline]
+    fn own_weight(&self, index: usize) -> Result<usize, TListError> {
+        // A node's sub-tree size counts its own weight plus the sizes of its children. Every
+        // node of a TList weighs 1, but a ChunkedTList weighs each by its element count.
+        let size = match self.node_list[index] {
+            Some(ref node) => node.size(),
+            None => return Err(corrupted(index, "No node to weigh!")),
+        };
+        Ok(size - self.get_child_size(index, Dir::Left)? - self.get_child_size(index, Dir::Right)?)
+    }
+
+    // Weighted node operations, used by ChunkedTList to index the elements of its chunks
+
+    #[inline]
+    fn total_weight(&self) -> usize {
+        match self.node_list.get(self.root_idx) {
+            Some(Some(root)) if !self.is_empty() => root.size(),
+            _ => 0,
+        }
+    }
+
+    fn locate(&self, mut index: usize) -> Result<(usize, usize), TListError> {
+        // Finds the node containing the given weighted index, returning its slot and the
+        // offset of index within the node's own weight.
+        let total = self.total_weight();
+        if index >= total {
+            return Err(TListError::IndexOutOfBounds { index, len: total });
+        }
+
+        let mut search_idx = self.root_idx;
+        loop {
+            let rank = self.get_child_size(search_idx, Dir::Left)?;
+            if index < rank {
+                search_idx = match self.get_child_idx(search_idx, Dir::Left) {
+                    Some(idx) => idx,
+                    None => return Err(corrupted(search_idx, "No left child!")),
+                };
+                continue;
+            }
+
+            let weight = self.own_weight(search_idx)?;
+            if index < rank + weight {
+                return Ok((search_idx, index - rank));
+            }
+
+            index -= rank + weight;
+            search_idx = match self.get_child_idx(search_idx, Dir::Right) {
+                Some(idx) => idx,
+                None => return Err(corrupted(search_idx, "No right child!")),
+            };
+        }
+    }
+
+    fn insert_after(&mut self, prev: Option<usize>, elem: T, weight: usize) -> Result<usize, TListError> {
+        // Adds a node of the given weight directly after the node at slot prev in index
+        // order, or at the very front if prev is None, returning the new node's slot.
+        if weight > MAX_LEN - self.total_weight() {
+            return Err(TListError::CapacityOverflow);
+        }
+        let insert_idx = self.add_leaf(elem)?;
+        if let Some(n) = self.node_list[insert_idx].as_mut() {
+            n.set_size(weight).map_err(|()| corrupted(insert_idx, "Sub-tree size out of range!"))?;
+        }
+        self.augment_path(Some(insert_idx));
+
+        if self.len() == 1 {
+            self.root_idx = insert_idx;
+            self.insert_fix_up(insert_idx)?;
+            return Ok(insert_idx);
+        }
+
+        // The new node goes in the first empty spot after prev: its right child if it has
+        // none, or else the left child of its successor, which can't have one.
+        let (parent_idx, dir) = match prev {
+            None => (self.minimum(self.root_idx), Dir::Left),
+            Some(p_idx) => match self.get_child_idx(p_idx, Dir::Right) {
+                None => (p_idx, Dir::Right),
+                Some(r_idx) => (self.minimum(r_idx), Dir::Left),
+            },
+        };
+
+        self.push_path(parent_idx);
+        match self.node_list[parent_idx].as_mut() {
+            Some(n) => match dir {
+                Dir::Left => n.set_left(Some(insert_idx)),
+                Dir::Right => n.set_right(Some(insert_idx)),
+            },
+            None => return Err(corrupted(parent_idx, "No node to insert after!")),
+        }
+        if let Some(n) = self.node_list[insert_idx].as_mut() {
+            n.set_parent(Some(parent_idx));
+        }
+        self.adjust_weight(parent_idx, 0, weight)?;
+
+        self.insert_fix_up(insert_idx)?;
+        Ok(insert_idx)
+    }
+
+    fn adjust_weight(&mut self, index: usize, old: usize, new: usize) -> Result<(), TListError> {
+        // Changes the size of the node at index and of all its ancestors from counting a
+        // weight of old to one of new.
+        if new > old && new - old > MAX_LEN - self.total_weight() {
+            return Err(TListError::CapacityOverflow);
+        }
+        let mut walk_idx = Some(index);
+        while let Some(idx) = walk_idx {
+            walk_idx = match self.node_list[idx].as_mut() {
+                Some(node) => {
+                    node.set_size(node.size() + new - old)
+                        .map_err(|()| corrupted(idx, "Sub-tree size out of range!"))?;
+                    node.parent()
+                },
+                None => return Err(corrupted(idx, "No node on weight path!")),
+            };
+        }
+        self.augment_path(Some(index));
+        Ok(())
+    }
+
+    fn augment_path(&mut self, index: Option<usize>) {
+        // Recomputes the augmented summaries of the node at index and of all its ancestors,
+        // bottom up, if the TList keeps any.
+        let Some(augment) = self.augment else {
+            return;
+        };
+        let mut walk_idx = index;
+        while let Some(idx) = walk_idx {
+            augment(self, idx);
+            walk_idx = self.get_parent_idx(idx);
+        }
+    }
+
+    fn push_path(&mut self, index: usize) {
+        // Pushes any pending updates down the path from the root to the node at index, top
+        // down, so that every node on it and their children are current, if the TList keeps
+        // any.
+        let Some(push_down) = self.push_down else {
+            return;
+        };
+        let mut path = Vec::new();
+        let mut walk_idx = Some(index);
+        while let Some(idx) = walk_idx {
+            path.push(idx);
+            walk_idx = self.get_parent_idx(idx);
+        }
+        for idx in path.into_iter().rev() {
+            push_down(self, idx);
+        }
+    }
+
+    fn push_rotation(&mut self, h_idx: usize, dir: Dir) {
+        // Pushes any pending updates out of the node at h_idx and its child in dir, the two
+        // nodes a rotation swaps, so that none are left pending over a changed sub-tree.
+        if let Some(push_down) = self.push_down {
+            push_down(self, h_idx);
+            if let Some(child_idx) = self.get_child_idx(h_idx, dir) {
+                push_down(self, child_idx);
+            }
+        }
+    }
+
+    fn neighbor(&self, index: usize, dir: Dir) -> Option<usize> {
+        // Finds the next node in index order in the given direction: the nearest node of
+        // that sub-tree if there is one, or else the first ancestor we reach from the
+        // other side.
+        let back = match dir {
+            Dir::Left => Dir::Right,
+            Dir::Right => Dir::Left,
+        };
+
+        if let Some(mut idx) = self.get_child_idx(index, dir) {
+            while let Some(next_idx) = self.get_child_idx(idx, back) {
+                idx = next_idx;
+            }
+            return Some(idx);
         }
+
+        let mut idx = index;
+        while let Some(p_idx) = self.get_parent_idx(idx) {
+            if self.get_child_idx(p_idx, back) == Some(idx) {
+                return Some(p_idx);
+            }
+            idx = p_idx;
+        }
+        None
     }
 
     #[inline]
-    fn insert_fix_up(&mut self, index: usize) {
+    fn insert_fix_up(&mut self, index: usize) -> Result<(), TListError> {
         // Repairs invariants damaged during an insertion event
         // This should only be called after inserting a node, called
         // on the index of the newly inserted node
@@ -461,14 +1152,10 @@ impl<T> TList<T> where T: Sized {
         // we move to the index of another red node (or we return).
 
         let mut z_idx = index;
-        loop {
-            let z_p = match self.get_parent_idx(z_idx) {
-                Some(idx) => idx,
-                None => break, // no parent => we're at the root
-            };
-
+        // no parent => we're at the root
+        while let Some(z_p) = self.get_parent_idx(z_idx) {
             let red_parent = match self.node_list[z_p] {
-                Some(ref node) => node.color == Color::Red,
+                Some(ref node) => node.color() == Color::Red,
                 None => false,
             };
 
@@ -477,42 +1164,37 @@ impl<T> TList<T> where T: Sized {
             }
         
             // Root is always black by construction, so a grandparent of z exists
-            let gp_idx = self.get_grandparent_idx(z_idx).expect("Grandparent should exist!");
+            let gp_idx = match self.get_grandparent_idx(z_idx) {
+                Some(idx) => idx,
+                None => return Err(corrupted(z_idx, "Grandparent should exist!")),
+            };
 
             // Get the uncle of z, y as an index
-            let y_idx = {
-                let gp_node = self.node_list[gp_idx]
-                    .as_ref()
-                    .unwrap();
-                if gp_node.left == Some(z_p) {
-                    gp_node.right
-                } else {
-                    gp_node.left
-                }
+            let y_idx = match self.node_list[gp_idx] {
+                Some(ref gp_node) => {
+                    if gp_node.left() == Some(z_p) {
+                        gp_node.right()
+                    } else {
+                        gp_node.left()
+                    }
+                },
+                None => return Err(corrupted(gp_idx, "No node at grandparent!")),
             };
 
             // None is "black", as per CLRS: nil nodes are always black.
             let y_color = y_idx
                 .and_then(|idx| self.node_list[idx].as_ref())
-                .map(|n| n.color);
+                .map(|n| n.color());
 
             if Some(Color::Red) == y_color {
-                self.node_list[y_idx.unwrap()]
-                    .as_mut()
-                    .map(|n| n.color = Color::Black);
-                
-                self.node_list[z_p]
-                    .as_mut()
-                    .map(|n| n.color = Color::Black);
-
-                self.node_list[gp_idx]
-                    .as_mut()
-                    .map(|n| n.color = Color::Red);
+                self.set_color(y_idx, Color::Black);
+                self.set_color(Some(z_p), Color::Black);
+                self.set_color(Some(gp_idx), Color::Red);
 
                 z_idx = gp_idx;
             } else {
                 let z_p_dir = {
-                    if Some(z_p) == self.node_list[gp_idx].as_ref().and_then(|n| n.left) {
+                    if Some(z_p) == self.node_list[gp_idx].as_ref().and_then(|n| n.left()) {
                         Dir::Left
                     } else {
                         Dir::Right
@@ -520,7 +1202,7 @@ impl<T> TList<T> where T: Sized {
                 };
 
                 let z_dir = {
-                    if Some(z_idx) == self.node_list[z_p].as_ref().and_then(|n| n.left) {
+                    if Some(z_idx) == self.node_list[z_p].as_ref().and_then(|n| n.left()) {
                         Dir::Left
                     } else {
                         Dir::Right
@@ -531,65 +1213,170 @@ impl<T> TList<T> where T: Sized {
                     // CLRS case 2 fall through to case 3 
                     z_idx = z_p;
                     match z_p_dir {
-                        Dir::Left => self.left_rotate(z_idx),
-                        Dir::Right => self.right_rotate(z_idx),
+                        Dir::Left => self.left_rotate(z_idx)?,
+                        Dir::Right => self.right_rotate(z_idx)?,
                     };
                 } 
                 // CLRS only case 3
-                self.get_parent_idx(z_idx)
-                    .and_then(|idx| self.node_list[idx].as_mut())
-                    .map(|n| n.color = Color::Black);
+                let new_z_p = self.get_parent_idx(z_idx);
+                self.set_color(new_z_p, Color::Black);
 
                 let n_gp = match self.get_grandparent_idx(z_idx) {
                     Some(idx) => idx,
-                    None => {
-                        if cfg!(test) {
-                            panic!("Panic in Case 3!")
-                        }
-                        break;
-                    }
+                    None => return Err(corrupted(z_idx, "Panic in Case 3!")),
                 };
-                self.node_list[n_gp].as_mut()
-                    .map(|n| n.color = Color::Red);
+                self.set_color(Some(n_gp), Color::Red);
 
                 match z_p_dir {
-                    Dir::Left => self.right_rotate(gp_idx),
-                    Dir::Right => self.left_rotate(gp_idx),
+                    Dir::Left => self.right_rotate(gp_idx)?,
+                    Dir::Right => self.left_rotate(gp_idx)?,
                 };
             }
         }
 
         // Root node must exist since we just inserted a value, so at least one node exists
-        self.node_list[self.root_idx].as_mut().unwrap().color = Color::Black;
-            
+        match self.node_list[self.root_idx].as_mut() {
+            Some(root) => root.set_color(Color::Black),
+            None => return Err(corrupted(self.root_idx, "No node at root!")),
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn remove_fix_up(&mut self, index: Option<usize>, parent: Option<usize>) -> Result<(), TListError> {
+        // Repairs invariants damaged during a removal event, following CLRS.
+        // x is the node which took the place of the removed node and carries
+        // the 'extra black'. Since x may be nil, its parent is tracked explicitly.
+        let mut x_idx = index;
+        let mut x_p = parent;
+
+        while x_idx != Some(self.root_idx) && self.color_of(x_idx) == Color::Black {
+            let p_idx = match x_p {
+                Some(idx) => idx,
+                None => break,
+            };
+
+            let x_dir = if x_idx == self.get_child_idx(p_idx, Dir::Left) {
+                Dir::Left
+            } else {
+                Dir::Right
+            };
+            let w_dir = match x_dir {
+                Dir::Left => Dir::Right,
+                Dir::Right => Dir::Left,
+            };
+
+            // x is doubly black, so its sibling w must exist
+            let mut w_idx = match self.get_child_idx(p_idx, w_dir) {
+                Some(idx) => idx,
+                None => return Err(corrupted(p_idx, "No sibling for doubly black node!")),
+            };
+
+            if self.color_of(Some(w_idx)) == Color::Red {
+                // CLRS case 1
+                self.set_color(Some(w_idx), Color::Black);
+                self.set_color(Some(p_idx), Color::Red);
+                self.rotate_toward(p_idx, x_dir)?;
+                w_idx = match self.get_child_idx(p_idx, w_dir) {
+                    Some(idx) => idx,
+                    None => return Err(corrupted(p_idx, "No sibling after case 1!")),
+                };
+            }
+
+            let w_near = self.get_child_idx(w_idx, x_dir);
+            let w_far = self.get_child_idx(w_idx, w_dir);
+            if self.color_of(w_near) == Color::Black && self.color_of(w_far) == Color::Black {
+                // CLRS case 2
+                self.set_color(Some(w_idx), Color::Red);
+                x_idx = Some(p_idx);
+                x_p = self.get_parent_idx(p_idx);
+            } else {
+                if self.color_of(w_far) == Color::Black {
+                    // CLRS case 3 fall through to case 4
+                    self.set_color(w_near, Color::Black);
+                    self.set_color(Some(w_idx), Color::Red);
+                    self.rotate_toward(w_idx, w_dir)?;
+                    w_idx = match self.get_child_idx(p_idx, w_dir) {
+                        Some(idx) => idx,
+                        None => return Err(corrupted(p_idx, "No sibling after case 3!")),
+                    };
+                }
+
+                // CLRS case 4
+                let p_color = self.color_of(Some(p_idx));
+                self.set_color(Some(w_idx), p_color);
+                self.set_color(Some(p_idx), Color::Black);
+                let w_far = self.get_child_idx(w_idx, w_dir);
+                self.set_color(w_far, Color::Black);
+                self.rotate_toward(p_idx, x_dir)?;
+                x_idx = Some(self.root_idx);
+                x_p = None;
+            }
+        }
+
+        self.set_color(x_idx, Color::Black);
+        Ok(())
+    }
+
+    #[inline]
+    fn rotate_toward(&mut self, h_idx: usize, dir: Dir) -> Result<(), TListError> {
+        // Rotates the node at h_idx down in the given direction
+        match dir {
+            Dir::Left => self.left_rotate(h_idx),
+            Dir::Right => self.right_rotate(h_idx),
+        }
+    }
+
+    #[inline]
+    fn color_of(&self, index: Option<usize>) -> Color {
+        // None is "black", as per CLRS: nil nodes are always black.
+        index
+            .and_then(|idx| self.node_list[idx].as_ref())
+            .map_or(Color::Black, |n| n.color())
+    }
+
+    #[inline]
+    fn set_color(&mut self, index: Option<usize>, color: Color) {
+        if let Some(n) = index.and_then(|idx| self.node_list[idx].as_mut()) {
+            n.set_color(color);
+        }
+    }
+
+    #[inline]
+    fn minimum(&self, mut index: usize) -> usize {
+        // Finds the left-most node of the sub-tree rooted at index
+        while let Some(left_idx) = self.get_child_idx(index, Dir::Left) {
+            index = left_idx;
+        }
+        index
     }
 
     #[inline]
-    fn left_rotate(&mut self, h_idx: usize) {
+    fn left_rotate(&mut self, h_idx: usize) -> Result<(), TListError> {
         // Performs a left tree rotation of the node at h_idx
+        self.push_rotation(h_idx, Dir::Right);
 
         // Fetch the current parent node and pull it out as an owned object
         // in the current scope. Replace it with a None so that the underlying
         // vector doesn't reshuffle.
-        let h_node_opt = mem::replace(&mut self.node_list[h_idx], None);
+        let h_node_opt = self.node_list[h_idx].take();
 
         // left_rotate should not be called in a situation where either the h node
         // or right child don't exist, but left_rotate can't guarentee that directly,
-        // so we go through the option matching, and replace the h_node and return if
-        // something is missing. In test, we panic since this should violate the invariants.
+        // so we go through the option matching, and replace the h_node and return an
+        // error if something is missing. In test, we panic since this should violate
+        // the invariants.
         let (mut h_node, y_node_opt, y_idx) = match h_node_opt {
             Some(h_node) => {
-                match h_node.right {
+                match h_node.right() {
                     Some(y_idx) => {
-                        let y_node = mem::replace(&mut self.node_list[y_idx], None);
+                        let y_node = self.node_list[y_idx].take();
                         (h_node, y_node, y_idx)
                     },
                     None => {
-                        if cfg!(test) {
-                            panic!("Panic in left rotation! No right child of {}", h_idx);
-                        }
-                        mem::swap(&mut self.node_list[h_idx], &mut Some(h_node));
-                        return;
+                        self.node_list[h_idx] = Some(h_node);
+                        return Err(corrupted(h_idx, "Panic in left rotation! No right child"));
                     },
                 }
             },
@@ -597,177 +1384,161 @@ impl<T> TList<T> where T: Sized {
                 // If h_node is None, then we don't need to replace it, since we put
                 // a None in it's place above.
                 // Also, this really shouldn't happen.
-                if cfg!(test) {
-                    panic!();
-                }
-                return;
+                return Err(corrupted(h_idx, "No node to rotate left!"));
             },
         };
 
         let mut y_node = match y_node_opt {
             Some(y_node) => y_node,
             None => {
-                if cfg!(test) {
-                    panic!();
-                }
-                mem::replace(&mut self.node_list[h_idx], Some(h_node));
-                return;
+                self.node_list[h_idx] = Some(h_node);
+                return Err(corrupted(y_idx, "No node at right child!"));
             }
         };
 
-        h_node.right = y_node.left;
-        h_node.right.and_then(|n_idx| self.node_list[n_idx].as_mut())
-            .map(|mut n| n.parent = Some(h_idx));
-        y_node.parent = h_node.parent;
+        let (h_size, y_size) = (h_node.size(), y_node.size());
+        h_node.set_right(y_node.left());
+        if let Some(n) = h_node.right().and_then(|n_idx| self.node_list[n_idx].as_mut()) {
+            n.set_parent(Some(h_idx));
+        }
+        y_node.set_parent(h_node.parent());
 
-        match h_node.parent {
+        match h_node.parent() {
             Some(p_idx) => {
-                self.node_list[p_idx]
-                    .as_mut()
-                    .map(|p_node| {
-                        if p_node.left == Some(h_idx) {
-                            p_node.left = Some(y_idx);
-                        } else {
-                            p_node.right = Some(y_idx);
-                        }
-                    });
+                if let Some(p_node) = self.node_list[p_idx].as_mut() {
+                    if p_node.left() == Some(h_idx) {
+                        p_node.set_left(Some(y_idx));
+                    } else {
+                        p_node.set_right(Some(y_idx));
+                    }
+                }
             },
             None => self.root_idx = y_idx,
         }
 
-        h_node.parent = Some(y_idx);
-        y_node.left = Some(h_idx);
+        h_node.set_parent(Some(y_idx));
+        y_node.set_left(Some(h_idx));
         
         // Re-insert the nodes into their positions in the node list;
-        mem::replace(&mut self.node_list[h_idx], Some(h_node));
-        mem::replace(&mut self.node_list[y_idx], Some(y_node));
+        self.node_list[h_idx] = Some(h_node);
+        self.node_list[y_idx] = Some(y_node);
 
         // reset size calculations
-        // this has to be done after re-inserting to ensure child links work properly
-        {
-            let h_size = self.get_child_size(h_idx, Dir::Left) + self.get_child_size(h_idx, Dir::Right) + 1;
-            self.node_list[h_idx]
-                .as_mut()
-                .unwrap()
-                .size = h_size;
+        // this has to be done after re-inserting to ensure child links work properly.
+        // y takes over h's whole sub-tree, while h swaps y for y's old child. Working
+        // from the old sizes rather than summing children keeps any node weights intact.
+        let moved_size = self.get_child_size(h_idx, Dir::Right)?;
+        if let Some(n) = self.node_list[h_idx].as_mut() {
+            n.set_size(h_size - y_size + moved_size)
+                .map_err(|()| corrupted(h_idx, "Sub-tree size out of range!"))?;
         }
-
-        {
-            let y_size = self.get_child_size(y_idx, Dir::Left) + self.get_child_size(y_idx, Dir::Right) + 1;
-            self.node_list[y_idx]
-                .as_mut()
-                .unwrap()
-                .size = y_size;
+        if let Some(n) = self.node_list[y_idx].as_mut() {
+            n.set_size(h_size).map_err(|()| corrupted(y_idx, "Sub-tree size out of range!"))?;
+        }
+        if let Some(augment) = self.augment {
+            augment(self, h_idx);
+            augment(self, y_idx);
         }
+
+        Ok(())
     }
 
     #[inline]
-    fn right_rotate(&mut self, h_idx: usize) {
+    fn right_rotate(&mut self, h_idx: usize) -> Result<(), TListError> {
         // follows the same logic as left_rotate, properly mirror reversed
-        let h_node_opt = mem::replace(&mut self.node_list[h_idx], None);
+        self.push_rotation(h_idx, Dir::Left);
+        let h_node_opt = self.node_list[h_idx].take();
 
         let (mut h_node, x_node_opt, x_idx) = match h_node_opt {
             Some(h_node) => {
-                match h_node.left {
+                match h_node.left() {
                     Some(x_idx) => {
-                        let x_node = mem::replace(&mut self.node_list[x_idx], None);
+                        let x_node = self.node_list[x_idx].take();
                         (h_node, x_node, x_idx)
                     },
                     None => {
-                        if cfg!(test) {
-                            panic!();
-                        }
-                        mem::swap(&mut self.node_list[h_idx], &mut Some(h_node));
-                        return;
+                        self.node_list[h_idx] = Some(h_node);
+                        return Err(corrupted(h_idx, "Panic in right rotation! No left child"));
                     },
                 }
             },
-            None => {
-                if cfg!(test) {
-                    panic!();
-                }
-                return;
-            },
+            None => return Err(corrupted(h_idx, "No node to rotate right!")),
         };
 
         let mut x_node = match x_node_opt {
             Some(x_node) => x_node,
             None => {
-                if cfg!(test) {
-                    panic!();
-                }
-                mem::replace(&mut self.node_list[h_idx], Some(h_node));
-                return;
+                self.node_list[h_idx] = Some(h_node);
+                return Err(corrupted(x_idx, "No node at left child!"));
             }
         };
 
-        h_node.left = x_node.right;
-        h_node.left.and_then(|n_idx| self.node_list[n_idx].as_mut())
-            .map(|mut n| n.parent = Some(h_idx));
-        x_node.parent = h_node.parent;
+        let (h_size, x_size) = (h_node.size(), x_node.size());
+        h_node.set_left(x_node.right());
+        if let Some(n) = h_node.left().and_then(|n_idx| self.node_list[n_idx].as_mut()) {
+            n.set_parent(Some(h_idx));
+        }
+        x_node.set_parent(h_node.parent());
 
-        match h_node.parent {
+        match h_node.parent() {
             Some(p_idx) => {
-                self.node_list[p_idx]
-                    .as_mut()
-                    .map(|p_node| {
-                        if p_node.left == Some(h_idx) {
-                            p_node.left = Some(x_idx);
-                        } else {
-                            p_node.right = Some(x_idx);
-                        }
-                    });
+                if let Some(p_node) = self.node_list[p_idx].as_mut() {
+                    if p_node.left() == Some(h_idx) {
+                        p_node.set_left(Some(x_idx));
+                    } else {
+                        p_node.set_right(Some(x_idx));
+                    }
+                }
             },
             None => self.root_idx = x_idx,
         }
 
-        h_node.parent = Some(x_idx);
-        x_node.right = Some(h_idx);
+        h_node.set_parent(Some(x_idx));
+        x_node.set_right(Some(h_idx));
        
-        mem::replace(&mut self.node_list[h_idx], Some(h_node));
-        mem::replace(&mut self.node_list[x_idx], Some(x_node));
+        self.node_list[h_idx] = Some(h_node);
+        self.node_list[x_idx] = Some(x_node);
 
-        {
-            let h_size = self.get_child_size(h_idx, Dir::Left) + self.get_child_size(h_idx, Dir::Right) + 1;
-            self.node_list[h_idx]
-                .as_mut()
-                .unwrap()
-                .size = h_size;
+        let moved_size = self.get_child_size(h_idx, Dir::Left)?;
+        if let Some(n) = self.node_list[h_idx].as_mut() {
+            n.set_size(h_size - x_size + moved_size)
+                .map_err(|()| corrupted(h_idx, "Sub-tree size out of range!"))?;
         }
-
-        {
-            let x_size = self.get_child_size(x_idx, Dir::Left) + self.get_child_size(x_idx, Dir::Right) + 1;
-            self.node_list[x_idx]
-                .as_mut()
-                .unwrap()
-                .size = x_size;
+        if let Some(n) = self.node_list[x_idx].as_mut() {
+            n.set_size(h_size).map_err(|()| corrupted(x_idx, "Sub-tree size out of range!"))?;
         }
+        if let Some(augment) = self.augment {
+            augment(self, h_idx);
+            augment(self, x_idx);
+        }
+
+        Ok(())
     }
 
     #[inline]
-    fn search(&self, mut index: usize) -> Option<usize> {
-        if index > self.len() {
-            return None;
+    fn search(&self, index: usize) -> Option<usize> {
+        self.try_search(index).ok()
+    }
+
+    #[inline]
+    fn try_search(&self, mut index: usize) -> Result<usize, TListError> {
+        if index >= self.len() {
+            return Err(TListError::IndexOutOfBounds { index, len: self.len() });
         }
 
         let mut search_idx = self.root_idx;
 
         loop {
-            let rank = self.get_child_size(search_idx, Dir::Left);
+            let rank = self.get_child_size(search_idx, Dir::Left)?;
             
             if rank == index {
-                return Some(search_idx);
+                return Ok(search_idx);
             }
 
             search_idx = if index < rank {
                 match self.get_child_idx(search_idx, Dir::Left) {
                     Some(idx) => idx,
-                    None => {
-                        if cfg!(test) {
-                            panic!("No left child!")
-                        }
-                        return None;
-                    },
+                    None => return Err(corrupted(search_idx, "No left child!")),
                 }
             } else {
                 match self.get_child_idx(search_idx, Dir::Right) {
@@ -775,12 +1546,7 @@ impl<T> TList<T> where T: Sized {
                         index -= rank + 1;
                         idx
                     }
-                    None => {
-                        if cfg!(test) {
-                            panic!("No right child from {} getting index {}!", search_idx, index)
-                        }
-                        return None;
-                    },
+                    None => return Err(corrupted(search_idx, "No right child!")),
                 }
             };
         }
@@ -834,26 +1600,40 @@ impl<T> TList<T> where T: Sized {
         }
 
     #[inline]
-    fn realloc(&mut self) -> usize {
-        let current_len = self.len();
-        let additional = 1 + (current_len >> 1);
-        self.node_list.reserve_exact(additional);
-        for i in 0..additional {
-            self.node_list.push(None);
-            self.free_list.push(i + current_len);
+    fn check_max_len(&self, additional: usize) -> Result<(), TListError> {
+        match self.len().checked_add(additional) {
+            Some(len) if len <= MAX_LEN => Ok(()),
+            _ => Err(TListError::CapacityOverflow),
         }
-        self.free_list.pop().expect("Memory allocation failure.")
     }
 
     #[inline]
-    fn add_leaf(&mut self, elem: T) -> usize {
+    fn add_leaf(&mut self, elem: T) -> Result<usize, TListError> {
         let leaf = Node::new_leaf(elem, Color::Red);
-        let insert_idx = match self.free_list.pop() {
-            Some(insert_idx) => insert_idx,
-            None => self.realloc(),
-        };
-        mem::replace(&mut self.node_list[insert_idx], Some(leaf));
-        insert_idx
+
+        // Reuse a hole left by a removal if there is one, otherwise raise the
+        // high-water mark, growing the node_list as Vec::push would if needed.
+        match self.free_list.pop() {
+            Some(insert_idx) => {
+                self.node_list[insert_idx] = Some(leaf);
+                Ok(insert_idx)
+            },
+            None => {
+                // Slot indexes must stay addressable by the node links
+                if self.node_list.len() >= MAX_LEN {
+                    return Err(TListError::CapacityOverflow);
+                }
+                self.node_list.try_reserve(1)?;
+                self.node_list.push(Some(leaf));
+                Ok(self.node_list.len() - 1)
+            },
+        }
+    }
+}
+
+impl<T> Default for TList<T> where T: Sized {
+    fn default() -> TList<T> {
+        Self::new()
     }
 }
 
@@ -863,12 +1643,12 @@ impl<T> TList<T> where T: Sized {
 /// Note: The current implementation is implemented in terms of get() for testing purposes rather than 
 /// properly optimized, and so runs in O(N log N) time. The final version will be updated to run in
 /// O(N) time, asymptotically the same as iterating over a Vec.
-pub struct Iter<'a, T: 'a> {
-    tree: &'a TList<T>,
+pub struct Iter<'a, T: 'a, A: Allocator + Clone + 'a = Global> {
+    tree: &'a TList<T, A>,
     loc: usize,
 }
 
-impl<'a, T> Iterator for Iter<'a, T> where T: 'a {
+impl<'a, T, A> Iterator for Iter<'a, T, A> where T: 'a, A: Allocator + Clone {
     type Item = &'a T;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -892,12 +1672,7 @@ impl<'a, T> Iterator for Iter<'a, T> where T: 'a {
     }
 }
 
-impl<'a, T> ExactSizeIterator for Iter<'a, T> where T: 'a {}
-
-/// An iterator over a TList that consumes the list and yields owned values, equivalent to an
-/// IntoIter<T> over a Vec<T>.
-///
-/// Note: IntoIter<T>'s next() is currently unimplemented!() and so unusable.
+impl<'a, T, A> ExactSizeIterator for Iter<'a, T, A> where T: 'a, A: Allocator + Clone {}
 
 // The IntoIter will destroy the RB invariants as it consumes the
 // tree. It pre-calculates the order of node traversal on construction
@@ -905,29 +1680,72 @@ impl<'a, T> ExactSizeIterator for Iter<'a, T> where T: 'a {}
 // slots similar to a deletion process, but without fixing up the tree
 // since the affine type-system ensures the tree is inacessible to outside
 // code and is dropped when the IntoIter goes out of scope.
-pub struct IntoIter<T> {
-    tree: TList<T>,
+
+/// An iterator over a TList that consumes the list and yields owned values, equivalent to an
+/// IntoIter<T> over a Vec<T>.
+pub struct IntoIter<T, A: Allocator + Clone = Global> {
+    tree: TList<T, A>,
     traversal_list: Vec<usize>,
 }
 
-impl<T> Iterator for IntoIter<T> {
+impl<T, A> IntoIterator for TList<T, A> where A: Allocator + Clone {
+    type Item = T;
+    type IntoIter = IntoIter<T, A>;
+
+    /// Consumes self and returns an iterator over the values in the TList in index order, yielding
+    /// owned values. Mirrors Vec's into_iter() and IntoIter<T>.
+    fn into_iter(self) -> IntoIter<T, A> {
+        // The traversal list is stored in reverse index order so that next() can pop from it.
+        let mut traversal_list = self.in_order();
+        traversal_list.reverse();
+
+        IntoIter {
+            tree: self,
+            traversal_list,
+        }
+    }
+}
+
+impl<'a, T, A> IntoIterator for &'a TList<T, A> where A: Allocator + Clone {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, A>;
+
+    fn into_iter(self) -> Iter<'a, T, A> {
+        self.iter()
+    }
+}
+
+impl<T, A> Iterator for IntoIter<T, A> where A: Allocator + Clone {
     type Item = T;
 
     fn next(&mut self) -> Option<Self::Item> {
-        unimplemented!()
+        self.traversal_list
+            .pop()
+            .and_then(|idx| self.tree.node_list[idx].take())
+            .map(|n| n.data)
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let exact = self.tree.len() - self.traversal_list.len();
+        let exact = self.traversal_list.len();
         (exact, Some(exact))
     }
 }
 
+impl<T, A> ExactSizeIterator for IntoIter<T, A> where A: Allocator + Clone {}
+
 #[cfg(test)]
+#[allow(clippy::vec_init_then_push, clippy::needless_range_loop)]
 mod tests {
     extern crate rand;
 
-    use super::{TList, Node, NodeLoc, Color, DEFAULT_STACK_DEPTH};
+    use std::alloc::Layout;
+    use std::cell::Cell;
+    use std::ptr::NonNull;
+    use std::rc::Rc;
+
+    use super::{TList, TListError, Node, NodeLoc, Color, DEFAULT_STACK_DEPTH, MAX_LEN};
+    use super::{Allocator, ArenaVec, Global, ObserverSlot};
+    use super::allocator_api2::alloc::AllocError;
 
     use self::rand::Rng;
 
@@ -937,21 +1755,21 @@ mod tests {
         queue.push((tree.root_idx, true));
 
         while let Some((idx, should_be_black)) = queue.pop() {
-            if let Some(ref node) = *&tree.node_list[idx] {
+            if let Some(ref node) = tree.node_list[idx] {
                 if should_be_black {
                     // If the node doesn't exist, it's implicitly Black, so we don't bother to test
-                    assert_eq!(node.color, Color::Black);
+                    assert_eq!(node.color(), Color::Black);
                 }
-                let child_should_be_black = match node.color {
+                let child_should_be_black = match node.color() {
                     Color::Red => true,
                     Color::Black => false,
                 };
 
-                if let Some(left) = node.left {
+                if let Some(left) = node.left() {
                     queue.push((left, child_should_be_black));
                 }
 
-                if let Some(right) = node.right {
+                if let Some(right) = node.right() {
                     queue.push((right, child_should_be_black));
                 }
             }
@@ -965,11 +1783,9 @@ mod tests {
         let test_data: Vec<usize> = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
 
         let test_tree = TList::<usize>::from_data(&test_data);
-        let mut idx = 0usize;
-        for i in test_tree.node_list.iter() {
+        for (idx, i) in test_tree.node_list.iter().enumerate() {
             let node = i.clone();
             assert_eq!(Some(idx), node.map(|n| n.data));
-            idx += 1;
         }
 
         assert_color_invariants(&test_tree);
@@ -1115,114 +1931,44 @@ mod tests {
         let mut expected_nodes = Vec::<Node<usize>>::with_capacity(10);
 
         // Node value 0, has no children
-        expected_nodes.push(Node {
-            data: 0usize,
-            color: Color::Red,
-            parent: Some(1),
-            left: None,
-            right: None,
-            size: 1,
-        });
+        expected_nodes.push(Node::with_links(0usize, Color::Red, Some(1), None, None, 1));
 
         // Node value 1, has left child 0
-        expected_nodes.push(Node {
-            data: 1usize,
-            color: Color::Black,
-            parent: Some(2),
-            left: Some(0),
-            right: None,
-            size: 2,
-        });
+        expected_nodes.push(Node::with_links(1usize, Color::Black, Some(2), Some(0), None, 2));
 
         // Node value 2, has left 1, right 3
-        expected_nodes.push(Node {
-            data: 2usize,
-            color: Color::Red,
-            parent: Some(5),
-            left: Some(1),
-            right: Some(3),
-            size: 5,
-        });
+        expected_nodes.push(Node::with_links(2usize, Color::Red, Some(5), Some(1), Some(3), 5));
 
         // Node value 3, has right 4
-        expected_nodes.push(Node {
-            data:3usize,
-            color: Color::Black,
-            parent: Some(2),
-            left: None,
-            right: Some(4),
-            size: 2,
-        });
+        expected_nodes.push(Node::with_links(3usize, Color::Black, Some(2), None, Some(4), 2));
 
         // Node value 4, has no children
-        expected_nodes.push(Node {
-            data: 4usize,
-            color: Color::Red,
-            parent: Some(3),
-            left: None,
-            right: None,
-            size: 1,
-        });
+        expected_nodes.push(Node::with_links(4usize, Color::Red, Some(3), None, None, 1));
 
         // Node value 5, root node, left 2 right 7
-        expected_nodes.push(Node {
-            data: 5usize,
-            color: Color::Black,
-            parent: None,
-            left: Some(2),
-            right: Some(7),
-            size: 10,
-        });
+        expected_nodes.push(Node::with_links(5usize, Color::Black, None, Some(2), Some(7), 10));
 
         // Node value 6, no children
-        expected_nodes.push(Node {
-            data: 6usize,
-            color: Color::Black,
-            parent: Some(7),
-            left: None,
-            right: None,
-            size: 1,
-        });
+        expected_nodes.push(Node::with_links(6usize, Color::Black, Some(7), None, None, 1));
 
         // Node value 7, left 6 right 8
-        expected_nodes.push(Node {
-            data: 7usize,
-            color: Color::Red,
-            parent: Some(5),
-            left: Some(6),
-            right: Some(8),
-            size: 4,
-        });
+        expected_nodes.push(Node::with_links(7usize, Color::Red, Some(5), Some(6), Some(8), 4));
 
         // Node value 8, right 9
-        expected_nodes.push(Node {
-            data: 8usize,
-            color: Color::Black,
-            parent: Some(7),
-            left: None,
-            right: Some(9),
-            size: 2,
-        });
+        expected_nodes.push(Node::with_links(8usize, Color::Black, Some(7), None, Some(9), 2));
 
         // Node value 9, no children
-        expected_nodes.push(Node {
-            data: 9usize,
-            color: Color::Red,
-            parent: Some(8),
-            left: None,
-            right: None,
-            size: 1,
-        });
+        expected_nodes.push(Node::with_links(9usize, Color::Red, Some(8), None, None, 1));
 
-        expected_nodes.iter().zip(test_tree.node_list.iter()).map(|(expect, r)| {
+        expected_nodes.iter().zip(test_tree.node_list.iter()).for_each(|(expect, r)| {
             let r_test = r.clone().unwrap();
             assert_eq!(expect.data, r_test.data);
-            assert_eq!(expect.color, r_test.color);
-            assert_eq!(expect.parent, r_test.parent);
-            assert_eq!(expect.left, r_test.left);
-            assert_eq!(expect.right, r_test.right);
-            assert_eq!(expect.size, r_test.size);
-        }).collect::<Vec<()>>();
+            assert_eq!(expect.color(), r_test.color());
+            assert_eq!(expect.parent(), r_test.parent());
+            assert_eq!(expect.left(), r_test.left());
+            assert_eq!(expect.right(), r_test.right());
+            assert_eq!(expect.size(), r_test.size());
+        });
     }
 
     #[test]
@@ -1242,6 +1988,16 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_get_out_of_bounds() {
+        let test_data: Vec<usize> = (0..10).collect();
+        let mut test_list = TList::<usize>::from_data(&test_data);
+        assert_eq!(None, test_list.get(10));
+        assert_eq!(None, test_list.get_mut(10));
+        assert_eq!(Some(&9), test_list.get(9));
+        assert_eq!(None, TList::<usize>::new().get(0));
+    }
+
     #[test]
     fn test_get_randomized() {
         let mut test_data: Vec<i32> = Vec::with_capacity(10000);
@@ -1284,87 +2040,80 @@ mod tests {
         let test_iter = test_list.iter();
         test_iter
             .enumerate()
-            .map(|(idx, data_val)| assert_eq!(&test_data[idx], data_val))
-            .collect::<Vec<()>>();
+            .for_each(|(idx, data_val)| assert_eq!(&test_data[idx], data_val));
     }
 
     #[test]
-    fn test_rotations() {
-        let mut test_node_list = Vec::<Option<Node<usize>>>::with_capacity(5);
-        test_node_list.push(Some(Node {
-            data: 0,
-            color: Color::Red,
-            parent: Some(1),
-            left: None,
-            right: None,
-            size: 1,
-        }));
-        test_node_list.push(Some(Node {
-            data: 1,
-            color: Color::Black,
-            parent: None,
-            left: Some(0),
-            right: Some(3),
-            size: 5,
-        }));
-        test_node_list.push(Some(Node {
-            data: 2,
-            color: Color::Black,
-            parent: Some(1),
-            left: None,
-            right: None,
-            size: 1,
-        }));
-        test_node_list.push(Some(Node {
-            data: 3,
-            color: Color::Red,
-            parent: Some(1),
-            left: Some(2),
-            right: Some(4),
-            size: 3,
-        }));
-        test_node_list.push(Some(Node {
-            data: 4,
-            color: Color::Black,
-            parent: Some(3),
-            left: None,
-            right: None,
-            size: 1,
-        }));
+    fn test_traverse() {
+        let test_data: Vec<usize> = (0..100).collect();
+        let mut test_list = TList::<usize>::from_data(&test_data);
+        test_list.traverse(&|x| x * 2);
+        let expected: Vec<usize> = test_data.iter().map(|x| x * 2).collect();
+        assert_eq!(expected, test_list.iter().cloned().collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let test_data: Vec<usize> = (0..100).collect();
+        let test_list = TList::<usize>::from_data(&test_data);
+
+        let mut borrowed = Vec::new();
+        for val in &test_list {
+            borrowed.push(*val);
+        }
+        assert_eq!(test_data, borrowed);
+
+        let mut owned = test_list.into_iter();
+        assert_eq!(100, owned.len());
+        assert_eq!(Some(0), owned.next());
+        assert_eq!(99, owned.len());
+        assert_eq!(test_data[1..], owned.collect::<Vec<usize>>()[..]);
+    }
 
-        let free_list = Vec::new();
+    #[test]
+    fn test_rotations() {
+        let mut test_node_list = ArenaVec::<Option<Node<usize>>>::with_capacity(5);
+        test_node_list.push(Some(Node::with_links(0, Color::Red, Some(1), None, None, 1)));
+        test_node_list.push(Some(Node::with_links(1, Color::Black, None, Some(0), Some(3), 5)));
+        test_node_list.push(Some(Node::with_links(2, Color::Black, Some(1), None, None, 1)));
+        test_node_list.push(Some(Node::with_links(3, Color::Red, Some(1), Some(2), Some(4), 3)));
+        test_node_list.push(Some(Node::with_links(4, Color::Black, Some(3), None, None, 1)));
+
+        let free_list = ArenaVec::new();
         let mut test_tree = TList::<usize> {
             node_list: test_node_list,
-            free_list: free_list,
+            free_list,
             root_idx: 1,
+            compact_threshold: None,
+            observer: ObserverSlot::default(),
+            augment: None,
+            push_down: None,
         };
 
         assert_color_invariants(&test_tree);
-        test_tree.left_rotate(1);
+        test_tree.left_rotate(1).unwrap();
         assert_eq!(3, test_tree.root_idx);
         {
             let nref = test_tree.node_list[test_tree.root_idx].clone();
-            assert_eq!(None, nref.unwrap().parent);
+            assert_eq!(None, nref.unwrap().parent());
         }
 
         test_tree
             .iter()
             .enumerate()
-            .map(|(expected, got)| assert_eq!(expected, *got))
-            .collect::<Vec<()>>();
+            .for_each(|(expected, got)| assert_eq!(expected, *got));
 
         {
             let nref = test_tree.node_list[test_tree.root_idx].clone();
-            assert_eq!(None, nref.unwrap().parent);
+            assert_eq!(None, nref.unwrap().parent());
         }
 
-        test_tree.right_rotate(3);
+        test_tree.right_rotate(3).unwrap();
         assert_color_invariants(&test_tree);
         test_tree
             .iter()
             .enumerate()
-            .map(|(expected, got)| assert_eq!(expected, *got))
-            .collect::<Vec<()>>();
+            .for_each(|(expected, got)| assert_eq!(expected, *got));
     }
 
     #[test]
@@ -1375,11 +2124,28 @@ mod tests {
         test_tree
             .iter()
             .enumerate()
-            .map(|(expect, got)| assert_eq!(expect, *got))
-            .collect::<Vec<()>>();
+            .for_each(|(expect, got)| assert_eq!(expect, *got));
         assert_color_invariants(&test_tree);
     }
 
+    #[test]
+    fn test_insert_into_empty() {
+        let mut test_list = TList::<usize>::with_capacity(4);
+        test_list.insert(1, 0);
+        test_list.insert(0, 0);
+        test_list.insert(2, 2);
+        assert_eq!(vec![0, 1, 2], test_list.iter().cloned().collect::<Vec<usize>>());
+        assert_color_invariants(&test_list);
+    }
+
+    #[test]
+    fn test_default() {
+        let mut test_list: TList<u8> = Default::default();
+        assert_eq!(0, test_list.len());
+        test_list.push(7);
+        assert_eq!(Some(&7), test_list.get(0));
+    }
+
     #[test]
     fn test_insert_random() {
         let mut rng = rand::thread_rng();
@@ -1398,8 +2164,7 @@ mod tests {
 
         test_list.iter()
             .zip(test_vec.iter())
-            .map(|(got, expected)| assert_eq!(got, expected))
-            .collect::<Vec<()>>();
+            .for_each(|(got, expected)| assert_eq!(got, expected));
     }
 
     #[test]
@@ -1414,6 +2179,16 @@ mod tests {
 
             let test_list = TList::<usize>::from_data(&test_data);
             assert_color_invariants(&test_list);
+            test_list.assert_invariants();
+        }
+    }
+
+    #[test]
+    fn test_from_data_black_heights() {
+        // Covers trees whose deepest level is both even and odd
+        for len in 1..65 {
+            let test_data: Vec<usize> = (0..len).collect();
+            TList::<usize>::from_data(&test_data).assert_invariants();
         }
     }
 
@@ -1436,6 +2211,326 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_remove_random() {
+        let mut rng = rand::thread_rng();
+        let size = rng.gen_range::<usize>(1000, 5000);
+        let test_vec: Vec<usize> = (0..size).collect();
+        let mut test_vec = test_vec;
+        let mut test_list = TList::<usize>::from_data(&test_vec);
+
+        while !test_vec.is_empty() {
+            let loc = rng.gen_range::<usize>(0, test_vec.len());
+            assert_eq!(Some(test_vec.remove(loc)), test_list.remove(loc));
+            if test_vec.len().is_multiple_of(97) {
+                test_list.assert_invariants();
+            }
+        }
+
+        assert!(test_list.is_empty());
+        assert_eq!(None, test_list.remove(0));
+        assert_eq!(None, test_list.pop());
+    }
+
+    #[test]
+    fn test_mixed_insert_remove_into_iter() {
+        let mut rng = rand::thread_rng();
+        let mut test_list = TList::<u32>::with_capacity(4);
+        let mut test_vec = Vec::new();
+
+        for i in 0..5000u32 {
+            if rng.gen_weighted_bool(3) && !test_vec.is_empty() {
+                let loc = rng.gen_range::<usize>(0, test_vec.len());
+                assert_eq!(Some(test_vec.remove(loc)), test_list.remove(loc));
+            } else {
+                let loc = rng.gen_range::<usize>(0, test_vec.len() + 1);
+                test_vec.insert(loc, i);
+                test_list.insert(i, loc);
+            }
+        }
+
+        test_list.assert_invariants();
+        assert_eq!(test_vec, test_list.into_iter().collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn test_try_insert_remove() {
+        let mut test_list = TList::<usize>::with_capacity(0);
+        assert_eq!(Err(TListError::IndexOutOfBounds { index: 1, len: 0 }), test_list.try_insert(7, 1));
+        assert_eq!(Err(TListError::IndexOutOfBounds { index: 0, len: 0 }), test_list.try_remove(0));
+
+        for i in 0..100 {
+            assert_eq!(Ok(()), test_list.try_push(i));
+        }
+        assert_eq!(Ok(()), test_list.try_insert(100, 50));
+        assert_eq!(Ok(()), test_list.try_insert(101, 101));
+        assert_eq!(Err(TListError::IndexOutOfBounds { index: 103, len: 102 }), test_list.try_insert(0, 103));
+        test_list.assert_invariants();
+
+        assert_eq!(Ok(100), test_list.try_remove(50));
+        assert_eq!(Ok(101), test_list.try_remove(100));
+        assert_eq!(Err(TListError::IndexOutOfBounds { index: 100, len: 100 }), test_list.try_remove(100));
+        assert!(test_list.iter().cloned().eq(0..100));
+    }
+
+    #[test]
+    fn test_try_reserve() {
+        let mut test_list = TList::<u64>::with_capacity(4);
+        assert_eq!(Ok(()), test_list.try_reserve(2));
+        assert_eq!(4, test_list.capacity());
+        assert_eq!(Ok(()), test_list.try_reserve(100));
+        assert!(test_list.capacity() >= 100);
+
+        test_list.push(1);
+        let capacity = test_list.capacity();
+        assert_eq!(Err(TListError::CapacityOverflow), test_list.try_reserve(usize::MAX));
+        assert_eq!(Err(TListError::CapacityOverflow), test_list.try_reserve_exact(MAX_LEN));
+        assert_eq!(capacity, test_list.capacity());
+        if cfg!(not(feature = "u32-index")) {
+            match test_list.try_reserve(MAX_LEN - 1) {
+                Err(TListError::AllocationFailure(_)) => {},
+                other => panic!("expected an allocation failure, got {:?}", other),
+            }
+            assert_eq!(capacity, test_list.capacity());
+        }
+        assert_eq!(Some(&1), test_list.get(0));
+    }
+
+    #[test]
+    fn test_lazy_capacity() {
+        let mut test_list = TList::<u32>::with_capacity(1000);
+        assert_eq!(1000, test_list.capacity());
+        assert_eq!(0, test_list.node_list.len());
+        assert_eq!(0, test_list.free_list.capacity());
+
+        for i in 0..10 {
+            test_list.push(i);
+        }
+        assert_eq!(10, test_list.node_list.len());
+        assert!(test_list.free_list.is_empty());
+
+        // removals leave holes which are reused before the high-water mark is raised
+        test_list.remove(3);
+        test_list.remove(3);
+        assert_eq!(2, test_list.free_list.len());
+        test_list.insert(3, 3);
+        test_list.insert(4, 4);
+        assert_eq!(10, test_list.node_list.len());
+        assert!(test_list.free_list.is_empty());
+        assert!(test_list.iter().cloned().eq(0..10));
+        test_list.assert_invariants();
+    }
+
+    #[test]
+    fn test_reserve_shrink_clear() {
+        let mut test_list = TList::<usize>::with_capacity(0);
+        test_list.reserve(10);
+        assert!(test_list.capacity() >= 10);
+        test_list.reserve_exact(50);
+        assert_eq!(50, test_list.capacity());
+
+        for i in 0..50 {
+            test_list.push(i);
+        }
+        assert_eq!(50, test_list.capacity());
+
+        // freed slots count towards the reservation
+        for _ in 0..20 {
+            test_list.remove(10);
+        }
+        test_list.reserve_exact(20);
+        assert_eq!(50, test_list.capacity());
+
+        // only slots past the last occupied one can be released
+        while test_list.len() > 5 {
+            test_list.pop();
+        }
+        test_list.shrink_to_fit();
+        assert!(test_list.capacity() < 50);
+        assert!(test_list.capacity() >= 5);
+        assert!(test_list.iter().cloned().eq(0..5));
+        test_list.assert_invariants();
+        test_list.push(5);
+        assert!(test_list.iter().cloned().eq(0..6));
+
+        test_list.clear();
+        assert!(test_list.is_empty());
+        assert_eq!(None, test_list.get(0));
+        assert!(test_list.capacity() >= 5);
+        test_list.push(1);
+        test_list.insert(0, 0);
+        assert!(test_list.iter().cloned().eq(0..2));
+        test_list.assert_invariants();
+    }
+
+    #[test]
+    fn test_weight_overflow() {
+        // Weighted totals past MAX_LEN are refused before anything changes
+        let mut test_list = TList::<u32>::new();
+        let first = test_list.insert_after(None, 0, MAX_LEN - 2).unwrap();
+        let second = test_list.insert_after(Some(first), 1, 1).unwrap();
+        assert_eq!(Err(TListError::CapacityOverflow), test_list.insert_after(Some(second), 2, 2));
+        assert_eq!(Err(TListError::CapacityOverflow), test_list.adjust_weight(second, 1, 3));
+        assert_eq!(2, test_list.len());
+        assert_eq!(MAX_LEN - 1, test_list.total_weight());
+
+        test_list.adjust_weight(second, 1, 2).unwrap();
+        assert_eq!(MAX_LEN, test_list.total_weight());
+        test_list.adjust_weight(first, MAX_LEN - 2, 1).unwrap();
+        assert_eq!(3, test_list.total_weight());
+    }
+
+    #[test]
+    fn test_compact() {
+        let mut rng = rand::thread_rng();
+        let mut test_list = TList::<usize>::with_capacity(16);
+        let mut test_vec = Vec::new();
+        for i in 0..2000 {
+            let loc = rng.gen_range::<usize>(0, test_vec.len() + 1);
+            test_list.insert(i, loc);
+            test_vec.insert(loc, i);
+        }
+        for _ in 0..500 {
+            let loc = rng.gen_range::<usize>(0, test_vec.len());
+            assert_eq!(Some(test_vec.remove(loc)), test_list.remove(loc));
+        }
+
+        let handles: Vec<_> = (0..test_vec.len()).map(|i| test_list.handle(i).unwrap()).collect();
+        let remap = test_list.compact();
+
+        test_list.assert_invariants();
+        assert!(test_list.free_list.is_empty());
+        assert_eq!(test_vec.len(), test_list.node_list.len());
+        for (i, node) in test_list.node_list.iter().enumerate() {
+            assert_eq!(Some(&test_vec[i]), node.as_ref().map(|n| &n.data));
+        }
+        for (i, &h) in handles.iter().enumerate() {
+            let new_h = remap.get(h).unwrap();
+            assert_eq!(Some(i), test_list.index_of(new_h));
+            assert_eq!(Some(&test_vec[i]), test_list.get_by_handle(new_h));
+        }
+
+        test_list.insert(7, 0);
+        test_list.remove(100);
+        test_list.assert_invariants();
+    }
+
+    #[test]
+    fn test_handles() {
+        let mut test_list = TList::<usize>::from_data(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        let h = test_list.handle(5).unwrap();
+        assert_eq!(Some(5), test_list.index_of(h));
+        assert_eq!(None, test_list.handle(10));
+
+        for i in 0..5 {
+            test_list.insert(100 + i, 0);
+        }
+        test_list.remove(12);
+        assert_eq!(Some(10), test_list.index_of(h));
+        *test_list.get_mut_by_handle(h).unwrap() += 10;
+        assert_eq!(Some(&15), test_list.get(10));
+
+        test_list.remove(10);
+        assert_eq!(None, test_list.index_of(h));
+        assert_eq!(None, test_list.get_by_handle(h));
+    }
+
+    #[test]
+    fn test_node_packing() {
+        use std::mem::size_of;
+
+        // Empty slots use the packed size as a niche, and a node with a one byte payload
+        // costs five links: three links, the size and color, and the padded payload.
+        let link_size = if cfg!(feature = "u32-index") { 4 } else { size_of::<usize>() };
+        assert_eq!(size_of::<Node<u8>>(), size_of::<Option<Node<u8>>>());
+        assert_eq!(5 * link_size, size_of::<Option<Node<u8>>>());
+
+        let mut node = Node::new_leaf(7u8, Color::Red);
+        assert_eq!(Ok(()), node.set_size(MAX_LEN));
+        assert_eq!(Err(()), node.set_size(MAX_LEN + 1));
+        assert_eq!(Err(()), node.set_size(0));
+        node.set_parent(Some(MAX_LEN - 1));
+        assert_eq!((MAX_LEN, Color::Red), (node.size(), node.color()));
+        node.set_color(Color::Black);
+        assert_eq!((MAX_LEN, Color::Black), (node.size(), node.color()));
+        assert_eq!((Some(MAX_LEN - 1), None, None), (node.parent(), node.left(), node.right()));
+    }
+
+    // Counts the bytes allocated through it which are still live, and refuses any allocation
+    // which would take the total past its limit.
+    #[derive(Clone)]
+    pub(crate) struct CountingAlloc {
+        pub(crate) live: Rc<Cell<usize>>,
+        pub(crate) limit: usize,
+    }
+
+    unsafe impl Allocator for CountingAlloc {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            if self.live.get() + layout.size() > self.limit {
+                return Err(AllocError);
+            }
+            self.live.set(self.live.get() + layout.size());
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.live.set(self.live.get() - layout.size());
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    #[test]
+    fn test_custom_allocator() {
+        let live = Rc::new(Cell::new(0));
+        let alloc = CountingAlloc { live: live.clone(), limit: usize::MAX };
+        let mut test_list = TList::<u64, _>::new_in(alloc.clone());
+        assert!(live.get() > 0);
+        for i in 0..1000 {
+            test_list.insert(i, (i as usize) / 2);
+        }
+        for _ in 0..100 {
+            test_list.remove(10);
+        }
+        test_list.compact();
+        test_list.assert_invariants();
+        assert_eq!(900, test_list.iter().count());
+        assert_eq!(live.get(), test_list.allocator().live.get());
+
+        drop(test_list);
+        assert_eq!(0, live.get());
+
+        // Nodes past the limit are refused without disturbing the list
+        let limited = CountingAlloc { live: live.clone(), limit: 4096 };
+        let mut test_list = TList::<u64, _>::with_capacity_in(0, limited);
+        let mut pushed = 0;
+        while let Ok(()) = test_list.try_push(pushed) {
+            pushed += 1;
+        }
+        assert!(pushed > 0);
+        match test_list.try_push(pushed) {
+            Err(TListError::AllocationFailure(_)) => {},
+            other => panic!("expected an allocation failure, got {:?}", other),
+        }
+        assert!(live.get() <= 4096);
+        assert!((0..pushed).eq(test_list.into_iter()));
+        assert_eq!(0, live.get());
+    }
+
+    #[test]
+    fn test_auto_compact() {
+        let mut test_list = TList::<usize>::from_data(&(0..100).collect::<Vec<_>>());
+        test_list.set_compact_threshold(Some(0.25));
+        for _ in 0..25 {
+            test_list.remove(10);
+        }
+        assert_eq!(25, test_list.free_list.len());
+        test_list.remove(10);
+        assert!(test_list.free_list.is_empty());
+        assert_eq!(74, test_list.node_list.len());
+        assert!(test_list.iter().cloned().eq((0..10).chain(36..100)));
+        test_list.assert_invariants();
+    }
+
     #[test]
     fn assert_color_invs_delete() {
         let test_data = vec![0i32, 1i32, 2i32, 3i32, 4i32, 5i32];
@@ -0,0 +1,204 @@
+// (c) 2017 James Crooks
+//
+// The tree node stored in each occupied slot of a TList's arena.
+//
+// To keep the per-node overhead down, links to other nodes are stored as plain arena
+// indexes of type Link, with Link::MAX standing in for a missing link, and the node's
+// color is packed into the top bit of its sub-tree size. Link is a usize by default.
+// With the "u32-index" feature it is a u32, which halves the overhead again at the cost
+// of limiting a TList to MAX_LEN (2^31 - 1) elements.
+
+// The casts between Link and usize are only no-ops for the default width
+#![allow(clippy::unnecessary_cast)]
+
+#[cfg(not(feature = "u32-index"))]
+use core::num::NonZeroUsize as NonZeroLink;
+#[cfg(feature = "u32-index")]
+use core::num::NonZeroU32 as NonZeroLink;
+
+#[cfg(not(feature = "u32-index"))]
+type Link = usize;
+#[cfg(feature = "u32-index")]
+type Link = u32;
+
+const NIL: Link = Link::MAX;
+const RED_BIT: Link = 1 << (Link::BITS - 1);
+
+/// The most elements a TList can hold, limited by the width of the node links.
+pub const MAX_LEN: usize = (RED_BIT - 1) as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Color {
+    Red,
+    Black,
+}
+
+#[derive(Debug, Clone)]
+pub struct Node<T: Sized> {
+    pub data: T,
+    parent: Link,
+    left: Link,
+    right: Link,
+    // The sub-tree size in the low bits, with RED_BIT set for a Red node. Every node
+    // counts itself, so this is never zero, which gives Option<Node<T>> a niche and
+    // keeps empty arena slots free.
+    size_color: NonZeroLink,
+}
+
+#[inline]
+fn to_link(index: Option<usize>) -> Link {
+    // Slot indexes are kept below MAX_LEN by add_leaf(), so the cast can't truncate
+    match index {
+        Some(idx) => idx as Link,
+        None => NIL,
+    }
+}
+
+#[inline]
+fn from_link(link: Link) -> Option<usize> {
+    if link == NIL {
+        None
+    } else {
+        Some(link as usize)
+    }
+}
+
+impl<T> Node<T> where T: Sized {
+    #[inline]
+    pub fn new_leaf(elem: T, color: Color) -> Node<T> {
+        let mut node = Node {
+            data: elem,
+            parent: NIL,
+            left: NIL,
+            right: NIL,
+            size_color: NonZeroLink::MIN,
+        };
+        node.set_color(color);
+        node
+    }
+
+    #[inline]
+    pub fn parent(&self) -> Option<usize> {
+        from_link(self.parent)
+    }
+
+    #[inline]
+    pub fn set_parent(&mut self, parent: Option<usize>) {
+        self.parent = to_link(parent);
+    }
+
+    #[inline]
+    pub fn left(&self) -> Option<usize> {
+        from_link(self.left)
+    }
+
+    #[inline]
+    pub fn set_left(&mut self, left: Option<usize>) {
+        self.left = to_link(left);
+    }
+
+    #[inline]
+    pub fn right(&self) -> Option<usize> {
+        from_link(self.right)
+    }
+
+    #[inline]
+    pub fn set_right(&mut self, right: Option<usize>) {
+        self.right = to_link(right);
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        (self.size_color.get() & !RED_BIT) as usize
+    }
+
+    #[inline]
+    pub fn set_size(&mut self, size: usize) -> Result<(), ()> {
+        // Fails, leaving the node as it was, if size doesn't count the node itself or
+        // doesn't fit below RED_BIT
+        if size == 0 || size > MAX_LEN {
+            return Err(());
+        }
+        let packed = size as Link | (self.size_color.get() & RED_BIT);
+        self.size_color = NonZeroLink::new(packed).ok_or(())?;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn color(&self) -> Color {
+        if self.size_color.get() & RED_BIT == 0 {
+            Color::Black
+        } else {
+            Color::Red
+        }
+    }
+
+    #[inline]
+    pub fn set_color(&mut self, color: Color) {
+        let size = self.size_color.get() & !RED_BIT;
+        let packed = match color {
+            Color::Red => size | RED_BIT,
+            Color::Black => size,
+        };
+        self.size_color = NonZeroLink::new(packed).unwrap_or(NonZeroLink::MIN);
+    }
+
+    #[cfg(test)]
+    pub fn with_links(data: T, color: Color, parent: Option<usize>, left: Option<usize>,
+                      right: Option<usize>, size: usize) -> Node<T> {
+        let mut node = Node::new_leaf(data, color);
+        node.set_parent(parent);
+        node.set_left(left);
+        node.set_right(right);
+        node.set_size(size).unwrap();
+        node
+    }
+}
+
+/// The links and sub-tree size of a node, as read by raw_links().
+#[cfg(feature = "rayon")]
+#[derive(Debug, Clone, Copy)]
+pub struct RawLinks {
+    pub parent: Option<usize>,
+    pub left: Option<usize>,
+    pub right: Option<usize>,
+    pub size: usize,
+}
+
+/// Reads the links and sub-tree size of the node in an arena slot through a raw pointer,
+/// or returns None for an empty slot. Only the node's link and size fields are read, and
+/// no reference to the node is made, so its data may be borrowed or moved out elsewhere at
+/// the same time.
+///
+/// Safety: slot must point to a live arena slot, whose links and size nothing writes to
+/// during the call.
+#[cfg(feature = "rayon")]
+#[inline]
+#[allow(clippy::manual_map)]
+pub unsafe fn raw_links<T>(slot: *const Option<Node<T>>) -> Option<RawLinks> {
+    // Matching on the place reads the discriminant, which lives in the niche of size_color,
+    // and copies out the named fields, but never touches data. Option::map() would need a
+    // reference to the whole node.
+    match *slot {
+        Some(Node { parent, left, right, size_color, .. }) => Some(RawLinks {
+            parent: from_link(parent),
+            left: from_link(left),
+            right: from_link(right),
+            size: (size_color.get() & !RED_BIT) as usize,
+        }),
+        None => None,
+    }
+}
+
+/// Returns a raw pointer to the data of the node in an arena slot, or None for an empty
+/// slot, without making a reference to the rest of the node.
+///
+/// Safety: as raw_links(), and slot must be valid for writes.
+#[cfg(feature = "rayon")]
+#[inline]
+pub unsafe fn raw_data<T>(slot: *mut Option<Node<T>>) -> Option<*mut T> {
+    match *slot {
+        Some(Node { ref mut data, .. }) => Some(data as *mut T),
+        None => None,
+    }
+}
@@ -0,0 +1,186 @@
+// (c) 2017 James Crooks
+//
+// Change notifications for views kept in step with a TList.
+
+use alloc::boxed::Box;
+use core::fmt;
+
+/// A change to the indexes of a TList, reported to its Observer after an edit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Change {
+    /// count elements were inserted starting at index, shifting the elements at index and
+    /// above up by count.
+    Inserted {
+        /// The index of the first inserted element.
+        index: usize,
+        /// The number of elements inserted.
+        count: usize,
+    },
+    /// count elements were removed starting at index, shifting the elements above them down
+    /// by count.
+    Removed {
+        /// The index the first removed element had.
+        index: usize,
+        /// The number of elements removed.
+        count: usize,
+    },
+    /// The element at index may have been overwritten in place, without moving any others.
+    Replaced {
+        /// The index of the element.
+        index: usize,
+    },
+}
+
+/// Receives the Changes made to a TList, as registered with set_observer(), so that a view of
+/// the list can be updated incrementally rather than rebuilt.
+///
+/// Changes are reported in the order they are made, each relative to the indexes left by
+/// the ones before it. A closure taking a Change is an Observer.
+///
+/// Observers must be Send so that a TList holding one still is. They needn't be Sync: the
+/// TList only calls its Observer through a mutable borrow, which no other thread can share,
+/// so state such as a Cell may live in the Observer itself.
+pub trait Observer: Send {
+    /// Called after each change to the observed TList.
+    fn on_change(&mut self, change: Change);
+}
+
+impl<F> Observer for F where F: FnMut(Change) + Send {
+    fn on_change(&mut self, change: Change) {
+        self(change)
+    }
+}
+
+// Holds the Observer of a TList. A clone of the TList is a new list, so it starts out
+// without one.
+#[derive(Default)]
+pub struct ObserverSlot(pub Option<Box<dyn Observer>>);
+
+// Safety: the Observer is only ever reached through &mut ObserverSlot, and Debug only looks
+// at whether there is one, so sharing an &ObserverSlot between threads gives none of them
+// access to it.
+unsafe impl Sync for ObserverSlot {}
+
+impl Clone for ObserverSlot {
+    fn clone(&self) -> ObserverSlot {
+        ObserverSlot(None)
+    }
+}
+
+impl fmt::Debug for ObserverSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(_) => f.write_str("Some(Observer)"),
+            None => f.write_str("None"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::sync::mpsc::channel;
+    use std::sync::{Arc, Mutex};
+
+    use super::Change;
+    use {Handle, TList};
+
+    fn observed(list: &mut TList<u32>) -> Arc<Mutex<Vec<Change>>> {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let sink = log.clone();
+        list.set_observer(move |change| sink.lock().unwrap().push(change));
+        log
+    }
+
+    #[test]
+    fn test_observer_changes() {
+        let mut test_list = TList::<u32>::from_data(&[0, 1, 2, 3]);
+        let log = observed(&mut test_list);
+
+        test_list.insert(10, 1);
+        test_list.insert(11, 99);
+        test_list.push(12);
+        assert!(test_list.try_insert(13, 99).is_err());
+        assert_eq!(Some(0), test_list.remove(0));
+        assert_eq!(None, test_list.remove(99));
+        assert_eq!(Some(12), test_list.pop());
+        *test_list.get_mut(2).unwrap() += 1;
+        assert_eq!(None, test_list.get_mut(99));
+        let handle: Handle = test_list.handle(1).unwrap();
+        *test_list.get_mut_by_handle(handle).unwrap() += 1;
+        test_list.traverse(&|v| v * 2);
+        test_list.clear();
+        test_list.clear();
+
+        assert_eq!(*log.lock().unwrap(), vec![
+            Change::Inserted { index: 1, count: 1 },
+            Change::Inserted { index: 5, count: 1 },
+            Change::Inserted { index: 6, count: 1 },
+            Change::Removed { index: 0, count: 1 },
+            Change::Removed { index: 5, count: 1 },
+            Change::Replaced { index: 2 },
+            Change::Replaced { index: 1 },
+            Change::Replaced { index: 0 },
+            Change::Replaced { index: 1 },
+            Change::Replaced { index: 2 },
+            Change::Replaced { index: 3 },
+            Change::Replaced { index: 4 },
+            Change::Removed { index: 0, count: 5 },
+        ]);
+    }
+
+    #[test]
+    fn test_observer_replay() {
+        // Replaying the changes on a Vec of markers keeps it in step with the list
+        let mut test_list = TList::<u32>::from_data(&[0u32; 16]);
+        let log = observed(&mut test_list);
+        let mut view = vec![false; 16];
+
+        for i in 0..200 {
+            let len = test_list.len();
+            match i % 3 {
+                0 => test_list.insert(i, (i as usize * 7) % (len + 1)),
+                1 if len > 0 => { test_list.remove((i as usize * 5) % len); },
+                _ if len > 0 => { test_list.get_mut((i as usize * 3) % len); },
+                _ => {},
+            }
+
+            for change in log.lock().unwrap().drain(..) {
+                match change {
+                    Change::Inserted { index, count } => {
+                        view.splice(index..index, (0..count).map(|_| true));
+                    },
+                    Change::Removed { index, count } => { view.drain(index..index + count); },
+                    Change::Replaced { index } => view[index] = true,
+                }
+            }
+            assert_eq!(test_list.len(), view.len());
+        }
+
+        let mut clone = test_list.clone();
+        clone.push(0);
+        assert!(log.lock().unwrap().is_empty());
+        assert!(test_list.take_observer().is_some());
+        assert!(test_list.take_observer().is_none());
+    }
+
+    #[test]
+    fn test_observer_not_sync() {
+        // An Observer holding a Cell isn't Sync, yet the TList still is
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let mut test_list = TList::<u32>::new();
+        let (sender, receiver) = channel();
+        let seen = Cell::new(0);
+        test_list.set_observer(move |change| {
+            seen.set(seen.get() + 1);
+            sender.send((seen.get(), change)).unwrap();
+        });
+        assert_send_sync(&test_list);
+
+        test_list.push(1);
+        *test_list.get_mut(0).unwrap() += 1;
+        assert_eq!(vec![(1, Change::Inserted { index: 0, count: 1 }), (2, Change::Replaced { index: 0 })],
+                   receiver.try_iter().collect::<Vec<_>>());
+    }
+}
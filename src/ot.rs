@@ -0,0 +1,205 @@
+// (c) 2017 James Crooks
+//
+// Operational transformation of concurrent sequences of Deltas.
+
+use alloc::vec::Vec;
+
+use Delta;
+
+/// Transforms two sequences of Deltas made concurrently to the same list, each in
+/// DeltaMode::Sequential, against each other. Returns (a', b'), where a' has a's effect on the
+/// list as left by b and b' has b's effect on the list as left by a, so that applying a then
+/// b' gives the same list as applying b then a' (the TP1 property).
+///
+/// Where the two sides conflict, a wins: its inserts at the same index go before b's, its
+/// replacement of an element both replaced is kept, and an element deleted by either side
+/// stays deleted. The result is made of single-element Insert, Delete and Replace Deltas,
+/// with ranges expanded. Runs in O(K * L) time for K and L elements edited by a and b.
+pub fn transform<T>(a: Vec<Delta<T>>, b: Vec<Delta<T>>) -> (Vec<Delta<T>>, Vec<Delta<T>>) {
+    let mut b = expand(b);
+    let mut a_prime = Vec::new();
+
+    // Each op of a is carried past all of b, which is rewritten to come after it in turn
+    for op in expand(a) {
+        let mut op = Some(op);
+        let mut b_next = Vec::with_capacity(b.len());
+        for b_op in b {
+            match op.take() {
+                Some(a_op) => {
+                    let (a_op, b_op) = transform_op(a_op, b_op);
+                    op = a_op;
+                    b_next.extend(b_op);
+                },
+                None => b_next.push(b_op),
+            }
+        }
+        a_prime.extend(op);
+        b = b_next;
+    }
+
+    (a_prime, b)
+}
+
+fn expand<T>(deltas: Vec<Delta<T>>) -> Vec<Delta<T>> {
+    // Splits range Deltas into single-element ones with the same sequential effect
+    let mut ops = Vec::with_capacity(deltas.len());
+    for delta in deltas {
+        match delta {
+            Delta::InsertRange { index, values } => {
+                ops.extend(values.into_iter().enumerate().map(|(i, value)| Delta::Insert { index: index + i, value }));
+            },
+            Delta::DeleteRange { index, count } => ops.extend((0..count).map(|_| Delta::Delete { index })),
+            Delta::ReplaceRange { index, values } => {
+                ops.extend(values.into_iter().enumerate().map(|(i, value)| Delta::Replace { index: index + i, value }));
+            },
+            op => ops.push(op),
+        }
+    }
+    ops
+}
+
+fn transform_op<T>(a: Delta<T>, b: Delta<T>) -> (Option<Delta<T>>, Option<Delta<T>>) {
+    // Transforms two concurrent single-element ops, returning None for an op which has no
+    // effect once the other is applied
+    use self::Delta::{Delete, Insert};
+
+    match (a, b) {
+        (Insert { index: i, value: a_value }, Insert { index: j, value: b_value }) => {
+            if i <= j {
+                (Some(Insert { index: i, value: a_value }), Some(Insert { index: j + 1, value: b_value }))
+            } else {
+                (Some(Insert { index: i + 1, value: a_value }), Some(Insert { index: j, value: b_value }))
+            }
+        },
+        (Insert { index: i, value }, b) => {
+            let j = b.index();
+            let a = Insert { index: if i <= j { i } else { i - usize::from(is_delete(&b)) }, value };
+            (Some(a), Some(shift(b, usize::from(i <= j), 0)))
+        },
+        (a, Insert { index: j, value }) => {
+            let i = a.index();
+            let b = Insert { index: if j <= i { j } else { j - usize::from(is_delete(&a)) }, value };
+            (Some(shift(a, usize::from(j <= i), 0)), Some(b))
+        },
+        (Delete { index: i }, Delete { index: j }) => {
+            if i == j {
+                (None, None)
+            } else {
+                (Some(Delete { index: i - usize::from(j < i) }), Some(Delete { index: j - usize::from(i < j) }))
+            }
+        },
+        (Delete { index: i }, b) => {
+            let j = b.index();
+            let b = if i == j { None } else { Some(shift(b, 0, usize::from(i < j))) };
+            (Some(Delete { index: i }), b)
+        },
+        (a, Delete { index: j }) => {
+            let i = a.index();
+            let a = if i == j { None } else { Some(shift(a, 0, usize::from(j < i))) };
+            (a, Some(Delete { index: j }))
+        },
+        (a, b) => {
+            // Both are Replaces, and a's value wins at the same index
+            let b = if a.index() == b.index() { None } else { Some(b) };
+            (Some(a), b)
+        },
+    }
+}
+
+fn is_delete<T>(op: &Delta<T>) -> bool {
+    matches!(*op, Delta::Delete { .. })
+}
+
+fn shift<T>(op: Delta<T>, up: usize, down: usize) -> Delta<T> {
+    // Moves a single-element Delete or Replace to a new index
+    match op {
+        Delta::Delete { index } => Delta::Delete { index: index + up - down },
+        Delta::Replace { index, value } => Delta::Replace { index: index + up - down, value },
+        op => op,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::Rng;
+    use super::transform;
+    use {Delta, DeltaMode, TList};
+
+    fn converged(base: &TList<String>, a: Vec<Delta<String>>, b: Vec<Delta<String>>) -> Vec<String> {
+        // Applies a then b' and b then a' to clones of base, checking they agree
+        let (a_prime, b_prime) = transform(a.clone(), b.clone());
+
+        let mut ab = base.clone();
+        ab.apply(a, DeltaMode::Sequential).unwrap();
+        ab.apply(b_prime, DeltaMode::Sequential).unwrap();
+
+        let mut ba = base.clone();
+        ba.apply(b, DeltaMode::Sequential).unwrap();
+        ba.apply(a_prime, DeltaMode::Sequential).unwrap();
+
+        let (ab, ba): (Vec<String>, Vec<String>) = (ab.into_iter().collect(), ba.into_iter().collect());
+        assert_eq!(ab, ba);
+        ab
+    }
+
+    fn random_deltas<R: Rng>(rng: &mut R, mut len: usize, side: &str) -> Vec<Delta<String>> {
+        // A valid sequence of Deltas for a list of len elements
+        let mut deltas = Vec::new();
+        for n in 0..rng.gen_range(0, 6) {
+            let value = |i: usize| format!("{}{}.{}", side, n, i);
+            let index = rng.gen_range(0, len + 1);
+            let count = rng.gen_range(0, (len - index).min(3) + 1);
+            let delta = match rng.gen_range(0, 6) {
+                0 => Delta::Insert { index, value: value(0) },
+                1 => Delta::InsertRange { index, values: (0..3).map(value).collect() },
+                2 if index < len => Delta::Delete { index },
+                3 => Delta::DeleteRange { index, count },
+                4 if index < len => Delta::Replace { index, value: value(0) },
+                _ => Delta::ReplaceRange { index, values: (0..count).map(value).collect() },
+            };
+            len = len + match delta {
+                Delta::Insert { .. } => 1,
+                Delta::InsertRange { .. } => 3,
+                _ => 0,
+            } - match delta {
+                Delta::Delete { .. } => 1,
+                Delta::DeleteRange { count, .. } => count,
+                _ => 0,
+            };
+            deltas.push(delta);
+        }
+        deltas
+    }
+
+    #[test]
+    fn test_transform_conflicts() {
+        let base = TList::<String>::from_data(&["x".to_string(), "y".to_string(), "z".to_string()]);
+        let insert = |index: usize, value: &str| Delta::Insert { index, value: value.to_string() };
+        let replace = |index: usize, value: &str| Delta::Replace { index, value: value.to_string() };
+
+        // a's inserts at the same index go first, and a's replacement wins
+        assert_eq!(vec!["x", "a", "b", "y", "z"], converged(&base, vec![insert(1, "a")], vec![insert(1, "b")]));
+        assert_eq!(vec!["x", "a", "z"], converged(&base, vec![replace(1, "a")], vec![replace(1, "b")]));
+
+        // A deleted element stays deleted, however the other side edited it
+        assert_eq!(vec!["x", "z"], converged(&base, vec![Delta::Delete { index: 1 }], vec![replace(1, "b")]));
+        assert_eq!(vec!["z"], converged(&base, vec![Delta::DeleteRange { index: 0, count: 2 }],
+                                       vec![Delta::Delete { index: 1 }]));
+        assert_eq!(vec!["b", "z"], converged(&base, vec![Delta::DeleteRange { index: 0, count: 2 }],
+                                             vec![insert(1, "b")]));
+    }
+
+    #[test]
+    fn test_transform_random() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..500 {
+            let len = rng.gen_range(0, 8);
+            let base = TList::<String>::from_data(&(0..len).map(|i| i.to_string()).collect::<Vec<_>>());
+            let a = random_deltas(&mut rng, len, "a");
+            let b = random_deltas(&mut rng, len, "b");
+            converged(&base, a, b);
+        }
+    }
+}
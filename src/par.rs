@@ -0,0 +1,676 @@
+// (c) 2017 James Crooks
+//
+// Rayon parallel iterators over a TList, enabled by the "rayon" feature.
+
+//! Parallel iterators over a TList, for use with rayon.
+//!
+//! Borrowing iteration splits the list by index range: since every node knows the size of
+//! its sub-tree, finding where a range starts takes O(log N), and each half then walks its
+//! own part of the tree in O(1) amortized time per element. Mutable and owned iteration
+//! split the same way, walking the tree through raw pointers which only read its links, so
+//! that each thread can borrow or move out the elements of its own range.
+//!
+//! TList::par_from_vec() builds a balanced tree from a Vec on several threads.
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::ptr;
+
+use allocator_api2::vec::Vec as ArenaVec;
+use rayon::iter::plumbing::{bridge, Consumer, Producer, ProducerCallback, UnindexedConsumer};
+use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
+use rayon::prelude::*;
+
+use node::{raw_data, raw_links, Color, Node, RawLinks};
+use {Allocator, Dir, Global, TList, MAX_LEN};
+
+// Sub-trees smaller than this are linked up on the current thread
+const SEQUENTIAL_BUILD_LEN: usize = 4096;
+
+impl<T> TList<T> where T: Send {
+    /// Builds a new TList from a vector of data elements such that the index order of the
+    /// TList matches the vector, as from_data() does, but moves the elements into place and
+    /// links the tree up on several threads.
+    ///
+    /// Each element keeps its position in the vector as its slot in the node arena, and the
+    /// tree over a range of slots is rooted at the range's midpoint. A node's links, size and
+    /// color therefore only depend on the range it roots, so disjoint ranges are built
+    /// independently.
+    ///
+    /// Panics if data holds more than MAX_LEN elements.
+    pub fn par_from_vec(data: Vec<T>) -> TList<T> {
+        assert!(data.len() <= MAX_LEN, "TList capacity overflow");
+        let len = data.len();
+        let mut tree = TList::with_capacity(len);
+        tree.node_list.resize_with(len, || None);
+
+        tree.node_list
+            .par_iter_mut()
+            .zip(data.into_par_iter())
+            .for_each(|(slot, elem)| *slot = Some(Node::new_leaf(elem, Color::Black)));
+
+        // Splitting at the midpoint fills every level but the deepest, which holds nodes only
+        // if len + 1 isn't a power of two. Painting that level Red and the rest Black gives
+        // every path the same black height.
+        let full_levels = usize::BITS - 1 - (len + 1).leading_zeros();
+        tree.root_idx = len >> 1;
+        link_range(&mut tree.node_list, 0, None, 0, full_levels);
+        tree
+    }
+}
+
+fn link_range<T>(nodes: &mut [Option<Node<T>>], offset: usize, parent: Option<usize>,
+                 depth: u32, full_levels: u32) where T: Send {
+    // Links up the balanced sub-tree over the slots offset..offset + nodes.len(), rooted at
+    // the midpoint, whose parent is at slot parent and which sits at the given depth.
+    if nodes.is_empty() {
+        return;
+    }
+
+    let len = nodes.len();
+    let mid = len >> 1;
+    let (left, rest) = nodes.split_at_mut(mid);
+    let (root, right) = match rest.split_first_mut() {
+        Some((Some(root), right)) => (root, right),
+        _ => return,
+    };
+
+    let root_idx = offset + mid;
+    let sub_tree_root = |sub_offset: usize, sub_len: usize| {
+        if sub_len == 0 { None } else { Some(sub_offset + (sub_len >> 1)) }
+    };
+    root.set_parent(parent);
+    root.set_left(sub_tree_root(offset, left.len()));
+    root.set_right(sub_tree_root(root_idx + 1, right.len()));
+    root.set_size(len).expect("TList capacity overflow");
+    root.set_color(if depth < full_levels { Color::Black } else { Color::Red });
+
+    if len < SEQUENTIAL_BUILD_LEN {
+        link_range(left, offset, Some(root_idx), depth + 1, full_levels);
+        link_range(right, root_idx + 1, Some(root_idx), depth + 1, full_levels);
+    } else {
+        rayon::join(
+            || link_range(left, offset, Some(root_idx), depth + 1, full_levels),
+            || link_range(right, root_idx + 1, Some(root_idx), depth + 1, full_levels));
+    }
+}
+
+/// A parallel iterator over a TList that yields borrowed values in index order. Created by
+/// par_iter() from rayon's IntoParallelRefIterator.
+pub struct ParIter<'a, T: 'a, A: Allocator + Clone + 'a> {
+    tree: &'a TList<T, A>,
+}
+
+impl<'a, T, A> IntoParallelIterator for &'a TList<T, A> where T: Sync, A: Allocator + Clone + Sync {
+    type Item = &'a T;
+    type Iter = ParIter<'a, T, A>;
+
+    fn into_par_iter(self) -> ParIter<'a, T, A> {
+        ParIter { tree: self }
+    }
+}
+
+impl<'a, T, A> ParallelIterator for ParIter<'a, T, A> where T: Sync, A: Allocator + Clone + Sync {
+    type Item = &'a T;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result where C: UnindexedConsumer<Self::Item> {
+        bridge(self, consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        Some(self.tree.len())
+    }
+}
+
+impl<'a, T, A> IndexedParallelIterator for ParIter<'a, T, A> where T: Sync, A: Allocator + Clone + Sync {
+    fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    fn drive<C>(self, consumer: C) -> C::Result where C: Consumer<Self::Item> {
+        bridge(self, consumer)
+    }
+
+    fn with_producer<CB>(self, callback: CB) -> CB::Output where CB: ProducerCallback<Self::Item> {
+        callback.callback(RangeProducer {
+            tree: self.tree,
+            start: 0,
+            end: self.tree.len(),
+        })
+    }
+}
+
+// Produces the elements with indexes start..end
+struct RangeProducer<'a, T: 'a, A: Allocator + Clone + 'a> {
+    tree: &'a TList<T, A>,
+    start: usize,
+    end: usize,
+}
+
+impl<'a, T, A> Producer for RangeProducer<'a, T, A> where T: Sync, A: Allocator + Clone + Sync {
+    type Item = &'a T;
+    type IntoIter = RangeIter<'a, T, A>;
+
+    fn into_iter(self) -> RangeIter<'a, T, A> {
+        let (front, back) = if self.start < self.end {
+            (self.tree.search(self.start), self.tree.search(self.end - 1))
+        } else {
+            (None, None)
+        };
+
+        RangeIter {
+            tree: self.tree,
+            front,
+            back,
+            remaining: self.end - self.start,
+        }
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let mid = self.start + index;
+        (RangeProducer { tree: self.tree, start: self.start, end: mid },
+         RangeProducer { tree: self.tree, start: mid, end: self.end })
+    }
+}
+
+// Walks a range of the tree from both ends by following links to the neighboring nodes,
+// which takes O(1) amortized time per element once the ends have been found.
+struct RangeIter<'a, T: 'a, A: Allocator + Clone + 'a> {
+    tree: &'a TList<T, A>,
+    front: Option<usize>,
+    back: Option<usize>,
+    remaining: usize,
+}
+
+impl<'a, T, A> RangeIter<'a, T, A> where A: Allocator + Clone {
+    #[inline]
+    fn step(&mut self, dir: Dir) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let tree = self.tree;
+        let cursor = match dir {
+            Dir::Right => &mut self.front,
+            Dir::Left => &mut self.back,
+        };
+        let slot = (*cursor)?;
+        *cursor = tree.neighbor(slot, dir);
+        self.remaining -= 1;
+        tree.node_list[slot].as_ref().map(|n| &n.data)
+    }
+}
+
+impl<'a, T, A> Iterator for RangeIter<'a, T, A> where A: Allocator + Clone {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.step(Dir::Right)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T, A> DoubleEndedIterator for RangeIter<'a, T, A> where A: Allocator + Clone {
+    fn next_back(&mut self) -> Option<&'a T> {
+        self.step(Dir::Left)
+    }
+}
+
+impl<'a, T, A> ExactSizeIterator for RangeIter<'a, T, A> where A: Allocator + Clone {}
+
+/// A parallel iterator over a TList that yields mutably borrowed values in index order.
+/// Created by par_iter_mut() from rayon's IntoParallelRefMutIterator.
+pub struct ParIterMut<'a, T: 'a> {
+    tree: RawTree<T>,
+    len: usize,
+    marker: PhantomData<&'a mut T>,
+}
+
+impl<'a, T, A> IntoParallelIterator for &'a mut TList<T, A> where T: Send, A: Allocator + Clone {
+    type Item = &'a mut T;
+    type Iter = ParIterMut<'a, T>;
+
+    fn into_par_iter(self) -> ParIterMut<'a, T> {
+        ParIterMut {
+            tree: RawTree { slots: self.node_list.as_mut_ptr(), root: self.root_idx },
+            len: self.len(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> ParallelIterator for ParIterMut<'a, T> where T: Send {
+    type Item = &'a mut T;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result where C: UnindexedConsumer<Self::Item> {
+        bridge(self, consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        Some(self.len)
+    }
+}
+
+impl<'a, T> IndexedParallelIterator for ParIterMut<'a, T> where T: Send {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn drive<C>(self, consumer: C) -> C::Result where C: Consumer<Self::Item> {
+        bridge(self, consumer)
+    }
+
+    fn with_producer<CB>(self, callback: CB) -> CB::Output where CB: ProducerCallback<Self::Item> {
+        callback.callback(RangeMutProducer {
+            tree: self.tree,
+            start: 0,
+            end: self.len,
+            marker: PhantomData,
+        })
+    }
+}
+
+// Produces mutable borrows of the elements with indexes start..end. The ranges of the
+// producers split from one ParIterMut never overlap, so neither do their borrows.
+struct RangeMutProducer<'a, T: 'a> {
+    tree: RawTree<T>,
+    start: usize,
+    end: usize,
+    marker: PhantomData<&'a mut T>,
+}
+
+impl<'a, T> Producer for RangeMutProducer<'a, T> where T: Send {
+    type Item = &'a mut T;
+    type IntoIter = RangeMutIter<'a, T>;
+
+    fn into_iter(self) -> RangeMutIter<'a, T> {
+        RangeMutIter {
+            // Safety: the range lies within the tree the ParIterMut borrowed
+            range: unsafe { RawRange::new(self.tree, self.start, self.end) },
+            marker: PhantomData,
+        }
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let mid = self.start + index;
+        (RangeMutProducer { tree: self.tree, start: self.start, end: mid, marker: PhantomData },
+         RangeMutProducer { tree: self.tree, start: mid, end: self.end, marker: PhantomData })
+    }
+}
+
+struct RangeMutIter<'a, T: 'a> {
+    range: RawRange<T>,
+    marker: PhantomData<&'a mut T>,
+}
+
+impl<'a, T> Iterator for RangeMutIter<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<&'a mut T> {
+        // Safety: each element of the range is yielded once, and lives as long as the
+        // ParIterMut's borrow of the tree
+        self.range.step(Dir::Right).map(|data| unsafe { &mut *data })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.range.remaining, Some(self.range.remaining))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for RangeMutIter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a mut T> {
+        self.range.step(Dir::Left).map(|data| unsafe { &mut *data })
+    }
+}
+
+impl<'a, T> ExactSizeIterator for RangeMutIter<'a, T> {}
+
+/// A parallel iterator over a TList that consumes the list and yields owned values in index
+/// order. Created by into_par_iter() from rayon's IntoParallelIterator.
+pub struct IntoParIter<T, A: Allocator + Clone = Global> {
+    tree: TList<T, A>,
+}
+
+impl<T, A> IntoParallelIterator for TList<T, A> where T: Send, A: Allocator + Clone + Send {
+    type Item = T;
+    type Iter = IntoParIter<T, A>;
+
+    fn into_par_iter(self) -> IntoParIter<T, A> {
+        IntoParIter { tree: self }
+    }
+}
+
+impl<T, A> ParallelIterator for IntoParIter<T, A> where T: Send, A: Allocator + Clone + Send {
+    type Item = T;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result where C: UnindexedConsumer<Self::Item> {
+        bridge(self, consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        Some(self.tree.len())
+    }
+}
+
+impl<T, A> IndexedParallelIterator for IntoParIter<T, A> where T: Send, A: Allocator + Clone + Send {
+    fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    fn drive<C>(self, consumer: C) -> C::Result where C: Consumer<Self::Item> {
+        bridge(self, consumer)
+    }
+
+    fn with_producer<CB>(self, callback: CB) -> CB::Output where CB: ProducerCallback<Self::Item> {
+        let mut tree = self.tree;
+        let len = tree.len();
+        let root = tree.root_idx;
+
+        // Between them, the producers move every element out or drop it, so the slots are
+        // forgotten rather than dropped afterwards, even if the consumer panics.
+        let slots = ForgetSlots(&mut tree.node_list);
+        callback.callback(DrainProducer {
+            tree: RawTree { slots: slots.0.as_mut_ptr(), root },
+            start: 0,
+            end: len,
+        })
+    }
+}
+
+struct ForgetSlots<'a, T: 'a, A: Allocator + 'a>(&'a mut ArenaVec<Option<Node<T>>, A>);
+
+impl<'a, T, A> Drop for ForgetSlots<'a, T, A> where A: Allocator {
+    fn drop(&mut self) {
+        // Safety: the elements have all been moved out or dropped, and the rest of a node
+        // needs no drop
+        unsafe { self.0.set_len(0) }
+    }
+}
+
+// Moves out the elements with indexes start..end, dropping any which are never produced.
+struct DrainProducer<T> {
+    tree: RawTree<T>,
+    start: usize,
+    end: usize,
+}
+
+impl<T> Producer for DrainProducer<T> where T: Send {
+    type Item = T;
+    type IntoIter = Drain<T>;
+
+    fn into_iter(mut self) -> Drain<T> {
+        // Safety: the range lies within the tree, and its elements pass to the Drain
+        let range = unsafe { RawRange::new(self.tree, self.start, self.end) };
+        self.start = self.end;
+        Drain { range }
+    }
+
+    fn split_at(mut self, index: usize) -> (Self, Self) {
+        let mid = self.start + index;
+        let right = DrainProducer { tree: self.tree, start: mid, end: self.end };
+        self.end = mid;
+        (self, right)
+    }
+}
+
+impl<T> Drop for DrainProducer<T> {
+    fn drop(&mut self) {
+        if self.start < self.end {
+            drop(Drain { range: unsafe { RawRange::new(self.tree, self.start, self.end) } });
+        }
+    }
+}
+
+struct Drain<T> {
+    range: RawRange<T>,
+}
+
+impl<T> Iterator for Drain<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        // Safety: each element of the range is read out once, and the slots are forgotten
+        // without dropping it again
+        self.range.step(Dir::Right).map(|data| unsafe { ptr::read(data) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.range.remaining, Some(self.range.remaining))
+    }
+}
+
+impl<T> DoubleEndedIterator for Drain<T> {
+    fn next_back(&mut self) -> Option<T> {
+        self.range.step(Dir::Left).map(|data| unsafe { ptr::read(data) })
+    }
+}
+
+impl<T> ExactSizeIterator for Drain<T> {}
+
+impl<T> Drop for Drain<T> {
+    fn drop(&mut self) {
+        for _ in self.by_ref() {}
+    }
+}
+
+// A tree's arena seen through a raw pointer, so that several threads can walk the tree while
+// each borrows or moves out the elements of its own index range.
+//
+// Walking only reads the links and sizes of nodes, through node::raw_links(), which neither
+// reads nor references their data, and nothing writes links or sizes while the tree is
+// split up. Each element's data is handed to the one range containing it. So no two threads
+// ever touch the same data, and no thread reads memory another one writes.
+struct RawTree<T> {
+    slots: *mut Option<Node<T>>,
+    root: usize,
+}
+
+impl<T> Clone for RawTree<T> {
+    fn clone(&self) -> RawTree<T> {
+        *self
+    }
+}
+
+impl<T> Copy for RawTree<T> {}
+
+// Safety: see above. The elements themselves cross threads, so T must be Send.
+unsafe impl<T> Send for RawTree<T> where T: Send {}
+
+impl<T> RawTree<T> {
+    #[inline]
+    unsafe fn links(&self, slot: usize) -> Option<RawLinks> {
+        raw_links(self.slots.add(slot))
+    }
+
+    unsafe fn search(&self, mut index: usize) -> Option<usize> {
+        // As TList::search(), for an index known to be in bounds
+        let mut slot = self.root;
+        loop {
+            let node = self.links(slot)?;
+            let rank = match node.left {
+                Some(left) => self.links(left)?.size,
+                None => 0,
+            };
+            if index == rank {
+                return Some(slot);
+            }
+            slot = if index < rank {
+                node.left?
+            } else {
+                index -= rank + 1;
+                node.right?
+            };
+        }
+    }
+
+    unsafe fn neighbor(&self, slot: usize, dir: Dir) -> Option<usize> {
+        // As TList::neighbor()
+        let child = |links: RawLinks, dir: Dir| match dir {
+            Dir::Left => links.left,
+            Dir::Right => links.right,
+        };
+        let back = match dir {
+            Dir::Left => Dir::Right,
+            Dir::Right => Dir::Left,
+        };
+
+        if let Some(mut idx) = child(self.links(slot)?, dir) {
+            while let Some(next_idx) = child(self.links(idx)?, back) {
+                idx = next_idx;
+            }
+            return Some(idx);
+        }
+
+        let mut idx = slot;
+        while let Some(p_idx) = self.links(idx)?.parent {
+            if child(self.links(p_idx)?, back) == Some(idx) {
+                return Some(p_idx);
+            }
+            idx = p_idx;
+        }
+        None
+    }
+}
+
+// The elements with indexes start..end of a RawTree, walked from both ends as RangeIter does
+struct RawRange<T> {
+    tree: RawTree<T>,
+    front: Option<usize>,
+    back: Option<usize>,
+    remaining: usize,
+}
+
+impl<T> RawRange<T> {
+    // Safety: start..end must lie within the tree, and the caller takes over its elements
+    unsafe fn new(tree: RawTree<T>, start: usize, end: usize) -> RawRange<T> {
+        let (front, back) = if start < end {
+            (tree.search(start), tree.search(end - 1))
+        } else {
+            (None, None)
+        };
+
+        RawRange {
+            tree,
+            front,
+            back,
+            remaining: end - start,
+        }
+    }
+
+    #[inline]
+    fn step(&mut self, dir: Dir) -> Option<*mut T> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let tree = self.tree;
+        let cursor = match dir {
+            Dir::Right => &mut self.front,
+            Dir::Left => &mut self.back,
+        };
+        let slot = (*cursor)?;
+        self.remaining -= 1;
+        // Safety: the slot lies in this range, and the walk reads links only
+        unsafe {
+            *cursor = tree.neighbor(slot, dir);
+            raw_data(tree.slots.add(slot))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use rayon::prelude::*;
+
+    use TList;
+
+    #[test]
+    fn test_par_iter() {
+        let data: Vec<u64> = (0..10_000).collect();
+        let mut test_list = TList::<u64>::from_data(&data);
+        for i in 0..1000 {
+            test_list.remove(i * 3);
+            test_list.insert(i as u64, i * 7);
+        }
+        let expected: Vec<u64> = test_list.iter().cloned().collect();
+
+        let got: Vec<u64> = test_list.par_iter().cloned().collect();
+        assert_eq!(expected, got);
+        assert_eq!(expected.iter().sum::<u64>(), test_list.par_iter().sum::<u64>());
+        let got: Vec<u64> = test_list.par_iter().rev().skip(10).step_by(3).cloned().collect();
+        assert_eq!(expected.iter().rev().skip(10).step_by(3).cloned().collect::<Vec<_>>(), got);
+        let (left, right) = test_list.par_iter().with_min_len(1).cloned().partition::<Vec<_>, Vec<_>, _>(|v| v % 2 == 0);
+        assert_eq!(expected.len(), left.len() + right.len());
+
+        test_list.par_iter_mut().for_each(|v| *v *= 2);
+        assert!(expected.iter().map(|v| v * 2).eq(test_list.iter().cloned()));
+
+        let got: Vec<u64> = test_list.into_par_iter().map(|v| v / 2).collect();
+        assert_eq!(expected, got);
+
+        let empty = TList::<u64>::new();
+        assert_eq!(0, empty.par_iter().count());
+    }
+
+    #[test]
+    fn test_par_iter_mut_order() {
+        let mut test_list = TList::<usize>::new();
+        for i in 0..20_000 {
+            test_list.insert(0, i / 2);
+        }
+        test_list.par_iter_mut().with_min_len(7).enumerate().for_each(|(i, v)| *v = i);
+        assert!((0..20_000).eq(test_list.iter().cloned()));
+        test_list.par_iter_mut().rev().enumerate().for_each(|(i, v)| *v = i);
+        assert!((0..20_000).rev().eq(test_list.iter().cloned()));
+        test_list.assert_invariants();
+    }
+
+    #[test]
+    fn test_par_into_iter_drops() {
+        // Every element is either produced or dropped by the producers, exactly once
+        struct Counted(usize, Arc<AtomicUsize>);
+
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.1.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let mut test_list = TList::new();
+        for i in 0..10_000 {
+            test_list.insert(Counted(i, drops.clone()), i / 2);
+        }
+        test_list.remove(5);
+
+        let found = test_list.into_par_iter().with_min_len(16).find_any(|c| c.0 % 1000 == 999);
+        assert_eq!(Some(999), found.as_ref().map(|c| c.0 % 1000));
+        assert_eq!(9_999, drops.load(Ordering::SeqCst));
+        drop(found);
+        assert_eq!(10_000, drops.load(Ordering::SeqCst));
+
+        let empty = TList::<u8>::new();
+        assert_eq!(0, empty.into_par_iter().count());
+    }
+
+    #[test]
+    fn test_par_from_vec() {
+        for len in (0..300).chain(Some(100_000)) {
+            let data: Vec<usize> = (0..len).collect();
+            let mut test_list = TList::par_from_vec(data.clone());
+            test_list.assert_invariants();
+            assert_eq!(len, test_list.len());
+            assert!(data.iter().eq(test_list.iter()));
+
+            test_list.insert(len, len / 2);
+            test_list.remove(0);
+            test_list.assert_invariants();
+        }
+    }
+}
@@ -0,0 +1,455 @@
+// (c) 2017 James Crooks
+//
+// Unified diff parsing, and applying the parsed hunks to a document held as a TList of lines.
+
+//! Unified diffs, as produced by diff -u or git diff, applied to a `TList<String>` holding a
+//! document's lines without their line endings.
+//!
+//! Like GNU patch, a hunk whose context doesn't match at the line numbers it gives is looked
+//! for at the nearest offset which does match, and failing that with up to a fuzz factor of
+//! its outermost context lines ignored. Each hunk either applies whole or is rejected,
+//! leaving the document unchanged, and the others are still applied.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt;
+
+use {Allocator, Change, Dir, Handle, TList};
+
+/// The fuzz factor GNU patch uses by default.
+pub const DEFAULT_FUZZ: usize = 2;
+
+/// The hunks of a unified diff, in order.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Patch {
+    /// The hunks, in the order they appear in the diff.
+    pub hunks: Vec<Hunk>,
+}
+
+/// One hunk of a unified diff, starting with a header such as "@@ -12,7 +12,8 @@".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// The 1-based line number the hunk starts at in the original document, or the line
+    /// it inserts after if it only adds lines.
+    pub old_start: usize,
+    /// The number of original lines the hunk covers.
+    pub old_len: usize,
+    /// The 1-based line number the hunk starts at in the patched document.
+    pub new_start: usize,
+    /// The number of patched lines the hunk covers.
+    pub new_len: usize,
+    /// The lines of the hunk, in order.
+    pub lines: Vec<HunkLine>,
+}
+
+/// A line of a hunk, without its leading marker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HunkLine {
+    /// A line kept as is, marked with a space.
+    Context(String),
+    /// A line removed from the original, marked with a minus.
+    Remove(String),
+    /// A line added by the patch, marked with a plus.
+    Add(String),
+}
+
+/// What became of a hunk when applying a Patch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HunkResult {
+    /// The hunk was applied.
+    Applied {
+        /// How many lines from where the hunk said it applies it was found, after allowing
+        /// for the lines added and removed by the hunks before it.
+        offset: isize,
+        /// How many context lines at each end of the hunk had to be ignored to find it.
+        fuzz: usize,
+    },
+    /// No place in the document matched the hunk, so it was left out.
+    Rejected,
+}
+
+/// A unified diff which couldn't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    line: usize,
+    reason: &'static str,
+}
+
+impl ParseError {
+    /// Returns the 1-based line of the diff at which parsing failed.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid unified diff at line {}: {}", self.line, self.reason)
+    }
+}
+
+impl Error for ParseError {}
+
+impl Patch {
+    /// Parses the hunks of a unified diff. File headers and any other lines between hunks
+    /// are skipped, as are "\ No newline at end of file" markers.
+    pub fn parse(text: &str) -> Result<Patch, ParseError> {
+        let mut hunks = Vec::new();
+        let mut lines = text.lines().enumerate().map(|(n, line)| (n + 1, line)).peekable();
+
+        while let Some((n, line)) = lines.next() {
+            if !line.starts_with("@@ ") {
+                continue;
+            }
+            let (old_start, old_len, new_start, new_len) = parse_header(line)
+                .ok_or(ParseError { line: n, reason: "malformed hunk header" })?;
+
+            let mut hunk = Hunk { old_start, old_len, new_start, new_len, lines: Vec::new() };
+            let (mut old_left, mut new_left) = (old_len, new_len);
+            while old_left > 0 || new_left > 0 {
+                let (n, line) = lines.next()
+                    .ok_or(ParseError { line: n, reason: "hunk ends early" })?;
+                let (marker, rest) = match line.chars().next() {
+                    // Some tools strip the marker from empty context lines
+                    None => (' ', ""),
+                    Some(c) => (c, &line[c.len_utf8()..]),
+                };
+
+                let line = match marker {
+                    ' ' if old_left > 0 && new_left > 0 => {
+                        old_left -= 1;
+                        new_left -= 1;
+                        HunkLine::Context(rest.to_string())
+                    },
+                    '-' if old_left > 0 => {
+                        old_left -= 1;
+                        HunkLine::Remove(rest.to_string())
+                    },
+                    '+' if new_left > 0 => {
+                        new_left -= 1;
+                        HunkLine::Add(rest.to_string())
+                    },
+                    '\\' => continue,
+                    _ => return Err(ParseError { line: n, reason: "line doesn't fit the hunk" }),
+                };
+                hunk.lines.push(line);
+            }
+
+            while let Some(&(_, line)) = lines.peek() {
+                if !line.starts_with('\\') {
+                    break;
+                }
+                lines.next();
+            }
+            hunks.push(hunk);
+        }
+
+        Ok(Patch { hunks })
+    }
+
+    /// Applies the hunks to doc, in order, with GNU patch's default fuzz factor of 2.
+    /// Returns what became of each hunk.
+    pub fn apply<A>(&self, doc: &mut TList<String, A>) -> Vec<HunkResult>
+        where A: Allocator + Clone {
+        self.apply_with_fuzz(doc, DEFAULT_FUZZ)
+    }
+
+    /// Applies the hunks to doc as apply(), ignoring at most max_fuzz context lines at each end
+    /// of a hunk to find where it applies. A max_fuzz of 0 requires all context to match.
+    pub fn apply_with_fuzz<A>(&self, doc: &mut TList<String, A>, max_fuzz: usize) -> Vec<HunkResult>
+        where A: Allocator + Clone {
+        self.apply_observed(doc, max_fuzz, &mut |_| {})
+    }
+
+    pub(crate) fn apply_observed<A>(&self, doc: &mut TList<String, A>, max_fuzz: usize,
+                                    changed: &mut dyn FnMut(Change)) -> Vec<HunkResult>
+        where A: Allocator + Clone {
+        // Applies the hunks as apply_with_fuzz(), passing each line inserted or removed to
+        // changed as it's made, for callers keeping something else in step with doc.
+
+        // Lines added and removed by the hunks applied so far, plus the offset the last one
+        // was found at, which later hunks are likely to share
+        let mut shift: isize = 0;
+        let mut last_offset: isize = 0;
+        // Hunks may not apply on top of lines already patched
+        let mut floor = 0;
+
+        self.hunks.iter().map(|hunk| {
+            let found = (0..=max_fuzz).find_map(|fuzz| {
+                hunk.locate(doc, shift + last_offset, floor, fuzz)
+                    .map(|(pos, lead, trail)| (pos, lead, trail, fuzz))
+            });
+
+            match found {
+                Some((pos, lead, trail, fuzz)) => {
+                    let expected = hunk.old_pos() as isize + shift;
+                    let offset = pos as isize - lead as isize - expected;
+                    floor = hunk.splice(doc, pos, lead, trail, changed);
+                    shift += hunk.new_len as isize - hunk.old_len as isize;
+                    last_offset = offset;
+                    HunkResult::Applied { offset, fuzz }
+                },
+                None => HunkResult::Rejected,
+            }
+        }).collect()
+    }
+}
+
+fn parse_header(line: &str) -> Option<(usize, usize, usize, usize)> {
+    // Parses "@@ -a,b +c,d @@", where either count may be left out, meaning 1
+    let mut parts = line.strip_prefix("@@ ")?.split(' ');
+    let (old_start, old_len) = parse_range(parts.next()?.strip_prefix('-')?)?;
+    let (new_start, new_len) = parse_range(parts.next()?.strip_prefix('+')?)?;
+    if parts.next()? != "@@" {
+        return None;
+    }
+    Some((old_start, old_len, new_start, new_len))
+}
+
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    match range.split_once(',') {
+        Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+        None => Some((range.parse().ok()?, 1)),
+    }
+}
+
+impl Hunk {
+    fn old_pos(&self) -> usize {
+        // The 0-based index of the first original line, where a hunk which only adds lines
+        // gives the line it inserts after
+        if self.old_len == 0 { self.old_start } else { self.old_start.saturating_sub(1) }
+    }
+
+    fn locate<A>(&self, doc: &TList<String, A>, shift: isize, floor: usize, fuzz: usize)
+        -> Option<(usize, usize, usize)> where A: Allocator + Clone {
+        // Looks for the hunk's original lines in doc, less up to fuzz context lines at each
+        // end, trying the nearest offsets from where it's expected first. Returns the index
+        // the remaining lines match at, and how many leading and trailing lines were dropped.
+        let is_context = |line: &&HunkLine| matches!(**line, HunkLine::Context(_));
+        let lead = self.lines.iter().take_while(is_context).count().min(fuzz);
+        let trail = self.lines.iter().rev().take_while(is_context).count().min(fuzz);
+        if fuzz > 0 && lead < fuzz && trail < fuzz {
+            // Nothing more to drop than at a lower fuzz
+            return None;
+        }
+        let lines = &self.lines[lead..self.lines.len() - trail];
+        let old: Vec<&str> = lines.iter().filter_map(|l| match *l {
+            HunkLine::Context(ref s) | HunkLine::Remove(ref s) => Some(s.as_str()),
+            HunkLine::Add(_) => None,
+        }).collect();
+
+        let expected = self.old_pos() as isize + shift + lead as isize;
+        let last = doc.len().checked_sub(old.len())? as isize;
+
+        // The lines are compared by walking the nodes from where they start, and the start
+        // moves outwards one node at a time on each side, so each candidate costs O(L) for L
+        // lines rather than a search from the root for every line
+        let mut cursors: [Option<(isize, usize)>; 2] = [None, None];
+        let mut matches = |side: usize, pos: isize| {
+            if pos < floor as isize || pos > last {
+                return false;
+            }
+            if old.is_empty() {
+                return true;
+            }
+
+            let start = match cursors[side] {
+                Some((prev, slot)) if prev + 1 == pos => doc.neighbor(slot, Dir::Right),
+                Some((prev, slot)) if prev - 1 == pos => doc.neighbor(slot, Dir::Left),
+                _ => doc.search(pos as usize),
+            };
+            cursors[side] = start.map(|slot| (pos, slot));
+            let mut next = start;
+            old.iter().all(|line| match next {
+                Some(slot) => {
+                    next = doc.neighbor(slot, Dir::Right);
+                    doc.get_by_handle(Handle(slot)).map(String::as_str) == Some(*line)
+                },
+                None => false,
+            })
+        };
+
+        let reach = (expected - floor as isize).max(last - expected);
+        for distance in 0..=reach.max(0) {
+            if matches(0, expected - distance) {
+                return Some(((expected - distance) as usize, lead, trail));
+            }
+            if distance > 0 && matches(1, expected + distance) {
+                return Some(((expected + distance) as usize, lead, trail));
+            }
+        }
+        None
+    }
+
+    fn splice<A>(&self, doc: &mut TList<String, A>, mut pos: usize, lead: usize, trail: usize,
+                 changed: &mut dyn FnMut(Change)) -> usize where A: Allocator + Clone {
+        // Applies the hunk's lines, less those dropped for fuzz, at pos, which they were found
+        // to match. Returns the index just past the patched lines.
+        for line in &self.lines[lead..self.lines.len() - trail] {
+            match *line {
+                HunkLine::Context(_) => pos += 1,
+                HunkLine::Remove(_) => {
+                    doc.remove(pos);
+                    changed(Change::Removed { index: pos, count: 1 });
+                },
+                HunkLine::Add(ref s) => {
+                    doc.insert(s.clone(), pos);
+                    changed(Change::Inserted { index: pos, count: 1 });
+                    pos += 1;
+                },
+            }
+        }
+        pos
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HunkLine, HunkResult, Patch};
+    use {diff_ranges, Delta, TList};
+
+    fn doc(text: &str) -> TList<String> {
+        let lines: Vec<String> = text.lines().map(String::from).collect();
+        TList::<String>::from_data(&lines)
+    }
+
+    fn text(doc: &TList<String>) -> String {
+        doc.iter().map(|l| format!("{}\n", l)).collect()
+    }
+
+    const ORIGINAL: &str = "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten\n";
+
+    const PATCH: &str = "\
+--- a/numbers.txt
++++ b/numbers.txt
+@@ -1,4 +1,4 @@
+ one
+-two
++TWO
+ three
+ four
+@@ -7,3 +7,5 @@ six
+ seven
+ eight
++eight and a half
++eight and three quarters
+ nine
+\\ No newline at end of file
+";
+
+    #[test]
+    fn test_parse() {
+        let patch = Patch::parse(PATCH).unwrap();
+        assert_eq!(2, patch.hunks.len());
+        assert_eq!((7, 3, 7, 5), (patch.hunks[1].old_start, patch.hunks[1].old_len,
+                                  patch.hunks[1].new_start, patch.hunks[1].new_len));
+        assert_eq!(vec![
+            HunkLine::Context("one".to_string()),
+            HunkLine::Remove("two".to_string()),
+            HunkLine::Add("TWO".to_string()),
+            HunkLine::Context("three".to_string()),
+            HunkLine::Context("four".to_string()),
+        ], patch.hunks[0].lines);
+
+        let single = Patch::parse("@@ -3 +3 @@\n-x\n+y\n").unwrap();
+        assert_eq!((3, 1, 3, 1), (single.hunks[0].old_start, single.hunks[0].old_len,
+                                  single.hunks[0].new_start, single.hunks[0].new_len));
+
+        assert_eq!(1, Patch::parse("@@ -1,2 +1,2 x\n").unwrap_err().line());
+        assert_eq!(1, Patch::parse("@@ -1,2 +1,2 @@\n a\n").unwrap_err().line());
+        assert_eq!(4, Patch::parse("@@ -1,2 +1,2 @@\n a\n+b\n+c\n").unwrap_err().line());
+        assert!(Patch::parse("no hunks here\n").unwrap().hunks.is_empty());
+    }
+
+    #[test]
+    fn test_apply_exact() {
+        let mut test_doc = doc(ORIGINAL);
+        let results = Patch::parse(PATCH).unwrap().apply(&mut test_doc);
+        assert_eq!(vec![HunkResult::Applied { offset: 0, fuzz: 0 }; 2], results);
+        assert_eq!("one\nTWO\nthree\nfour\nfive\nsix\nseven\neight\neight and a half\n\
+                    eight and three quarters\nnine\nten\n", text(&test_doc));
+        test_doc.assert_invariants();
+    }
+
+    #[test]
+    fn test_apply_offset_fuzz_reject() {
+        // Two lines added at the top shift both hunks down, and a changed context line
+        // needs fuzz to get past
+        let mut test_doc = doc(&format!("zero\nhalf\n{}", ORIGINAL.replace("seven", "SEVEN")));
+        let results = Patch::parse(PATCH).unwrap().apply(&mut test_doc);
+        assert_eq!(vec![
+            HunkResult::Applied { offset: 2, fuzz: 0 },
+            HunkResult::Applied { offset: 2, fuzz: 1 },
+        ], results);
+        assert!(text(&test_doc).contains("TWO\n"));
+        assert!(text(&test_doc).contains("eight\neight and a half\n"));
+
+        let mut strict = doc(&format!("zero\nhalf\n{}", ORIGINAL.replace("seven", "SEVEN")));
+        let results = Patch::parse(PATCH).unwrap().apply_with_fuzz(&mut strict, 0);
+        assert_eq!(HunkResult::Rejected, results[1]);
+        assert!(text(&strict).contains("TWO\n"));
+        assert!(!text(&strict).contains("eight and"));
+
+        // A hunk whose removed line is gone is rejected, leaving the document as it was
+        let mut gone = doc(&ORIGINAL.replace("two", "deux"));
+        let results = Patch::parse(PATCH).unwrap().apply(&mut gone);
+        assert_eq!(HunkResult::Rejected, results[0]);
+        assert!(text(&gone).starts_with("one\ndeux\nthree\n"));
+    }
+
+    #[test]
+    fn test_apply_far_offsets() {
+        // Hunks found far above and below their line numbers, with near misses on the way
+        let numbers: String = (0..1000).map(|i| format!("{}\n", i % 500)).collect();
+        let mut test_doc = doc(&numbers);
+        let patch = Patch::parse("@@ -11,3 +11,3 @@\n 300\n-301\n+X\n 302\n\
+                                  @@ -901,2 +901,2 @@\n-100\n+Y\n 101\n").unwrap();
+        assert_eq!(vec![
+            HunkResult::Applied { offset: 290, fuzz: 0 },
+            HunkResult::Applied { offset: -300, fuzz: 0 },
+        ], patch.apply(&mut test_doc));
+        assert_eq!(Some("X"), test_doc.get(301).map(String::as_str));
+        assert_eq!(Some("Y"), test_doc.get(600).map(String::as_str));
+        assert_eq!(Some("301"), test_doc.get(801).map(String::as_str));
+        test_doc.assert_invariants();
+    }
+
+    #[test]
+    fn test_apply_insert_only() {
+        let mut empty = TList::<String>::new();
+        let results = Patch::parse("@@ -0,0 +1,2 @@\n+first\n+second\n").unwrap().apply(&mut empty);
+        assert_eq!(vec![HunkResult::Applied { offset: 0, fuzz: 0 }], results);
+        assert_eq!("first\nsecond\n", text(&empty));
+    }
+
+    #[test]
+    fn test_apply_matches_diff() {
+        // A patch written from a diff's Deltas turns the original into the edited document
+        let original = doc(ORIGINAL);
+        let edited = doc("zero\none\nthree\nfour\nFIVE\nsix\nseven\nnine\nten\neleven\n");
+        let mut patch_text = String::new();
+        for delta in diff_ranges(&original, &edited) {
+            let (index, removed, added) = match delta {
+                Delta::ReplaceRange { index, values } => (index, values.len(), values),
+                Delta::DeleteRange { index, count } => (index, count, Vec::new()),
+                Delta::InsertRange { index, values } => (index, 0, values),
+                _ => unreachable!(),
+            };
+            let start = if removed == 0 { index } else { index + 1 };
+            patch_text += &format!("@@ -{},{} +{},{} @@\n", start, removed, start, added.len());
+            for i in index..index + removed {
+                patch_text += &format!("-{}\n", original.get(i).unwrap());
+            }
+            for line in added {
+                patch_text += &format!("+{}\n", line);
+            }
+        }
+
+        // Each hunk's line numbers are relative to the original, as the Deltas' indexes are
+        let mut test_doc = original.clone();
+        let results = Patch::parse(&patch_text).unwrap().apply_with_fuzz(&mut test_doc, 0);
+        assert!(results.iter().all(|r| matches!(r, HunkResult::Applied { .. })), "{:?}", results);
+        assert_eq!(text(&edited), text(&test_doc));
+    }
+}
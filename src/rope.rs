@@ -0,0 +1,593 @@
+// (c) 2017 James Crooks
+//
+// A rope of text built on a TList of string chunks.
+
+//! TRope, a text rope indexed by bytes, chars and lines in O(log N) time.
+
+use alloc::string::String;
+use core::fmt;
+use core::ops::Range;
+
+use {corrupted, Dir, Handle, TList, TListError};
+
+// The most bytes a chunk holds. Chunks are split when an insertion overfills them and merged
+// with a neighbor when a removal leaves two adjacent chunks which fit in one.
+const MAX_CHUNK: usize = 1024;
+
+/// A rope of UTF-8 text for large documents, including ones which are a single huge line. The
+/// text is held in chunks of up to a kilobyte in a TList weighted by their bytes, and each
+/// node also caches the chars and newlines in its sub-tree, so that converting between byte,
+/// char and line offsets takes O(log N) time in the number of chunks, as do insert() and
+/// remove() when editing within a chunk's length of text.
+///
+/// Lines are counted by "\n", so a rope of len_bytes() zero has one line, and text ending in
+/// "\n" has an empty last line.
+#[derive(Debug, Clone)]
+pub struct TRope {
+    tree: TList<Chunk>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Chunk {
+    text: String,
+    chars: usize,
+    lines: usize,
+    // The chars and newlines of this chunk's whole sub-tree, kept up to date by the TList
+    sub_chars: usize,
+    sub_lines: usize,
+}
+
+impl Chunk {
+    fn new(text: String) -> Chunk {
+        let mut chunk = Chunk { text, ..Chunk::default() };
+        chunk.recount();
+        chunk
+    }
+
+    fn recount(&mut self) {
+        self.chars = self.text.chars().count();
+        self.lines = self.text.bytes().filter(|&b| b == b'\n').count();
+    }
+}
+
+// The measures of a run of text, as summed up by the TList
+#[derive(Debug, Clone, Copy, Default)]
+struct Totals {
+    bytes: usize,
+    chars: usize,
+    lines: usize,
+}
+
+impl TRope {
+    /// Generate a new, empty TRope.
+    pub fn new() -> TRope {
+        let mut tree = TList::new();
+        tree.augment = Some(summarize);
+        TRope { tree }
+    }
+
+    /// Returns the length of the text in bytes.
+    pub fn len_bytes(&self) -> usize {
+        self.tree.total_weight()
+    }
+
+    /// Returns the length of the text in chars.
+    pub fn len_chars(&self) -> usize {
+        self.totals().chars
+    }
+
+    /// Returns the number of lines, which is one more than the number of "\n"s.
+    pub fn len_lines(&self) -> usize {
+        self.totals().lines + 1
+    }
+
+    /// Returns true if the rope holds no text.
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Returns the line containing the given byte offset, which is the number of "\n"s before
+    /// it. Runs in O(log N) time.
+    ///
+    /// Panics if byte is past len_bytes().
+    pub fn byte_to_line(&self, byte: usize) -> usize {
+        assert!(byte <= self.len_bytes(), "byte offset {} is past the end of the rope", byte);
+        match self.seek(byte, |t| t.bytes) {
+            Some((slot, before)) => {
+                let text = &self.chunk(slot).text;
+                before.lines + text.as_bytes()[..byte - before.bytes].iter().filter(|&&b| b == b'\n').count()
+            },
+            None => self.totals().lines,
+        }
+    }
+
+    /// Returns the byte offset at which the given line starts. line may be len_lines(), which
+    /// starts at len_bytes(). Runs in O(log N) time.
+    ///
+    /// Panics if line is past len_lines().
+    pub fn line_to_byte(&self, line: usize) -> usize {
+        assert!(line <= self.len_lines(), "line {} is past the end of the rope", line);
+        if line == 0 {
+            return 0;
+        }
+
+        // Line n starts just after the (n - 1)th newline, counting from 0
+        match self.seek(line - 1, |t| t.lines) {
+            Some((slot, before)) => {
+                let newline = self.chunk(slot).text
+                    .match_indices('\n')
+                    .nth(line - 1 - before.lines)
+                    .map_or(0, |(offset, _)| offset);
+                before.bytes + newline + 1
+            },
+            None => self.len_bytes(),
+        }
+    }
+
+    /// Returns the byte offset of the given char offset. Runs in O(log N) time.
+    ///
+    /// Panics if char_idx is past len_chars().
+    pub fn char_to_byte(&self, char_idx: usize) -> usize {
+        assert!(char_idx <= self.len_chars(), "char index {} is past the end of the rope", char_idx);
+        match self.seek(char_idx, |t| t.chars) {
+            Some((slot, before)) => before.bytes + char_offset(&self.chunk(slot).text, char_idx - before.chars),
+            None => self.len_bytes(),
+        }
+    }
+
+    /// Returns the char offset of the given byte offset. Runs in O(log N) time.
+    ///
+    /// Panics if byte is past len_bytes() or isn't on a char boundary.
+    pub fn byte_to_char(&self, byte: usize) -> usize {
+        assert!(byte <= self.len_bytes(), "byte offset {} is past the end of the rope", byte);
+        match self.seek(byte, |t| t.bytes) {
+            Some((slot, before)) => {
+                let text = &self.chunk(slot).text;
+                let offset = byte - before.bytes;
+                assert!(text.is_char_boundary(offset), "byte offset {} is inside a char", byte);
+                before.chars + text[..offset].chars().count()
+            },
+            None => self.len_chars(),
+        }
+    }
+
+    /// Returns a copy of the text in the given range of chars. Runs in O(log N + M) time for
+    /// M bytes of text.
+    ///
+    /// Panics if the range ends before it starts or past len_chars().
+    pub fn slice(&self, range: Range<usize>) -> String {
+        assert!(range.start <= range.end && range.end <= self.len_chars(),
+                "char range {:?} is out of bounds for a rope of {} chars", range, self.len_chars());
+        let mut text = String::new();
+        let Some((mut slot, before)) = self.seek(range.start, |t| t.chars) else {
+            return text;
+        };
+
+        let mut skip = range.start - before.chars;
+        let mut remaining = range.end - range.start;
+        while remaining > 0 {
+            let chunk = &self.chunk(slot).text;
+            let start = char_offset(chunk, skip);
+            let taken = remaining.min(chunk[start..].chars().count());
+            let end = start + char_offset(&chunk[start..], taken);
+            text.push_str(&chunk[start..end]);
+
+            remaining -= taken;
+            skip = 0;
+            match self.tree.neighbor(slot, Dir::Right) {
+                Some(next) => slot = next,
+                None => break,
+            }
+        }
+        text
+    }
+
+    /// Inserts text at the given char offset, which may be len_chars() to append. Runs in
+    /// O(log N) time for text shorter than a chunk, splitting the chunk it goes into if it
+    /// overflows, and O(M log N) for M bytes of text in general.
+    ///
+    /// Panics if char_idx is past len_chars().
+    pub fn insert(&mut self, char_idx: usize, text: &str) {
+        assert!(char_idx <= self.len_chars(), "char index {} is past the end of the rope", char_idx);
+        self.try_insert(char_idx, text).unwrap_or_else(|err| panic!("{}", err));
+    }
+
+    /// Removes the text in the given range of chars. Runs in O(log N) time for a range within
+    /// a chunk or two, and O(K log N) when it spans K chunks.
+    ///
+    /// Panics if the range ends before it starts or past len_chars().
+    pub fn remove(&mut self, range: Range<usize>) {
+        assert!(range.start <= range.end && range.end <= self.len_chars(),
+                "char range {:?} is out of bounds for a rope of {} chars", range, self.len_chars());
+        self.try_remove(range).unwrap_or_else(|err| panic!("{}", err));
+    }
+
+    /// Checks the Red-Black invariants of the underlying tree, and that every node's size and
+    /// cached counts sum up its sub-tree, panicking with a description of the first violation
+    /// found. Only compiled for tests and fuzzing builds.
+    #[cfg(any(test, fuzzing))]
+    pub fn assert_invariants(&self) {
+        let total = self.tree.assert_weighted_invariants(&|chunk| {
+            assert!(!chunk.text.is_empty(), "empty chunk");
+            assert!(chunk.text.len() <= MAX_CHUNK, "overfull chunk");
+            chunk.text.len()
+        });
+        assert_eq!(self.len_bytes(), total);
+
+        for slot in self.tree.in_order() {
+            let chunk = self.chunk(slot);
+            let mut expected = Chunk::new(chunk.text.clone());
+            assert_eq!((expected.chars, expected.lines), (chunk.chars, chunk.lines), "stale chunk counts");
+            for dir in [Dir::Left, Dir::Right] {
+                if let Some(child) = self.tree.get_child_idx(slot, dir) {
+                    expected.chars += self.chunk(child).sub_chars;
+                    expected.lines += self.chunk(child).sub_lines;
+                }
+            }
+            assert_eq!((expected.chars, expected.lines), (chunk.sub_chars, chunk.sub_lines), "stale sub-tree counts");
+        }
+    }
+
+    fn try_insert(&mut self, char_idx: usize, text: &str) -> Result<(), TListError> {
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        // Appends go at the end of the last chunk rather than the start of a missing one
+        let (slot, offset) = if char_idx < self.len_chars() {
+            match self.seek(char_idx, |t| t.chars) {
+                Some((slot, before)) => (slot, char_offset(&self.try_chunk(slot)?.text, char_idx - before.chars)),
+                None => return Err(corrupted(self.tree.root_idx, "No chunk at char index!")),
+            }
+        } else if char_idx > 0 {
+            match self.seek(char_idx - 1, |t| t.chars) {
+                Some((slot, _)) => (slot, self.try_chunk(slot)?.text.len()),
+                None => return Err(corrupted(self.tree.root_idx, "No last chunk!")),
+            }
+        } else {
+            return self.insert_chunks(None, text);
+        };
+
+        let chunk = self.chunk_mut(slot)?;
+        let old_len = chunk.text.len();
+        if old_len + text.len() <= MAX_CHUNK {
+            chunk.text.insert_str(offset, text);
+            chunk.recount();
+            return self.tree.adjust_weight(slot, old_len, old_len + text.len());
+        }
+
+        // Otherwise text at the start of the chunk goes on the end of the one before if it
+        // fits, or else into new chunks between them, so that typing at a chunk boundary
+        // doesn't leave a chunk per keystroke. Elsewhere the chunk is cut at offset, with the
+        // text and the tail going into new chunks after it.
+        if offset == 0 {
+            let prev = self.tree.neighbor(slot, Dir::Left);
+            if let Some(prev) = prev {
+                let chunk = self.chunk_mut(prev)?;
+                let prev_len = chunk.text.len();
+                if prev_len + text.len() <= MAX_CHUNK {
+                    chunk.text.push_str(text);
+                    chunk.recount();
+                    return self.tree.adjust_weight(prev, prev_len, prev_len + text.len());
+                }
+            }
+            return self.insert_chunks(prev, text);
+        }
+        let mut rest = chunk.text.split_off(offset);
+        rest.insert_str(0, text);
+        chunk.recount();
+        self.tree.adjust_weight(slot, old_len, offset)?;
+        self.insert_chunks(Some(slot), &rest)
+    }
+
+    fn insert_chunks(&mut self, mut prev: Option<usize>, text: &str) -> Result<(), TListError> {
+        // Links text in after the chunk at prev, in chunks of even length. Each cut steps back
+        // to a char boundary, so the chunks still to come are counted again from what's left.
+        let mut rest = text;
+        while !rest.is_empty() {
+            let mut end = rest.len().div_ceil(rest.len().div_ceil(MAX_CHUNK));
+            while !rest.is_char_boundary(end) {
+                end -= 1;
+            }
+            let (piece, tail) = rest.split_at(end);
+            prev = Some(self.tree.insert_after(prev, Chunk::new(piece.into()), piece.len())?);
+            rest = tail;
+        }
+        Ok(())
+    }
+
+    fn try_remove(&mut self, range: Range<usize>) -> Result<(), TListError> {
+        let mut remaining = range.end - range.start;
+        while remaining > 0 {
+            let Some((slot, before)) = self.seek(range.start, |t| t.chars) else {
+                return Err(corrupted(self.tree.root_idx, "Removal past the end of the rope!"));
+            };
+
+            let chunk = self.chunk_mut(slot)?;
+            let skip = range.start - before.chars;
+            let taken = remaining.min(chunk.chars - skip);
+            let start = char_offset(&chunk.text, skip);
+            let end = start + char_offset(&chunk.text[start..], taken);
+            let old_len = chunk.text.len();
+
+            // Node sizes can't be zero, so an emptied chunk is unlinked at its old weight
+            if end - start == old_len {
+                self.tree.remove_slot(slot)?;
+            } else {
+                chunk.text.replace_range(start..end, "");
+                chunk.recount();
+                self.tree.adjust_weight(slot, old_len, old_len - (end - start))?;
+            }
+            remaining -= taken;
+        }
+
+        // Merge the chunks either side of the cut if they fit in one
+        if range.start > 0 {
+            if let Some((slot, _)) = self.seek(range.start - 1, |t| t.chars) {
+                self.merge(slot)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self, slot: usize) -> Result<(), TListError> {
+        // Merges the chunk at slot with the following chunk if they fit in one
+        let Some(next) = self.tree.neighbor(slot, Dir::Right) else {
+            return Ok(());
+        };
+        let (len, next_len) = (self.try_chunk(slot)?.text.len(), self.try_chunk(next)?.text.len());
+        if len + next_len > MAX_CHUNK {
+            return Ok(());
+        }
+
+        let next_chunk = self.tree.remove_slot(next)?;
+        let chunk = self.chunk_mut(slot)?;
+        chunk.text.push_str(&next_chunk.text);
+        chunk.recount();
+        self.tree.adjust_weight(slot, len, len + next_len)
+    }
+
+    fn seek<F>(&self, mut target: usize, measure: F) -> Option<(usize, Totals)> where F: Fn(&Totals) -> usize {
+        // Finds the chunk containing the target'th unit of the given measure, returning its
+        // slot and the totals of all the text before it, or None if target is past the end.
+        if self.tree.is_empty() || target >= measure(&self.totals()) {
+            return None;
+        }
+
+        let mut before = Totals::default();
+        let mut search_idx = self.tree.root_idx;
+        loop {
+            let left = self.tree.get_child_idx(search_idx, Dir::Left).map(|idx| self.sub_totals(idx)).unwrap_or_default();
+            if target < measure(&left) {
+                search_idx = self.tree.get_child_idx(search_idx, Dir::Left)?;
+                continue;
+            }
+
+            let chunk = self.chunk(search_idx);
+            let own = Totals { bytes: chunk.text.len(), chars: chunk.chars, lines: chunk.lines };
+            before.bytes += left.bytes;
+            before.chars += left.chars;
+            before.lines += left.lines;
+            if target < measure(&left) + measure(&own) {
+                return Some((search_idx, before));
+            }
+
+            target -= measure(&left) + measure(&own);
+            before.bytes += own.bytes;
+            before.chars += own.chars;
+            before.lines += own.lines;
+            search_idx = self.tree.get_child_idx(search_idx, Dir::Right)?;
+        }
+    }
+
+    fn totals(&self) -> Totals {
+        if self.tree.is_empty() {
+            Totals::default()
+        } else {
+            self.sub_totals(self.tree.root_idx)
+        }
+    }
+
+    fn sub_totals(&self, slot: usize) -> Totals {
+        let chunk = self.chunk(slot);
+        let bytes = self.tree.node_list[slot].as_ref().map_or(0, |n| n.size());
+        Totals { bytes, chars: chunk.sub_chars, lines: chunk.sub_lines }
+    }
+
+    #[inline]
+    fn chunk(&self, slot: usize) -> &Chunk {
+        // Queries have no error to return, so a missing chunk is reported by panicking
+        self.try_chunk(slot).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    #[inline]
+    fn try_chunk(&self, slot: usize) -> Result<&Chunk, TListError> {
+        match self.tree.get_by_handle(Handle(slot)) {
+            Some(chunk) => Ok(chunk),
+            None => Err(corrupted(slot, "No chunk at slot!")),
+        }
+    }
+
+    #[inline]
+    fn chunk_mut(&mut self, slot: usize) -> Result<&mut Chunk, TListError> {
+        match self.tree.get_mut_by_handle(Handle(slot)) {
+            Some(chunk) => Ok(chunk),
+            None => Err(corrupted(slot, "No chunk at slot!")),
+        }
+    }
+}
+
+fn summarize(tree: &mut TList<Chunk>, slot: usize) {
+    // The TList's augment hook, which recounts a chunk's sub-tree from its children
+    let mut sub = (0, 0);
+    for dir in [Dir::Left, Dir::Right] {
+        if let Some(child) = tree.get_child_idx(slot, dir).and_then(|idx| tree.node_list[idx].as_ref()) {
+            sub.0 += child.data.sub_chars;
+            sub.1 += child.data.sub_lines;
+        }
+    }
+    if let Some(node) = tree.node_list[slot].as_mut() {
+        node.data.sub_chars = node.data.chars + sub.0;
+        node.data.sub_lines = node.data.lines + sub.1;
+    }
+}
+
+fn char_offset(text: &str, chars: usize) -> usize {
+    // The byte offset of the given char offset into text, which may be its length
+    text.char_indices().nth(chars).map_or(text.len(), |(offset, _)| offset)
+}
+
+impl Default for TRope {
+    fn default() -> TRope {
+        TRope::new()
+    }
+}
+
+impl<'a> From<&'a str> for TRope {
+    fn from(text: &'a str) -> TRope {
+        let mut rope = TRope::new();
+        rope.insert(0, text);
+        rope
+    }
+}
+
+impl fmt::Display for TRope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for slot in self.tree.in_order() {
+            f.write_str(&self.chunk(slot).text)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::Rng;
+    use super::{TRope, MAX_CHUNK};
+
+    fn random_text<R: Rng>(rng: &mut R, len: usize) -> String {
+        let alphabet: Vec<char> = "abc é😀\n".chars().collect();
+        (0..len).map(|_| *rng.choose(&alphabet).unwrap()).collect()
+    }
+
+    fn assert_metrics(rope: &TRope, model: &str) {
+        // Checks every conversion against the model string
+        assert_eq!(model, rope.to_string());
+        assert_eq!(model.len(), rope.len_bytes());
+        assert_eq!(model.chars().count(), rope.len_chars());
+        assert_eq!(model.matches('\n').count() + 1, rope.len_lines());
+
+        let mut line_starts = vec![0];
+        line_starts.extend(model.match_indices('\n').map(|(offset, _)| offset + 1));
+        for (line, &start) in line_starts.iter().enumerate() {
+            assert_eq!(start, rope.line_to_byte(line));
+        }
+        assert_eq!(model.len(), rope.line_to_byte(rope.len_lines()));
+
+        let chars = model.char_indices().map(|(offset, _)| offset).chain(Some(model.len()));
+        for (char_idx, byte) in chars.enumerate() {
+            assert_eq!(byte, rope.char_to_byte(char_idx));
+            assert_eq!(char_idx, rope.byte_to_char(byte));
+            assert_eq!(model[..byte].matches('\n').count(), rope.byte_to_line(byte));
+        }
+    }
+
+    #[test]
+    fn test_rope_metrics() {
+        let rope = TRope::new();
+        assert!(rope.is_empty());
+        assert_metrics(&rope, "");
+
+        let text = "one\ntwö\n\n😀 three";
+        let rope = TRope::from(text);
+        assert_metrics(&rope, text);
+        assert_eq!("twö\n", rope.slice(4..8));
+        assert_eq!("", rope.slice(3..3));
+        rope.assert_invariants();
+    }
+
+    #[test]
+    fn test_rope_single_long_line() {
+        // A line many chunks long, edited in the middle
+        let mut model: String = "abcdé".repeat(MAX_CHUNK);
+        let mut rope = TRope::from(model.as_str());
+        rope.assert_invariants();
+        assert_eq!(1, rope.len_lines());
+
+        rope.insert(2500, "\nmiddle\n");
+        model.insert_str(model.char_indices().nth(2500).unwrap().0, "\nmiddle\n");
+        assert_eq!(3, rope.len_lines());
+        assert_eq!(model.find("middle").unwrap(), rope.line_to_byte(1));
+
+        rope.remove(100..4000);
+        let (start, end) = (model.char_indices().nth(100).unwrap().0, model.char_indices().nth(4000).unwrap().0);
+        model.replace_range(start..end, "");
+        assert_metrics(&rope, &model);
+        rope.assert_invariants();
+    }
+
+    #[test]
+    fn test_rope_multibyte_chunks() {
+        // Cuts at 1024 bytes fall inside a 3 byte char, in both a fresh rope and a chunk
+        // split by an insertion
+        let text = format!("aa{}", "€".repeat(682));
+        let mut rope = TRope::from(text.as_str());
+        rope.assert_invariants();
+        assert_eq!(text, rope.to_string());
+
+        rope.insert(1, &"€".repeat(400));
+        rope.insert(1000, "b€");
+        rope.assert_invariants();
+        let mut expected: Vec<char> = text.chars().collect();
+        expected.splice(1..1, "€".repeat(400).chars());
+        expected.splice(1000..1000, "b€".chars());
+        assert_eq!(expected.into_iter().collect::<String>(), rope.to_string());
+        assert_eq!(1, rope.len_lines());
+    }
+
+    #[test]
+    fn test_rope_typing_at_chunk_boundary() {
+        // Typing at the boundary of two full chunks fills one new chunk between them
+        let mut rope = TRope::from("a".repeat(2 * MAX_CHUNK).as_str());
+        assert_eq!(2, rope.tree.len());
+        for i in 0..100 {
+            rope.insert(MAX_CHUNK + i, "b");
+        }
+        assert_eq!(3, rope.tree.len());
+        rope.assert_invariants();
+        assert_eq!(format!("{}{}{}", "a".repeat(MAX_CHUNK), "b".repeat(100), "a".repeat(MAX_CHUNK)), rope.to_string());
+    }
+
+    #[test]
+    fn test_rope_random() {
+        let mut rng = rand::thread_rng();
+        let mut model = String::new();
+        let mut rope = TRope::new();
+
+        for _ in 0..300 {
+            let chars = model.chars().count();
+            if chars == 0 || rng.gen_weighted_bool(2) {
+                let len = if rng.gen_weighted_bool(10) { rng.gen_range(0, 3 * MAX_CHUNK) } else { rng.gen_range(0, 20) };
+                let text = random_text(&mut rng, len);
+                let char_idx = rng.gen_range(0, chars + 1);
+                rope.insert(char_idx, &text);
+                let byte = model.char_indices().nth(char_idx).map_or(model.len(), |(offset, _)| offset);
+                model.insert_str(byte, &text);
+            } else {
+                let start = rng.gen_range(0, chars + 1);
+                let span = rng.gen_range(0, 2 * MAX_CHUNK);
+                let end = rng.gen_range(start, (start + span).min(chars) + 1);
+                let byte = |c: usize| model.char_indices().nth(c).map_or(model.len(), |(offset, _)| offset);
+                let (start_byte, end_byte) = (byte(start), byte(end));
+                assert_eq!(&model[start_byte..end_byte], rope.slice(start..end));
+                rope.remove(start..end);
+                model.replace_range(start_byte..end_byte, "");
+            }
+            rope.assert_invariants();
+            assert_eq!(model, rope.to_string());
+        }
+        assert_metrics(&rope, &model);
+    }
+}
@@ -0,0 +1,262 @@
+// (c) 2017 James Crooks
+//
+// A TList shared between one writer and many reader threads, enabled by the "std" feature.
+
+//! SharedTList, which hands readers on other threads consistent snapshots of a TList.
+//!
+//! Versions are swapped in RCU style: each published version of the list is immutable and
+//! reference counted, read() only holds a lock for as long as it takes to bump that count,
+//! and a version is freed once the last reader holding it lets go. Readers therefore never
+//! see a version while it is being edited, and never wait on the writer's edits.
+
+use std::mem;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+use TList;
+
+/// A TList shared between threads, where any thread can take a consistent, immutable
+/// Snapshot of the current version with read() and writers publish whole new versions.
+///
+/// A writer makes its edits outside of any lock, either on a TList of its own which it hands
+/// to publish(), or on a private copy of the current version through update(). Readers only
+/// contend with the writer for the moment it takes to swap the new version in, and a reader
+/// which holds on to a Snapshot keeps seeing that version, unaffected by later ones.
+///
+/// SharedTList<T> is Send and Sync when T is Send and Sync, as each version may be read from
+/// several threads at once and freed on whichever thread drops it last.
+#[derive(Debug)]
+pub struct SharedTList<T> {
+    current: RwLock<Snapshot<T>>,
+    // Serializes writers, so that concurrent calls to update() don't lose each other's edits
+    writer: Mutex<()>,
+}
+
+/// An immutable version of the list in a SharedTList, which dereferences to the TList.
+///
+/// Cloning a Snapshot is O(1) and shares the same version. Snapshot<T> is Send and Sync when
+/// T is Send and Sync.
+#[derive(Debug)]
+pub struct Snapshot<T> {
+    list: Arc<TList<T>>,
+    version: u64,
+}
+
+impl<T> SharedTList<T> {
+    /// Shares list as the first version, numbered 0.
+    pub fn new(list: TList<T>) -> SharedTList<T> {
+        SharedTList {
+            current: RwLock::new(Snapshot { list: Arc::new(list), version: 0 }),
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// Returns a Snapshot of the current version of the list in O(1) time, which stays
+    /// valid and unchanged however long it is held.
+    pub fn read(&self) -> Snapshot<T> {
+        // A version is swapped in whole, so a lock poisoned by a panicking thread still
+        // guards a consistent version
+        self.current.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Returns the number of the current version, which counts the versions published so far.
+    pub fn version(&self) -> u64 {
+        self.current.read().unwrap_or_else(PoisonError::into_inner).version
+    }
+
+    /// Replaces the current version with list, returning its version number. Readers holding
+    /// a Snapshot of an older version keep it until they drop it.
+    pub fn publish(&self, list: TList<T>) -> u64 {
+        let _writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        self.swap(list)
+    }
+
+    /// Applies f to a copy of the current version and publishes the result, returning the
+    /// value returned by f. The copy takes O(N) time, during which readers are not blocked;
+    /// a writer making many small edits can keep its own TList and publish() it as needed.
+    ///
+    /// Calls to update() and publish() from several threads are applied one at a time, so
+    /// that each update() sees the edits of the ones before it.
+    pub fn update<F, R>(&self, f: F) -> R where F: FnOnce(&mut TList<T>) -> R, T: Clone {
+        let _writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut list = TList::clone(&self.read());
+        let result = f(&mut list);
+        self.swap(list);
+        result
+    }
+
+    fn swap(&self, list: TList<T>) -> u64 {
+        let (version, old) = {
+            let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+            let version = current.version + 1;
+            (version, mem::replace(&mut *current, Snapshot { list: Arc::new(list), version }))
+        };
+        // The old version, if no reader holds it, is freed here rather than under the lock
+        drop(old);
+        version
+    }
+}
+
+impl<T> Default for SharedTList<T> {
+    fn default() -> SharedTList<T> {
+        SharedTList::new(TList::new())
+    }
+}
+
+impl<T> From<TList<T>> for SharedTList<T> {
+    fn from(list: TList<T>) -> SharedTList<T> {
+        SharedTList::new(list)
+    }
+}
+
+impl<T> Snapshot<T> {
+    /// Returns the number of the version this Snapshot was taken of.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+}
+
+impl<T> Clone for Snapshot<T> {
+    fn clone(&self) -> Snapshot<T> {
+        Snapshot { list: Arc::clone(&self.list), version: self.version }
+    }
+}
+
+impl<T> Deref for Snapshot<T> {
+    type Target = TList<T>;
+
+    fn deref(&self) -> &TList<T> {
+        &self.list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    use super::{SharedTList, Snapshot};
+    use TList;
+
+    fn assert_send_sync<S: Send + Sync>() {}
+
+    // Version v holds v % 97 + 1 copies of v
+    fn version_list(v: u64) -> TList<u64> {
+        let mut list = TList::new();
+        for _ in 0..v % 97 + 1 {
+            list.push(v);
+        }
+        list
+    }
+
+    fn assert_whole(snapshot: &Snapshot<u64>) {
+        let v = snapshot.version();
+        snapshot.assert_invariants();
+        assert_eq!(v as usize % 97 + 1, snapshot.len(), "torn length in version {}", v);
+        assert!(snapshot.iter().all(|&e| e == v), "torn elements in version {}", v);
+    }
+
+    #[test]
+    fn test_shared_send_sync() {
+        assert_send_sync::<SharedTList<u64>>();
+        assert_send_sync::<Snapshot<String>>();
+    }
+
+    #[test]
+    fn test_shared_snapshots() {
+        let shared = SharedTList::new(TList::<u64>::from_data(&[1, 2, 3]));
+        let before = shared.read();
+        assert_eq!(0, before.version());
+
+        assert_eq!(Some(1), shared.update(|list| { list.push(4); list.remove(0) }));
+        let after = shared.read();
+        assert_eq!(1, after.version());
+        assert_eq!(1, shared.version());
+        assert!(before.iter().eq([1, 2, 3].iter()));
+        assert!(after.iter().eq([2, 3, 4].iter()));
+
+        assert_eq!(2, shared.publish(TList::new()));
+        assert!(shared.read().is_empty());
+        assert!(after.clone().iter().eq([2, 3, 4].iter()));
+        drop(shared);
+        assert_eq!(3, after.len());
+    }
+
+    #[test]
+    fn test_shared_readers_never_torn() {
+        const VERSIONS: u64 = 2000;
+        let shared = Arc::new(SharedTList::new(version_list(0)));
+        let done = Arc::new(AtomicBool::new(false));
+        let start = Arc::new(Barrier::new(5));
+
+        let readers: Vec<_> = (0..4).map(|_| {
+            let (shared, done, start) = (shared.clone(), done.clone(), start.clone());
+            thread::spawn(move || {
+                start.wait();
+                let mut last = 0;
+                let mut held = Vec::new();
+                while !done.load(Ordering::Acquire) {
+                    let snapshot = shared.read();
+                    assert!(snapshot.version() >= last, "versions went backwards");
+                    last = snapshot.version();
+                    assert_whole(&snapshot);
+                    // Keep some old versions alive while the writer moves on
+                    if held.len() < 64 && last % 7 == 0 {
+                        held.push(snapshot);
+                    }
+                }
+                for snapshot in &held {
+                    assert_whole(snapshot);
+                }
+                last
+            })
+        }).collect();
+
+        start.wait();
+        for v in 1..VERSIONS + 1 {
+            if v % 2 == 0 {
+                assert_eq!(v, shared.publish(version_list(v)));
+            } else {
+                shared.update(|list| {
+                    list.clear();
+                    for _ in 0..v % 97 + 1 {
+                        list.push(v);
+                    }
+                });
+            }
+        }
+        done.store(true, Ordering::Release);
+
+        for reader in readers {
+            assert!(reader.join().unwrap() <= VERSIONS);
+        }
+        assert_whole(&shared.read());
+        assert_eq!(VERSIONS, shared.version());
+    }
+
+    #[test]
+    fn test_shared_concurrent_updates() {
+        let shared = Arc::new(SharedTList::<usize>::default());
+        let writers: Vec<_> = (0..4).map(|w| {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                for i in 0..250 {
+                    shared.update(|list| list.insert(w * 1000 + i, list.len() / 2));
+                }
+            })
+        }).collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let snapshot = shared.read();
+        snapshot.assert_invariants();
+        assert_eq!(1000, snapshot.len());
+        assert_eq!(1000, snapshot.version());
+        let mut values: Vec<usize> = snapshot.iter().cloned().collect();
+        values.sort();
+        values.dedup();
+        assert_eq!(1000, values.len());
+    }
+}
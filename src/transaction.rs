@@ -0,0 +1,204 @@
+// (c) 2017 James Crooks
+//
+// Batches of TList edits which are applied all together or not at all.
+
+use allocator_api2::vec::Vec as LogVec;
+
+use {Allocator, TList, TListError};
+
+/// A batch of edits to a TList, made through TList::transaction(). The edits are applied to
+/// the list as they are made, each logging the operation which inverts it, so that if the
+/// transaction fails they are undone in reverse order. A successful transaction only pays
+/// for keeping the log.
+///
+/// Values replaced through get_mut() or removed through remove() are cloned into the log.
+pub struct Transaction<'a, T: 'a + Clone, A: 'a + Allocator + Clone> {
+    list: &'a mut TList<T, A>,
+    log: LogVec<Inverse<T>>,
+}
+
+// The edit which undoes a logged one
+enum Inverse<T> {
+    Remove(usize),
+    Insert(T, usize),
+    Replace(T, usize),
+}
+
+impl<T, A> TList<T, A> where T: Clone, A: Allocator + Clone {
+    /// Runs f on a Transaction over the TList, through which it can edit the list. If f
+    /// returns an error or panics, every edit it made is undone before the error is returned
+    /// or the panic continues, leaving the list as it was. Otherwise the edits are kept, and
+    /// f's result is returned.
+    ///
+    /// An Observer is told of the edits as they are made, and then of the ones undoing them.
+    ///
+    /// Undoing an edit can only fail if the tree is corrupted. That panics, rather than leave
+    /// the list half rolled back, and so aborts the process if f panicked.
+    pub fn transaction<F, R, E>(&mut self, f: F) -> Result<R, E>
+        where F: FnOnce(&mut Transaction<'_, T, A>) -> Result<R, E> {
+        let mut tx = Transaction { list: self, log: LogVec::new() };
+        let result = f(&mut tx);
+        if result.is_ok() {
+            tx.log.clear();
+        }
+        // Dropping the Transaction rolls back whatever is left in the log
+        result
+    }
+}
+
+impl<'a, T, A> Transaction<'a, T, A> where T: Clone, A: Allocator + Clone {
+    /// Returns the length of the list, including the edits made so far.
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    /// Returns true if the list, including the edits made so far, is empty.
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Returns a reference to the value at index if it exists.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.list.get(index)
+    }
+
+    /// Inserts elem at index, which may be at most len(), as TList::try_insert().
+    pub fn insert(&mut self, elem: T, index: usize) -> Result<(), TListError> {
+        // Room to log the edit is made first, so that an edit is never left unlogged
+        self.log.try_reserve(1)?;
+        self.list.try_insert(elem, index)?;
+        self.log.push(Inverse::Remove(index));
+        Ok(())
+    }
+
+    /// Appends elem to the end of the list, as TList::try_push().
+    pub fn push(&mut self, elem: T) -> Result<(), TListError> {
+        let index = self.len();
+        self.insert(elem, index)
+    }
+
+    /// Removes the value at index and returns it, as TList::try_remove().
+    pub fn remove(&mut self, index: usize) -> Result<T, TListError> {
+        self.log.try_reserve(1)?;
+        let removed = self.list.try_remove(index)?;
+        self.log.push(Inverse::Insert(removed.clone(), index));
+        Ok(removed)
+    }
+
+    /// Returns a mutable reference to the value at index, after logging a copy of the value to
+    /// restore if the transaction fails. Fails if index is past the end, or if there's no room
+    /// to log the copy.
+    pub fn get_mut(&mut self, index: usize) -> Result<&mut T, TListError> {
+        let len = self.list.len();
+        let old = self.list.get(index).ok_or(TListError::IndexOutOfBounds { index, len })?.clone();
+        self.log.try_reserve(1)?;
+        self.log.push(Inverse::Replace(old, index));
+        self.list.get_mut(index).ok_or(TListError::IndexOutOfBounds { index, len })
+    }
+}
+
+impl<'a, T, A> Drop for Transaction<'a, T, A> where T: Clone, A: Allocator + Clone {
+    fn drop(&mut self) {
+        // Each inverse is applied to the list as it was right after the edit it undoes. A
+        // removal frees the slot its reinsertion takes back, so none of these can fail short
+        // of the tree being corrupted. If one does, the list is left half rolled back, which
+        // mustn't pass silently: we panic, which aborts if f is already panicking.
+        while let Some(inverse) = self.log.pop() {
+            let undone = match inverse {
+                Inverse::Remove(index) => self.list.try_remove(index).map(|_| ()),
+                Inverse::Insert(elem, index) => self.list.try_insert(elem, index),
+                Inverse::Replace(elem, index) => match self.list.get_mut(index) {
+                    Some(value) => {
+                        *value = elem;
+                        Ok(())
+                    },
+                    None => Err(TListError::IndexOutOfBounds { index, len: self.list.len() }),
+                },
+            };
+            if let Err(err) = undone {
+                panic!("failed to roll back a transaction: {:?}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use super::{Inverse, LogVec, Transaction};
+    use {TList, TListError};
+
+    #[test]
+    fn test_transaction_commit() {
+        let mut test_list = TList::<u32>::from_data(&[0, 1, 2, 3]);
+        let result = test_list.transaction(|tx| {
+            tx.insert(10, 1)?;
+            tx.push(11)?;
+            *tx.get_mut(0).unwrap() = 9;
+            assert_eq!(Err(TListError::IndexOutOfBounds { index: 6, len: 6 }), tx.get_mut(6).map(|_| ()));
+            let removed = tx.remove(2)?;
+            Ok::<u32, TListError>(removed)
+        });
+
+        assert_eq!(Ok(1), result);
+        test_list.assert_invariants();
+        assert!(test_list.iter().eq([9, 10, 2, 3, 11].iter()));
+    }
+
+    #[test]
+    fn test_transaction_rollback() {
+        let data: Vec<u32> = (0..64).collect();
+        let mut test_list = TList::<u32>::from_data(&data);
+
+        for fail_at in 0..40 {
+            let result = test_list.transaction(|tx| {
+                for i in 0..fail_at {
+                    match i % 4 {
+                        0 => tx.insert(100 + i, (i as usize * 7) % (tx.len() + 1))?,
+                        1 => { tx.remove((i as usize * 5) % tx.len())?; },
+                        2 => *tx.get_mut((i as usize * 3) % tx.len()).unwrap() += 1000,
+                        _ => tx.push(200 + i)?,
+                    }
+                }
+                tx.remove(usize::MAX).map(|_| ())
+            });
+
+            match result {
+                Err(TListError::IndexOutOfBounds { index: usize::MAX, .. }) => {},
+                other => panic!("unexpected result {:?}", other),
+            }
+            test_list.assert_invariants();
+            assert!(data.iter().eq(test_list.iter()));
+        }
+    }
+
+    #[test]
+    fn test_transaction_panic() {
+        let mut test_list = TList::<String>::from_data(&["a".to_string(), "b".to_string()]);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _ = test_list.transaction(|tx| {
+                tx.get_mut(1).unwrap().push('!');
+                tx.insert("c".to_string(), 0)?;
+                if tx.len() == 3 {
+                    panic!("edit failed");
+                }
+                Ok::<(), TListError>(())
+            });
+        }));
+
+        assert!(result.is_err());
+        test_list.assert_invariants();
+        assert!(test_list.iter().eq(["a", "b"].iter()));
+    }
+
+    #[test]
+    #[should_panic(expected = "failed to roll back a transaction")]
+    fn test_transaction_rollback_failure() {
+        // A log which no longer fits the list, as only a corrupted tree could produce
+        let mut test_list = TList::<u32>::from_data(&[0, 1]);
+        let mut log = LogVec::new();
+        log.push(Inverse::Remove(5));
+        drop(Transaction { list: &mut test_list, log });
+    }
+}
@@ -0,0 +1,272 @@
+// (c) 2017 James Crooks
+//
+// Maps from the indexes of a list before a batch of edits to the indexes after it.
+
+use alloc::vec::Vec;
+
+use Change;
+
+/// Which side of elements inserted exactly at an index that index maps to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Bias {
+    /// Before the inserted elements, so the index stays where it was, as a cursor typed at.
+    Before,
+    /// After the inserted elements, so the index follows the element it referred to, which
+    /// was shifted up by them.
+    #[default]
+    After,
+}
+
+/// Translates indexes into a list from before a batch of edits to after it, so that references
+/// held outside the list, such as line numbers of diagnostics or search hits, can be updated
+/// without rescanning the list.
+///
+/// A Transform starts out as the identity and records edits with insert(), remove() and
+/// splice(), made alongside the same calls on the list, or with record() from the Changes an
+/// Observer or UndoTList reports. Each edit's index is relative to the list as left by the
+/// edits before it. Transform::from_deltas() records a batch of Deltas.
+///
+/// The edits are kept as a sorted list of splices of the original list, so map() takes
+/// O(log K) time for K separate edited regions, while recording an edit takes O(K).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Transform {
+    splices: Vec<Splice>,
+}
+
+// A run of the original list from old to old + deleted replaced by inserted elements, which
+// start at new after the edits. Splices are sorted, with at least one untouched element
+// between neighbors, since edits that meet are merged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Splice {
+    old: usize,
+    deleted: usize,
+    inserted: usize,
+    new: usize,
+}
+
+impl Splice {
+    fn old_end(&self) -> usize {
+        self.old + self.deleted
+    }
+
+    fn new_end(&self) -> usize {
+        self.new + self.inserted
+    }
+}
+
+impl Transform {
+    /// Generate a new identity Transform, which maps every index to itself.
+    pub fn new() -> Transform {
+        Transform::default()
+    }
+
+    /// Returns true if the Transform maps every index to itself.
+    pub fn is_identity(&self) -> bool {
+        self.splices.is_empty()
+    }
+
+    /// Records count elements inserted at index.
+    pub fn insert(&mut self, index: usize, count: usize) {
+        self.splice(index, 0, count);
+    }
+
+    /// Records count elements removed from index.
+    pub fn remove(&mut self, index: usize, count: usize) {
+        self.splice(index, count, 0);
+    }
+
+    /// Records removed elements deleted from index and inserted elements put in their place.
+    pub fn splice(&mut self, index: usize, removed: usize, inserted: usize) {
+        if removed == 0 && inserted == 0 {
+            return;
+        }
+        let (start, end) = (index, index + removed);
+
+        // The splices which the edited range overlaps or touches, which are merged with it
+        let first = self.splices.partition_point(|s| s.new_end() < start);
+        let last = first + self.splices[first..].partition_point(|s| s.new <= end);
+
+        // Untouched elements before the edit are shifted as much as the last splice before it
+        let (prev_old, prev_new) = match first {
+            0 => (0, 0),
+            _ => (self.splices[first - 1].old_end(), self.splices[first - 1].new_end()),
+        };
+        let to_old = |new: usize| new - prev_new + prev_old;
+
+        let merged = if first == last {
+            Splice { old: to_old(start), deleted: removed, inserted, new: start }
+        } else {
+            // The merged splice runs from the start of the edit or the first splice, whichever
+            // is lower, to the end of the edit or the last splice, whichever is higher
+            let (head, tail) = (self.splices[first], self.splices[last - 1]);
+            let (old, new) = if start < head.new { (to_old(start), start) } else { (head.old, head.new) };
+            let (old_end, new_end) = if end > tail.new_end() {
+                (tail.old_end() + end - tail.new_end(), end)
+            } else {
+                (tail.old_end(), tail.new_end())
+            };
+            Splice { old, deleted: old_end - old, inserted: new_end - new - removed + inserted, new }
+        };
+
+        for splice in &mut self.splices[last..] {
+            splice.new = splice.new - removed + inserted;
+        }
+        let replacement = Some(merged).filter(|s| s.deleted > 0 || s.inserted > 0);
+        self.splices.splice(first..last, replacement);
+    }
+
+    /// Records a Change to the indexes of a list. Replaced elements don't move, so they leave
+    /// the Transform as it was.
+    pub fn record(&mut self, change: Change) {
+        match change {
+            Change::Inserted { index, count } => self.insert(index, count),
+            Change::Removed { index, count } => self.remove(index, count),
+            Change::Replaced { .. } => {},
+        }
+    }
+
+    /// Maps an index from before the edits to after them, or returns None if its element
+    /// was removed. An index at which elements were inserted maps before or after them per
+    /// bias. Runs in O(log K) time.
+    pub fn map(&self, index: usize, bias: Bias) -> Option<usize> {
+        match self.find(index) {
+            Some(splice) if index < splice.old_end() => None,
+            _ => Some(self.map_clamped(index, bias)),
+        }
+    }
+
+    /// Maps an index from before the edits to after them as map(), except that an index
+    /// whose element was removed maps to the start or end of whatever replaced it, per bias.
+    pub fn map_clamped(&self, index: usize, bias: Bias) -> usize {
+        let Some(splice) = self.find(index) else {
+            return index;
+        };
+        let removed = index < splice.old_end();
+        let inserted_at = splice.deleted == 0 && index == splice.old;
+        if !removed && !inserted_at {
+            return index - splice.old_end() + splice.new_end();
+        }
+        match bias {
+            Bias::Before => splice.new,
+            Bias::After => splice.new_end(),
+        }
+    }
+
+    /// Returns the Transform which maps indexes through this one and then through later,
+    /// whose edits were made after this one's.
+    pub fn then(&self, later: &Transform) -> Transform {
+        // later's splices are relative to the list these edits leave. Recording them from
+        // the highest index down keeps each one's index valid.
+        let mut composed = self.clone();
+        for splice in later.splices.iter().rev() {
+            composed.splice(splice.old, splice.deleted, splice.inserted);
+        }
+        composed
+    }
+
+    fn find(&self, index: usize) -> Option<&Splice> {
+        // The last splice starting at or before index
+        let after = self.splices.partition_point(|s| s.old <= index);
+        after.checked_sub(1).map(|i| &self.splices[i])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::Rng;
+    use super::{Bias, Transform};
+    use {Change, Delta, DeltaMode, TList};
+
+    #[test]
+    fn test_transform_map() {
+        let mut transform = Transform::new();
+        assert!(transform.is_identity());
+        assert_eq!(Some(7), transform.map(7, Bias::Before));
+
+        // 0 1 2 3 4 5 6 7 8 9 becomes 0 1 x x 2 3 5 6 y 9
+        transform.insert(2, 2);
+        transform.remove(6, 1);
+        transform.splice(8, 2, 1);
+        assert_eq!(Some(1), transform.map(1, Bias::After));
+        assert_eq!(Some(2), transform.map(2, Bias::Before));
+        assert_eq!(Some(4), transform.map(2, Bias::After));
+        assert_eq!(Some(5), transform.map(3, Bias::After));
+        assert_eq!(None, transform.map(4, Bias::After));
+        assert_eq!(Some(6), transform.map(5, Bias::After));
+        assert_eq!(None, transform.map(7, Bias::Before));
+        assert_eq!(Some(9), transform.map(9, Bias::Before));
+        assert_eq!(Some(10), transform.map(10, Bias::Before));
+
+        assert_eq!(6, transform.map_clamped(4, Bias::Before));
+        assert_eq!(8, transform.map_clamped(7, Bias::Before));
+        assert_eq!(9, transform.map_clamped(8, Bias::After));
+
+        // Removing the inserted elements cancels their insertion out, but putting back ones
+        // like those removed doesn't bring the originals back
+        transform.remove(2, 2);
+        transform.insert(4, 1);
+        assert_eq!(Some(2), transform.map(2, Bias::Before));
+        assert_eq!(None, transform.map(4, Bias::After));
+        assert_eq!(4, transform.map_clamped(4, Bias::Before));
+        transform.record(Change::Replaced { index: 3 });
+        assert_eq!(Some(3), transform.map(3, Bias::After));
+    }
+
+    #[test]
+    fn test_transform_random() {
+        // Checks a Transform against a list of the original indexes, edited alongside it, and
+        // composing Transforms against recording into one
+        let mut rng = rand::thread_rng();
+        for _ in 0..50 {
+            let mut list: Vec<Option<usize>> = (0..40).map(Some).collect();
+            let mut transform = Transform::new();
+            let mut parts = vec![Transform::new()];
+
+            for _ in 0..rng.gen_range(1, 20) {
+                let index = rng.gen_range(0, list.len() + 1);
+                let removed = rng.gen_range(0, (list.len() - index).min(4) + 1);
+                let inserted = rng.gen_range(0, 4);
+                list.splice(index..index + removed, (0..inserted).map(|_| None));
+                transform.splice(index, removed, inserted);
+                if rng.gen_weighted_bool(3) {
+                    parts.push(Transform::new());
+                }
+                parts.last_mut().unwrap().splice(index, removed, inserted);
+            }
+
+            let composed = parts.iter().fold(Transform::new(), |acc, part| acc.then(part));
+            for old in 0..40 {
+                let expected = list.iter().position(|&elem| elem == Some(old));
+                assert_eq!(expected, transform.map(old, Bias::After));
+                assert_eq!(expected, composed.map(old, Bias::After));
+            }
+            assert_eq!(Some(list.len()), transform.map(40, Bias::After));
+        }
+    }
+
+    #[test]
+    fn test_transform_from_deltas() {
+        let deltas = vec![
+            Delta::Insert { index: 1, value: 10 },
+            Delta::DeleteRange { index: 2, count: 2 },
+            Delta::Replace { index: 4, value: 40 },
+            Delta::InsertRange { index: 6, values: vec![60, 61] },
+        ];
+        for &mode in &[DeltaMode::Sequential, DeltaMode::Simultaneous] {
+            let mut list = TList::<u32>::from_data(&[0, 1, 2, 3, 4, 5, 6, 7]);
+            let transform = Transform::from_deltas(&deltas, mode);
+            list.apply(deltas.clone(), mode).unwrap();
+            let after: Vec<u32> = list.iter().cloned().collect();
+            for old in 0..8 {
+                let expected = after.iter().position(|&elem| elem == old as u32);
+                // The replaced element keeps its index but not its value
+                match (expected, transform.map(old, Bias::After)) {
+                    (None, Some(new)) => assert_eq!(40, after[new]),
+                    (expected, new) => assert_eq!(expected, new),
+                }
+            }
+        }
+    }
+}
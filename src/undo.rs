@@ -0,0 +1,648 @@
+// (c) 2017 James Crooks
+//
+// A TList which records its edits for undo and redo.
+
+//! UndoTList, a TList wrapper with an undo/redo history.
+
+use allocator_api2::vec::Vec as ArenaVec;
+use core::mem;
+
+use {Allocator, Change, Global, Iter, TList, TListError};
+
+/// A TList which records the inverse of each edit made through it, so that edits can be
+/// undone and redone. Edits made between begin_group() and end_group() are undone and redone
+/// together; any other edit forms a group of its own.
+///
+/// undo() and redo() report what they did as a list of Changes, in the order they were made,
+/// so that a view of the list can be updated incrementally. Making a new edit discards the
+/// groups which could have been redone.
+///
+/// The history can be capped to a number of groups with set_group_limit(), and to an
+/// estimate of its size in bytes with set_memory_limit(), past which the oldest groups are
+/// forgotten. Replacing or removing a value stores it in the history, so T must be Clone
+/// for the values handed back by remove() and get_mut().
+///
+/// The history is allocated through the list's allocator. Each edit has a try_ variant which
+/// reports a failure to allocate as TListError::AllocationFailure, leaving the list and its
+/// history as they were; the plain methods panic instead.
+#[derive(Debug, Clone)]
+pub struct UndoTList<T: Clone, A: Allocator + Clone = Global> {
+    list: TList<T, A>,
+    // Groups before undo_start have been forgotten; they're drained once they make up half
+    // of undo, so forgetting the oldest group is amortized O(1)
+    undo: ArenaVec<Group<T, A>, A>,
+    undo_start: usize,
+    redo: ArenaVec<Group<T, A>, A>,
+    // The group being recorded, and how many begin_group() calls are still open
+    open: Group<T, A>,
+    depth: usize,
+    max_groups: Option<usize>,
+    max_bytes: Option<usize>,
+    heap_size: fn(&T) -> usize,
+    // The size of the groups in undo, not counting the open group
+    undo_bytes: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Group<T, A: Allocator + Clone> {
+    // Applied in reverse order to undo, or redo, the group
+    ops: ArenaVec<Op<T>, A>,
+    bytes: usize,
+}
+
+// A group of inverse operations being built while applying another, with the Changes made
+type Applied<T, A> = (Group<T, A>, ArenaVec<Change, A>);
+
+#[derive(Debug, Clone)]
+enum Op<T> {
+    Remove(usize),
+    Insert(T, usize),
+    Replace(T, usize),
+}
+
+impl<T, A> Group<T, A> where A: Allocator + Clone {
+    fn new_in(alloc: A) -> Group<T, A> {
+        Group { ops: ArenaVec::new_in(alloc), bytes: 0 }
+    }
+
+    fn push(&mut self, op: Op<T>, heap_size: fn(&T) -> usize) {
+        // The caller has reserved room for op, so this doesn't allocate
+        self.bytes += op.size(heap_size);
+        self.ops.push(op);
+    }
+}
+
+impl<T> Op<T> {
+    fn size(&self, heap_size: fn(&T) -> usize) -> usize {
+        mem::size_of::<Op<T>>() + match *self {
+            Op::Remove(_) => 0,
+            Op::Insert(ref elem, _) | Op::Replace(ref elem, _) => heap_size(elem),
+        }
+    }
+}
+
+impl<T> UndoTList<T> where T: Clone {
+    /// Generate a new, empty UndoTList with an unlimited history.
+    pub fn new() -> UndoTList<T> {
+        UndoTList::from_list(TList::new())
+    }
+}
+
+impl<T, A> UndoTList<T, A> where T: Clone, A: Allocator + Clone {
+    /// Wraps list, with an empty, unlimited history allocated through the list's allocator.
+    pub fn from_list(list: TList<T, A>) -> UndoTList<T, A> {
+        let alloc = list.allocator().clone();
+        UndoTList {
+            undo: ArenaVec::new_in(alloc.clone()),
+            undo_start: 0,
+            redo: ArenaVec::new_in(alloc.clone()),
+            open: Group::new_in(alloc),
+            list,
+            depth: 0,
+            max_groups: None,
+            max_bytes: None,
+            heap_size: |_| 0,
+            undo_bytes: 0,
+        }
+    }
+
+    /// Unwraps the TList, discarding the history.
+    pub fn into_inner(self) -> TList<T, A> {
+        self.list
+    }
+
+    /// Returns the wrapped TList, for read access.
+    pub fn list(&self) -> &TList<T, A> {
+        &self.list
+    }
+
+    /// Gets the number of elements in the list.
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    /// Returns true if the list contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Returns a reference to the value at index if it exists.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.list.get(index)
+    }
+
+    /// Returns an iterator over the values in the list in index order.
+    pub fn iter(&self) -> Iter<'_, T, A> {
+        self.list.iter()
+    }
+
+    /// Inserts elem at index, or appends it if index is past the end, as TList::insert().
+    pub fn insert(&mut self, elem: T, index: usize) {
+        let index = index.min(self.len());
+        self.try_insert(elem, index).unwrap_or_else(|err| panic!("{}", err));
+    }
+
+    /// Inserts elem at index, which may be at most len(), as TList::try_insert(). On error the
+    /// value is dropped and neither the list nor its history is changed.
+    pub fn try_insert(&mut self, elem: T, index: usize) -> Result<(), TListError> {
+        self.reserve_record()?;
+        self.list.try_insert(elem, index)?;
+        self.record(Op::Remove(index));
+        Ok(())
+    }
+
+    /// Appends elem to the end of the list.
+    pub fn push(&mut self, elem: T) {
+        let index = self.len();
+        self.insert(elem, index);
+    }
+
+    /// Appends elem to the end of the list, as try_insert().
+    pub fn try_push(&mut self, elem: T) -> Result<(), TListError> {
+        let index = self.len();
+        self.try_insert(elem, index)
+    }
+
+    /// Removes the value at index and returns it, if it exists.
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        if index >= self.len() {
+            return None;
+        }
+        Some(self.try_remove(index).unwrap_or_else(|err| panic!("{}", err)))
+    }
+
+    /// Removes the value at index and returns it, reporting an index past the end or a failure
+    /// to allocate its history as an error, in which case nothing is changed.
+    pub fn try_remove(&mut self, index: usize) -> Result<T, TListError> {
+        self.reserve_record()?;
+        let removed = self.list.try_remove(index)?;
+        self.record(Op::Insert(removed.clone(), index));
+        Ok(removed)
+    }
+
+    /// Removes the last value and returns it, if the list isn't empty.
+    pub fn pop(&mut self) -> Option<T> {
+        match self.len() {
+            0 => None,
+            len => self.remove(len - 1),
+        }
+    }
+
+    /// Replaces the value at index with elem, returning the old value, if it exists.
+    pub fn replace(&mut self, elem: T, index: usize) -> Option<T> {
+        if index >= self.len() {
+            return None;
+        }
+        Some(self.try_replace(elem, index).unwrap_or_else(|err| panic!("{}", err)))
+    }
+
+    /// Replaces the value at index with elem and returns the old value, reporting an index
+    /// past the end or a failure to allocate its history as an error. On error elem is
+    /// dropped and nothing is changed.
+    pub fn try_replace(&mut self, elem: T, index: usize) -> Result<T, TListError> {
+        let len = self.len();
+        if index >= len {
+            return Err(TListError::IndexOutOfBounds { index, len });
+        }
+        self.reserve_record()?;
+        let value = self.list.get_mut(index).ok_or(TListError::IndexOutOfBounds { index, len })?;
+        let old = mem::replace(value, elem);
+        self.record(Op::Replace(old.clone(), index));
+        Ok(old)
+    }
+
+    /// Returns a mutable reference to the value at index if it exists, after recording a copy
+    /// of the value to restore when the edit is undone.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.len() {
+            return None;
+        }
+        Some(self.try_get_mut(index).unwrap_or_else(|err| panic!("{}", err)))
+    }
+
+    /// Returns a mutable reference to the value at index as get_mut(), reporting an index past
+    /// the end or a failure to allocate its history as an error.
+    pub fn try_get_mut(&mut self, index: usize) -> Result<&mut T, TListError> {
+        let len = self.len();
+        let old = match self.list.get(index) {
+            Some(value) => value.clone(),
+            None => return Err(TListError::IndexOutOfBounds { index, len }),
+        };
+        self.reserve_record()?;
+        self.record(Op::Replace(old, index));
+        self.list.get_mut(index).ok_or(TListError::IndexOutOfBounds { index, len })
+    }
+
+    /// Starts a group of edits which are undone and redone together, lasting until the
+    /// matching end_group(). Groups may be nested, in which case the outermost one counts.
+    pub fn begin_group(&mut self) {
+        self.depth += 1;
+    }
+
+    /// Ends the group started by the matching begin_group(). Does nothing if no group is open.
+    pub fn end_group(&mut self) {
+        if self.depth > 0 {
+            self.depth -= 1;
+            if self.depth == 0 {
+                self.close_group();
+            }
+        }
+    }
+
+    /// Returns true if there is a group of edits to undo.
+    pub fn can_undo(&self) -> bool {
+        self.undo.len() > self.undo_start || !self.open.ops.is_empty()
+    }
+
+    /// Returns true if there is a group of undone edits to redo.
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Undoes the most recent group of edits, ending any open group first, and returns the
+    /// Changes made in doing so. Returns None if there is nothing to undo.
+    pub fn undo(&mut self) -> Option<ArenaVec<Change, A>> {
+        self.try_undo().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Undoes the most recent group of edits as undo(), reporting a failure to allocate as an
+    /// error, in which case nothing is changed and any open group stays open.
+    pub fn try_undo(&mut self) -> Result<Option<ArenaVec<Change, A>>, TListError> {
+        let ops = if !self.open.ops.is_empty() {
+            self.open.ops.len()
+        } else {
+            match self.undo[self.undo_start..].last() {
+                Some(group) => group.ops.len(),
+                None => return Ok(None),
+            }
+        };
+        self.redo.try_reserve(1)?;
+        let reserved = self.reserve_apply(ops)?;
+
+        // The open group is undone straight away rather than closed first, as closing it
+        // could see it forgotten for being past the memory limit
+        self.depth = 0;
+        let group = if !self.open.ops.is_empty() {
+            let empty = Group::new_in(self.list.allocator().clone());
+            mem::replace(&mut self.open, empty)
+        } else {
+            match self.pop_undo() {
+                Some(group) => group,
+                None => return Ok(None),
+            }
+        };
+        let (redo, changes) = self.apply(group, reserved);
+        self.redo.push(redo);
+        Ok(Some(changes))
+    }
+
+    /// Redoes the most recently undone group of edits and returns the Changes made in doing
+    /// so. Returns None if there is nothing to redo.
+    pub fn redo(&mut self) -> Option<ArenaVec<Change, A>> {
+        self.try_redo().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Redoes the most recently undone group of edits as redo(), reporting a failure to
+    /// allocate as an error, in which case nothing is changed.
+    pub fn try_redo(&mut self) -> Result<Option<ArenaVec<Change, A>>, TListError> {
+        let ops = match self.redo.last() {
+            Some(group) => group.ops.len(),
+            None => return Ok(None),
+        };
+        self.undo.try_reserve(1)?;
+        let reserved = self.reserve_apply(ops)?;
+        let group = match self.redo.pop() {
+            Some(group) => group,
+            None => return Ok(None),
+        };
+        let (undo, changes) = self.apply(group, reserved);
+        self.push_undo(undo);
+        Ok(Some(changes))
+    }
+
+    /// Limits the history to the given number of groups, forgetting the oldest ones past it.
+    /// None, the default, doesn't limit the number. A group still being recorded doesn't count.
+    pub fn set_group_limit(&mut self, max_groups: Option<usize>) {
+        self.max_groups = max_groups;
+        self.enforce_limits();
+    }
+
+    /// Limits the history to an estimated max_bytes, forgetting the oldest groups past it.
+    /// Each recorded edit counts its own size plus heap_size() of the value it stores, e.g.
+    /// String::capacity() for a list of Strings. A group still being recorded counts too, but
+    /// is only forgotten once it's closed. None, the default, doesn't limit the size.
+    pub fn set_memory_limit(&mut self, max_bytes: Option<usize>, heap_size: fn(&T) -> usize) {
+        self.max_bytes = max_bytes;
+        self.heap_size = heap_size;
+        let start = self.undo_start;
+        for group in self.undo[start..].iter_mut().chain(Some(&mut self.open)) {
+            group.bytes = group.ops.iter().map(|op| op.size(heap_size)).sum();
+        }
+        self.undo_bytes = self.undo[start..].iter().map(|group| group.bytes).sum();
+        self.enforce_limits();
+    }
+
+    /// Forgets every recorded group, both to undo and to redo.
+    pub fn clear_history(&mut self) {
+        self.undo.clear();
+        self.undo_start = 0;
+        self.redo.clear();
+        self.open = Group::new_in(self.list.allocator().clone());
+        self.undo_bytes = 0;
+    }
+
+    fn reserve_record(&mut self) -> Result<(), TListError> {
+        // Makes room to record one more edit, and to close the group it goes in, before the
+        // list is changed, so that an allocation failure can't leave an edit made but missing
+        // from the history
+        self.open.ops.try_reserve(1)?;
+        self.undo.try_reserve(1)?;
+        Ok(())
+    }
+
+    fn reserve_apply(&mut self, ops: usize) -> Result<Applied<T, A>, TListError> {
+        // Allocates everything applying a group of ops needs before any of it is applied,
+        // including nodes for as many insertions, so it can't fail partway through
+        let mut inverse = Group::new_in(self.list.allocator().clone());
+        let mut changes = ArenaVec::new_in(self.list.allocator().clone());
+        inverse.ops.try_reserve(ops)?;
+        changes.try_reserve(ops)?;
+        self.list.try_reserve(ops)?;
+        Ok((inverse, changes))
+    }
+
+    fn record(&mut self, op: Op<T>) {
+        self.redo.clear();
+        self.open.push(op, self.heap_size);
+        if self.depth == 0 {
+            self.close_group();
+        } else {
+            self.enforce_limits();
+        }
+    }
+
+    fn close_group(&mut self) {
+        if !self.open.ops.is_empty() {
+            let empty = Group::new_in(self.list.allocator().clone());
+            let group = mem::replace(&mut self.open, empty);
+            self.push_undo(group);
+        }
+    }
+
+    fn push_undo(&mut self, group: Group<T, A>) {
+        // Room for the group was reserved by reserve_record() or try_redo()
+        self.undo_bytes += group.bytes;
+        self.undo.push(group);
+        self.enforce_limits();
+    }
+
+    fn pop_undo(&mut self) -> Option<Group<T, A>> {
+        if self.undo.len() == self.undo_start {
+            return None;
+        }
+        let group = self.undo.pop()?;
+        self.undo_bytes -= group.bytes;
+        if self.undo.len() == self.undo_start {
+            self.undo.clear();
+            self.undo_start = 0;
+        }
+        Some(group)
+    }
+
+    fn enforce_limits(&mut self) {
+        while self.undo.len() > self.undo_start
+            && (self.max_groups.is_some_and(|max| self.undo.len() - self.undo_start > max)
+                || self.max_bytes.is_some_and(|max| self.undo_bytes + self.open.bytes > max)) {
+            let empty = Group::new_in(self.list.allocator().clone());
+            let group = mem::replace(&mut self.undo[self.undo_start], empty);
+            self.undo_bytes -= group.bytes;
+            self.undo_start += 1;
+        }
+        if self.undo_start > 0 && self.undo_start * 2 >= self.undo.len() {
+            self.undo.drain(..self.undo_start);
+            self.undo_start = 0;
+        }
+    }
+
+    fn apply(&mut self, group: Group<T, A>, reserved: Applied<T, A>) -> Applied<T, A> {
+        // Applies the group's operations in reverse order, returning the group of their
+        // inverses, which applied in reverse order in turn brings the list back.
+        let (mut inverse, mut changes) = reserved;
+        for op in group.ops.into_iter().rev() {
+            let (undo, change) = match op {
+                Op::Remove(index) => {
+                    let removed = self.list.remove(index).expect("undo history out of step");
+                    (Op::Insert(removed, index), Change::Removed { index, count: 1 })
+                },
+                Op::Insert(elem, index) => {
+                    self.list.insert(elem, index);
+                    (Op::Remove(index), Change::Inserted { index, count: 1 })
+                },
+                Op::Replace(elem, index) => {
+                    let value = self.list.get_mut(index).expect("undo history out of step");
+                    (Op::Replace(mem::replace(value, elem), index), Change::Replaced { index })
+                },
+            };
+            inverse.push(undo, self.heap_size);
+            changes.push(change);
+        }
+        (inverse, changes)
+    }
+}
+
+impl<T> Default for UndoTList<T> where T: Clone {
+    fn default() -> UndoTList<T> {
+        UndoTList::new()
+    }
+}
+
+impl<T, A> From<TList<T, A>> for UndoTList<T, A> where T: Clone, A: Allocator + Clone {
+    fn from(list: TList<T, A>) -> UndoTList<T, A> {
+        UndoTList::from_list(list)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::Rng;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use super::UndoTList;
+    use tests::CountingAlloc;
+    use {Change, TList, TListError};
+
+    fn replay(view: &mut Vec<bool>, changes: &[Change]) {
+        for &change in changes {
+            match change {
+                Change::Inserted { index, count } => {
+                    view.splice(index..index, (0..count).map(|_| true));
+                },
+                Change::Removed { index, count } => { view.drain(index..index + count); },
+                Change::Replaced { index } => view[index] = true,
+            }
+        }
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut rng = rand::thread_rng();
+        let mut test_list = UndoTList::from(TList::<u32>::from_data(&[0, 1, 2, 3]));
+        let mut states = vec![test_list.iter().cloned().collect::<Vec<_>>()];
+
+        for i in 0..300 {
+            let grouped = i % 5 == 0;
+            if grouped {
+                test_list.begin_group();
+                test_list.begin_group();
+            }
+            for _ in 0..if grouped { 4 } else { 1 } {
+                let len = test_list.len();
+                match rng.gen_range(0, 5) {
+                    0 | 1 => test_list.insert(i, rng.gen_range(0, len + 1)),
+                    2 if len > 0 => { test_list.remove(rng.gen_range(0, len)); },
+                    3 if len > 0 => { test_list.replace(i, rng.gen_range(0, len)); },
+                    _ if len > 0 => *test_list.get_mut(rng.gen_range(0, len)).unwrap() += 1000,
+                    _ => test_list.push(i),
+                }
+            }
+            if grouped {
+                test_list.end_group();
+                test_list.end_group();
+            }
+            states.push(test_list.iter().cloned().collect());
+        }
+
+        // Undo everything, then redo it, checking each state and the reported Changes
+        let mut view = vec![false; test_list.len()];
+        for state in states.iter().rev().skip(1) {
+            let changes = test_list.undo().unwrap();
+            replay(&mut view, &changes);
+            assert_eq!(view.len(), test_list.len());
+            assert!(state.iter().eq(test_list.iter()));
+        }
+        assert!(!test_list.can_undo());
+        assert_eq!(None, test_list.undo());
+
+        for state in states.iter().skip(1) {
+            let changes = test_list.redo().unwrap();
+            replay(&mut view, &changes);
+            assert_eq!(view.len(), test_list.len());
+            assert!(state.iter().eq(test_list.iter()));
+        }
+        assert_eq!(None, test_list.redo());
+        test_list.list().assert_invariants();
+
+        // A new edit discards what could have been redone
+        test_list.undo();
+        test_list.push(7);
+        assert!(!test_list.can_redo());
+    }
+
+    #[test]
+    fn test_undo_groups() {
+        let mut test_list = UndoTList::<u32>::new();
+        test_list.push(1);
+        test_list.begin_group();
+        test_list.push(2);
+        test_list.push(3);
+        test_list.remove(0);
+        assert_eq!(Some(&[
+            Change::Inserted { index: 0, count: 1 },
+            Change::Removed { index: 2, count: 1 },
+            Change::Removed { index: 1, count: 1 },
+        ][..]), test_list.undo().as_deref());
+        assert!(test_list.iter().eq([1].iter()));
+
+        // end_group() without a group, or after undo() closed it, is ignored
+        test_list.end_group();
+        test_list.push(4);
+        test_list.push(5);
+        test_list.undo();
+        assert!(test_list.iter().eq([1, 4].iter()));
+    }
+
+    #[test]
+    fn test_undo_limits() {
+        let mut test_list = UndoTList::<String>::new();
+        for i in 0..10 {
+            test_list.push(i.to_string());
+        }
+        test_list.set_group_limit(Some(4));
+        for _ in 0..4 {
+            assert!(test_list.undo().is_some());
+        }
+        assert!(!test_list.can_undo());
+        assert!(test_list.iter().eq(["0", "1", "2", "3", "4", "5"].iter()));
+
+        test_list.set_group_limit(None);
+        test_list.set_memory_limit(Some(1024), String::capacity);
+        for _ in 0..3 {
+            test_list.replace("x".repeat(300), 0);
+        }
+        assert_eq!(3, test_list.undo.len() - test_list.undo_start);
+        test_list.replace("x".repeat(300), 0);
+        assert_eq!(3, test_list.undo.len() - test_list.undo_start);
+        assert!(test_list.undo_bytes <= 1024);
+
+        test_list.set_memory_limit(Some(0), String::capacity);
+        assert!(!test_list.can_undo());
+        test_list.clear_history();
+        assert_eq!(0, test_list.undo_bytes);
+
+        // The open group counts against the limit, crowding out older groups as it grows
+        test_list.set_memory_limit(Some(1024), String::capacity);
+        test_list.replace("x".repeat(600), 0);
+        test_list.begin_group();
+        test_list.replace("y".repeat(700), 1);
+        assert_eq!(1, test_list.undo.len() - test_list.undo_start);
+        test_list.replace("z".to_string(), 1);
+        assert_eq!(0, test_list.undo.len() - test_list.undo_start);
+        assert!(test_list.undo_bytes + test_list.open.bytes <= 1024);
+        test_list.end_group();
+        assert!(test_list.undo().is_some());
+        assert!(!test_list.can_undo());
+        assert_eq!(Some(&"x".repeat(600)), test_list.get(0));
+        assert_eq!(Some(&"1".to_string()), test_list.get(1));
+    }
+
+    #[test]
+    fn test_undo_allocation_failure() {
+        let live = Rc::new(Cell::new(0));
+        let limited = CountingAlloc { live: live.clone(), limit: 4096 };
+        let mut test_list = UndoTList::from_list(TList::<u64, _>::with_capacity_in(0, limited));
+        let mut pushed = 0;
+        while let Ok(()) = test_list.try_push(pushed) {
+            pushed += 1;
+        }
+        assert!(pushed > 0);
+        match test_list.try_push(pushed) {
+            Err(TListError::AllocationFailure(_)) => {},
+            other => panic!("expected an allocation failure, got {:?}", other),
+        }
+        assert!((0..pushed).eq(test_list.iter().cloned()));
+        assert_eq!(pushed as usize, test_list.undo.len() - test_list.undo_start);
+
+        // Undoing needs room for the redo history too, and is refused whole when it runs out
+        loop {
+            let len = test_list.len();
+            match test_list.try_undo() {
+                Ok(Some(changes)) => {
+                    assert_eq!(&[Change::Removed { index: len - 1, count: 1 }][..], &changes[..]);
+                },
+                Ok(None) => break,
+                Err(TListError::AllocationFailure(_)) => {
+                    assert!((0..len as u64).eq(test_list.iter().cloned()));
+                    assert!(test_list.can_undo());
+                    break;
+                },
+                Err(err) => panic!("expected an allocation failure, got {:?}", err),
+            }
+        }
+        assert!(live.get() <= 4096);
+        test_list.list().assert_invariants();
+        drop(test_list);
+        assert_eq!(0, live.get());
+    }
+}
@@ -0,0 +1,306 @@
+// (c) 2017 James Crooks
+//
+// A TList whose elements can be hidden, counting the visible elements of each sub-tree.
+
+//! VisibleTList, a TList whose elements can be hidden from a second, visible-only indexing.
+
+use {Dir, Handle, TList};
+
+/// A TList in which each element can be hidden, as for folded lines of code or soft-deleted
+/// rows, keeping its place in the list but being skipped by visible indexes.
+///
+/// Alongside its sub-tree size, each node counts the visible elements of its sub-tree, so
+/// that elements can be found both by their index among all elements, as with TList, and by
+/// their index among the visible ones, in O(log N) time. Showing or hiding an element also
+/// takes O(log N).
+#[derive(Debug, Clone)]
+pub struct VisibleTList<T> {
+    tree: TList<Entry<T>>,
+}
+
+#[derive(Debug, Clone)]
+struct Entry<T> {
+    value: T,
+    visible: bool,
+    // The visible elements of this entry's whole sub-tree, kept up to date by the TList
+    sub_visible: usize,
+}
+
+impl<T> VisibleTList<T> {
+    /// Generate a new, empty VisibleTList.
+    pub fn new() -> VisibleTList<T> {
+        let mut tree = TList::new();
+        tree.augment = Some(summarize::<T>);
+        VisibleTList { tree }
+    }
+
+    /// Gets the number of elements, visible or hidden.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Returns true if the list contains no elements, visible or hidden.
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Gets the number of visible elements. Runs in O(1) time.
+    pub fn visible_len(&self) -> usize {
+        self.sub_visible(Some(self.tree.root_idx))
+    }
+
+    /// Returns a reference to the element at index among all elements, if it exists.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.tree.get(index).map(|entry| &entry.value)
+    }
+
+    /// Returns a mutable reference to the element at index among all elements, if it exists.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.tree.get_mut(index).map(|entry| &mut entry.value)
+    }
+
+    /// Returns a reference to the element at index among the visible elements, if it exists.
+    /// Runs in O(log N) time.
+    pub fn get_visible(&self, visible_index: usize) -> Option<&T> {
+        self.raw_index(visible_index).and_then(|index| self.get(index))
+    }
+
+    /// Returns whether the element at index among all elements is visible, if it exists.
+    pub fn is_visible(&self, index: usize) -> Option<bool> {
+        self.tree.get(index).map(|entry| entry.visible)
+    }
+
+    /// Inserts a new visible or hidden value at index among all elements. As with TList, an
+    /// index past the end appends.
+    pub fn insert(&mut self, value: T, index: usize, visible: bool) {
+        self.tree.insert(Entry { value, visible, sub_visible: usize::from(visible) }, index);
+    }
+
+    /// Appends a new visible or hidden value to the end of the list.
+    pub fn push(&mut self, value: T, visible: bool) {
+        let index = self.len();
+        self.insert(value, index, visible);
+    }
+
+    /// Deletes the element at index among all elements, returning it if it exists.
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        self.tree.remove(index).map(|entry| entry.value)
+    }
+
+    /// Shows or hides the element at index among all elements, returning whether it was
+    /// visible before, or None if it doesn't exist. Runs in O(log N) time.
+    pub fn set_visible(&mut self, index: usize, visible: bool) -> Option<bool> {
+        let slot = self.tree.search(index)?;
+        let entry = self.tree.get_mut_by_handle(Handle(slot))?;
+        let was_visible = entry.visible;
+        entry.visible = visible;
+        self.tree.augment_path(Some(slot));
+        Some(was_visible)
+    }
+
+    /// Returns a Handle to the element at index among all elements, as TList::handle().
+    pub fn handle(&self, index: usize) -> Option<Handle> {
+        self.tree.handle(index)
+    }
+
+    /// Returns the current index among all elements of the element a Handle refers to, as
+    /// TList::index_of().
+    pub fn index_of(&self, handle: Handle) -> Option<usize> {
+        self.tree.index_of(handle)
+    }
+
+    /// Returns the index among all elements of the element at visible_index among the
+    /// visible ones, if it exists. Runs in O(log N) time.
+    pub fn raw_index(&self, mut visible_index: usize) -> Option<usize> {
+        if visible_index >= self.visible_len() {
+            return None;
+        }
+
+        let mut search_idx = self.tree.root_idx;
+        let mut index = 0;
+        loop {
+            let left = self.tree.get_child_idx(search_idx, Dir::Left);
+            let left_visible = self.sub_visible(left);
+            if visible_index < left_visible {
+                search_idx = left?;
+                continue;
+            }
+
+            let own = usize::from(self.entry(search_idx)?.visible);
+            let left_size = self.tree.get_child_size(search_idx, Dir::Left).ok()?;
+            if visible_index < left_visible + own {
+                return Some(index + left_size);
+            }
+            visible_index -= left_visible + own;
+            index += left_size + 1;
+            search_idx = self.tree.get_child_idx(search_idx, Dir::Right)?;
+        }
+    }
+
+    /// Returns the index among the visible elements of the element at index among all
+    /// elements, or None if it's hidden or doesn't exist. Runs in O(log N) time.
+    pub fn visible_index_of(&self, index: usize) -> Option<usize> {
+        match self.is_visible(index) {
+            Some(true) => Some(self.visible_before(index)),
+            _ => None,
+        }
+    }
+
+    /// Counts the visible elements before index among all elements, which is where the
+    /// element at index would appear among the visible ones if it were shown. Runs in
+    /// O(log N) time.
+    pub fn visible_before(&self, mut index: usize) -> usize {
+        let mut count = 0;
+        let mut walk_idx = if index < self.len() { Some(self.tree.root_idx) } else { None };
+        while let Some(idx) = walk_idx {
+            let left = self.tree.get_child_idx(idx, Dir::Left);
+            let left_size = self.tree.get_child_size(idx, Dir::Left).unwrap_or(0);
+            if index < left_size {
+                walk_idx = left;
+                continue;
+            }
+
+            count += self.sub_visible(left);
+            if index == left_size {
+                return count;
+            }
+            count += self.entry(idx).map_or(0, |entry| usize::from(entry.visible));
+            index -= left_size + 1;
+            walk_idx = self.tree.get_child_idx(idx, Dir::Right);
+        }
+        self.visible_len()
+    }
+
+    /// Returns an iterator over the visible elements in index order. Runs in O(N) time over
+    /// the whole list, hidden elements included.
+    pub fn iter_visible(&self) -> impl Iterator<Item = &T> {
+        self.tree.in_order()
+            .into_iter()
+            .filter_map(move |slot| self.entry(slot))
+            .filter(|entry| entry.visible)
+            .map(|entry| &entry.value)
+    }
+
+    /// Checks the Red-Black invariants of the underlying tree, and that every node's visible
+    /// count sums up its sub-tree, panicking with a description of the first violation found.
+    /// Only compiled for tests and fuzzing builds.
+    #[cfg(any(test, fuzzing))]
+    pub fn assert_invariants(&self) {
+        self.tree.assert_invariants();
+        for slot in self.tree.in_order() {
+            let mut expected = self.entry(slot).map_or(0, |entry| usize::from(entry.visible));
+            for dir in [Dir::Left, Dir::Right] {
+                expected += self.sub_visible(self.tree.get_child_idx(slot, dir));
+            }
+            assert_eq!(expected, self.sub_visible(Some(slot)), "stale visible count");
+        }
+    }
+
+    fn entry(&self, slot: usize) -> Option<&Entry<T>> {
+        self.tree.get_by_handle(Handle(slot))
+    }
+
+    fn sub_visible(&self, slot: Option<usize>) -> usize {
+        match slot {
+            Some(slot) if !self.tree.is_empty() => self.entry(slot).map_or(0, |entry| entry.sub_visible),
+            _ => 0,
+        }
+    }
+}
+
+impl<T> Default for VisibleTList<T> {
+    fn default() -> VisibleTList<T> {
+        VisibleTList::new()
+    }
+}
+
+fn summarize<T>(tree: &mut TList<Entry<T>>, slot: usize) {
+    // The TList's augment hook, which recounts an entry's sub-tree from its children
+    let mut sub_visible = 0;
+    for dir in [Dir::Left, Dir::Right] {
+        if let Some(child) = tree.get_child_idx(slot, dir).and_then(|idx| tree.node_list[idx].as_ref()) {
+            sub_visible += child.data.sub_visible;
+        }
+    }
+    if let Some(node) = tree.node_list[slot].as_mut() {
+        node.data.sub_visible = usize::from(node.data.visible) + sub_visible;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::Rng;
+    use super::VisibleTList;
+
+    #[test]
+    fn test_visible_folding() {
+        // Folding lines 1 to 3 of a 6 line file
+        let mut lines = VisibleTList::new();
+        for line in 0..6 {
+            lines.push(line, true);
+        }
+        for line in 1..4 {
+            assert_eq!(Some(true), lines.set_visible(line, false));
+        }
+
+        assert_eq!(6, lines.len());
+        assert_eq!(3, lines.visible_len());
+        assert_eq!(vec![&0, &4, &5], lines.iter_visible().collect::<Vec<_>>());
+        assert_eq!(Some(&4), lines.get_visible(1));
+        assert_eq!(Some(&2), lines.get(2));
+        assert_eq!(None, lines.get_visible(3));
+        assert_eq!(Some(4), lines.raw_index(1));
+        assert_eq!(Some(2), lines.visible_index_of(5));
+        assert_eq!(None, lines.visible_index_of(2));
+        assert_eq!(1, lines.visible_before(2));
+        assert_eq!(None, lines.set_visible(6, true));
+
+        assert_eq!(Some(false), lines.set_visible(2, true));
+        assert_eq!(Some(&2), lines.get_visible(1));
+        assert_eq!(Some(3), lines.remove(3));
+        assert_eq!(Some(2), lines.visible_index_of(3));
+        lines.assert_invariants();
+    }
+
+    #[test]
+    fn test_visible_random() {
+        let mut rng = rand::thread_rng();
+        let mut list = VisibleTList::new();
+        let mut model: Vec<(u32, bool)> = Vec::new();
+
+        for n in 0..1000 {
+            let index = rng.gen_range(0, model.len() + 1);
+            match rng.gen_range(0, 3) {
+                0 => {
+                    let visible = rng.gen_weighted_bool(2);
+                    list.insert(n, index, visible);
+                    model.insert(index, (n, visible));
+                },
+                1 if index < model.len() => {
+                    assert_eq!(Some(model.remove(index).0), list.remove(index));
+                },
+                _ if index < model.len() => {
+                    let visible = rng.gen_weighted_bool(2);
+                    assert_eq!(Some(model[index].1), list.set_visible(index, visible));
+                    model[index].1 = visible;
+                },
+                _ => {},
+            }
+        }
+        list.assert_invariants();
+
+        let visible: Vec<usize> = (0..model.len()).filter(|&i| model[i].1).collect();
+        assert_eq!(visible.len(), list.visible_len());
+        for (visible_index, &index) in visible.iter().enumerate() {
+            assert_eq!(Some(index), list.raw_index(visible_index));
+            assert_eq!(Some(&model[index].0), list.get_visible(visible_index));
+        }
+        for (index, &(_, shown)) in model.iter().enumerate() {
+            let before = visible.iter().filter(|&&i| i < index).count();
+            assert_eq!(before, list.visible_before(index));
+            assert_eq!(if shown { Some(before) } else { None }, list.visible_index_of(index));
+        }
+    }
+}